@@ -0,0 +1,136 @@
+use near_sdk::{env, json_types::U64, near, require, NearToken, Promise};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::math::*;
+use crate::*;
+
+impl RewardCampaign {
+    /// Returns the amount of the campaign vested by the given epoch. Campaigns vest linearly at the end of each epoch.
+    pub fn vested_amount(&self, epoch: u64) -> u128 {
+        let elapsed_epochs = epoch.saturating_sub(self.start_epoch).min(self.num_epochs);
+        mul_div_with_rounding(
+            U256::from(self.total_amount),
+            U256::from(elapsed_epochs),
+            U256::from(self.num_epochs),
+            false,
+        )
+        .as_u128()
+    }
+
+    /// Returns the amount vested by the given epoch that has not been released yet.
+    pub fn releasable_amount(&self, epoch: u64) -> u128 {
+        self.vested_amount(epoch) - self.released_amount
+    }
+
+    /// Returns the epoch at which the campaign is fully vested.
+    pub fn end_epoch(&self) -> u64 {
+        self.start_epoch + self.num_epochs
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns all active reward campaigns.
+    pub fn get_reward_campaigns(&self) -> Vec<RewardCampaignInfo> {
+        let mut campaign_ids: Vec<&u64> = self.reward_campaigns.keys().collect();
+        campaign_ids.sort();
+        campaign_ids
+            .into_iter()
+            .map(|campaign_id| self.get_reward_campaign(U64(*campaign_id)))
+            .collect()
+    }
+
+    /// Returns an active reward campaign.
+    pub fn get_reward_campaign(&self, campaign_id: U64) -> RewardCampaignInfo {
+        let campaign = self
+            .reward_campaigns
+            .get(&campaign_id.0)
+            .expect(ERR_CAMPAIGN_DOES_NOT_EXIST);
+
+        RewardCampaignInfo {
+            campaign_id,
+            funder: campaign.funder.clone(),
+            total_amount: campaign.total_amount.into(),
+            released_amount: campaign.released_amount.into(),
+            releasable_amount: campaign.releasable_amount(env::epoch_height()).into(),
+            remaining_amount: (campaign.total_amount - campaign.released_amount).into(),
+            start_epoch: campaign.start_epoch.into(),
+            end_epoch: campaign.end_epoch().into(),
+        }
+    }
+
+    #[payable]
+    /// Creates a reward campaign that releases the attached NEAR linearly into the total staked over the given number of epochs.
+    pub fn create_reward_campaign(&mut self, num_epochs: u64) -> U64 {
+        self.check_owner();
+        self.check_not_locked();
+
+        let amount = env::attached_deposit().as_yoctonear();
+        require!(amount >= ONE_NEAR, ERR_CAMPAIGN_UNDER_ONE_NEAR);
+        require!(num_epochs > 0, ERR_CAMPAIGN_NO_EPOCHS);
+
+        let funder = env::predecessor_account_id();
+        let start_epoch = env::epoch_height();
+
+        self.reward_campaign_nonce += 1;
+        self.reward_campaigns.insert(
+            self.reward_campaign_nonce,
+            RewardCampaign {
+                funder: funder.clone(),
+                total_amount: amount,
+                released_amount: 0,
+                start_epoch,
+                num_epochs,
+            },
+        );
+
+        Event::RewardCampaignCreated {
+            campaign_id: &self.reward_campaign_nonce.into(),
+            funder: &funder,
+            amount: &amount.into(),
+            start_epoch: &start_epoch.into(),
+            num_epochs: &num_epochs.into(),
+        }
+        .emit();
+
+        self.reward_campaign_nonce.into()
+    }
+
+    /// Cancels a reward campaign and refunds the unvested NEAR to the campaign funder.
+    /// NEAR that has vested but has not been released yet is still released on the next total staked update.
+    pub fn cancel_reward_campaign(&mut self, campaign_id: U64) {
+        self.check_owner();
+        self.check_not_locked();
+
+        let current_epoch = env::epoch_height();
+        let campaign = self
+            .reward_campaigns
+            .get_mut(&campaign_id.0)
+            .expect(ERR_CAMPAIGN_DOES_NOT_EXIST);
+
+        let vested_amount = campaign.vested_amount(current_epoch);
+        let refunded_amount = campaign.total_amount - vested_amount;
+        let released_amount = campaign.released_amount;
+        let funder = campaign.funder.clone();
+
+        if vested_amount == released_amount {
+            self.reward_campaigns.remove(&campaign_id.0);
+        } else {
+            // end the campaign at the current epoch with the amount vested so far
+            campaign.total_amount = vested_amount;
+            campaign.num_epochs = current_epoch - campaign.start_epoch;
+        }
+
+        if refunded_amount > 0 {
+            Promise::new(funder).transfer(NearToken::from_yoctonear(refunded_amount));
+        }
+
+        Event::RewardCampaignCancelled {
+            campaign_id: &campaign_id,
+            refunded_amount: &refunded_amount.into(),
+            released_amount: &released_amount.into(),
+        }
+        .emit();
+    }
+}
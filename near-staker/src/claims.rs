@@ -0,0 +1,134 @@
+use near_sdk::{env, json_types::U128, log, near, require, AccountId, NearToken};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::whitelist::WhitelistTrait;
+use crate::*;
+
+impl NearStaker {
+    /// Returns the TruNEAR the distributor approved the recipient to claim as allocation rewards.
+    pub(crate) fn internal_distribution_allowance(
+        &self,
+        distributor: &AccountId,
+        recipient: &AccountId,
+    ) -> u128 {
+        self.distribution_allowances
+            .get(&(distributor.clone(), recipient.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Sets the TruNEAR the distributor approved the recipient to claim, removing the allowance when it is zero.
+    pub(crate) fn internal_set_distribution_allowance(
+        &mut self,
+        distributor: &AccountId,
+        recipient: &AccountId,
+        amount: u128,
+    ) {
+        let key = (distributor.clone(), recipient.clone());
+        if amount > 0 {
+            self.distribution_allowances.insert(key, amount);
+        } else {
+            self.distribution_allowances.remove(&key);
+        }
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns the TruNEAR the distributor approved the recipient to claim as allocation rewards.
+    pub fn get_distribution_allowance(&self, distributor: AccountId, recipient: AccountId) -> U128 {
+        self.internal_distribution_allowance(&distributor, &recipient)
+            .into()
+    }
+
+    /// Approves the recipient of an allocation to claim up to the given amount of TruNEAR as allocation rewards,
+    /// including distribution fees. Replaces any previous allowance.
+    pub fn approve_distribution(&mut self, recipient: AccountId, amount: U128) {
+        self.check_not_paused();
+        self.check_whitelisted();
+
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.allocations.contains_key(&distributor),
+            ERR_NO_ALLOCATIONS
+        );
+        require!(
+            self.internal_allocation(&distributor, &recipient).is_some(),
+            ERR_NO_ALLOCATIONS_TO_RECIPIENT
+        );
+
+        let old_allowance = self.internal_distribution_allowance(&distributor, &recipient);
+        self.internal_set_distribution_allowance(&distributor, &recipient, amount.0);
+
+        Event::DistributionAllowanceSet {
+            user: &distributor,
+            recipient: &recipient,
+            old_allowance: &old_allowance.into(),
+            new_allowance: &amount,
+        }
+        .emit();
+    }
+
+    /// Claims the TruNEAR rewards of the caller's allocation from the distributor, up to the allowance
+    /// approved by the distributor. Rewards above the allowance remain to be distributed.
+    pub fn claim_allocation_rewards(&mut self, distributor: AccountId) {
+        self.check_not_paused();
+        self.check_contract_in_sync();
+        self.check_not_locked();
+
+        let recipient = env::predecessor_account_id();
+        require!(
+            self.is_whitelisted(distributor.clone()),
+            ERR_USER_NOT_WHITELISTED
+        );
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.internal_allocation(&distributor, &recipient).is_some(),
+            ERR_NO_ALLOCATIONS_TO_RECIPIENT
+        );
+
+        let allowance = self.internal_distribution_allowance(&distributor, &recipient);
+        require!(allowance > 0, ERR_NO_DISTRIBUTION_ALLOWANCE);
+
+        let global_price = self.current_share_price();
+        let distribution_info = match self.internal_distribute(
+            distributor.clone(),
+            recipient.clone(),
+            global_price,
+            false,
+            NearToken::from_near(0),
+            Some(allowance),
+        ) {
+            Err(error) => env::panic_str(error.to_string().as_str()),
+            Ok(None) => {
+                log!("No rewards to claim");
+                return;
+            }
+            Ok(Some(distribution_info)) => distribution_info,
+        };
+
+        // the allowance covers both the distributed rewards and the distribution fees
+        let claimed_shares = distribution_info.shares_amount + distribution_info.fees;
+        let remaining_allowance = allowance - claimed_shares;
+        self.internal_set_distribution_allowance(&distributor, &recipient, remaining_allowance);
+
+        self.internal_emit_distributed_rewards(
+            &distributor,
+            &recipient,
+            &distribution_info,
+            false,
+            false,
+            self.get_total_allocated(distributor.clone()),
+        );
+
+        Event::AllocationRewardsClaimed {
+            user: &distributor,
+            recipient: &recipient,
+            shares: &claimed_shares.into(),
+            remaining_allowance: &remaining_allowance.into(),
+        }
+        .emit();
+    }
+}
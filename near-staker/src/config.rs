@@ -0,0 +1,201 @@
+use near_sdk::{env, json_types::U64, near, require};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::*;
+
+impl NearStaker {
+    /// Returns the current value of a config.
+    pub(crate) fn internal_config_value(&self, key: ConfigKey) -> u64 {
+        match key {
+            ConfigKey::FEE => self.fee as u64,
+            ConfigKey::DISTRIBUTION_FEE => self.distribution_fee as u64,
+            ConfigKey::MANAGEMENT_FEE => self.management_fee as u64,
+            ConfigKey::CONFIG_CHANGE_DELAY => self.config_change_delay,
+            ConfigKey::MAX_FEE_INCREASE => self.max_fee_increase as u64,
+        }
+    }
+
+    /// Returns whether changing a config from the old to the new value loosens the protection of TruNEAR holders.
+    fn is_loosening(key: ConfigKey, old_value: u64, new_value: u64) -> bool {
+        match key {
+            ConfigKey::CONFIG_CHANGE_DELAY => new_value < old_value,
+            _ => new_value > old_value,
+        }
+    }
+
+    /// Sets a config to a new value and emits the associated event.
+    fn internal_apply_config_change(&mut self, key: ConfigKey, new_value: u64) {
+        match key {
+            ConfigKey::FEE => {
+                // the fees accrued up to now are collected at the previous fee
+                if self.token.total_supply > 0 {
+                    self.check_contract_in_sync();
+                    self.internal_collect_fees();
+                }
+                Event::SetFeeEvent {
+                    old_fee: &self.fee,
+                    new_fee: &(new_value as u16),
+                }
+                .emit();
+                self.fee = new_value as u16;
+            }
+            ConfigKey::DISTRIBUTION_FEE => {
+                Event::SetDistributionFeeEvent {
+                    old_distribution_fee: &self.distribution_fee,
+                    new_distribution_fee: &(new_value as u16),
+                }
+                .emit();
+                self.distribution_fee = new_value as u16;
+            }
+            ConfigKey::MANAGEMENT_FEE => {
+                // the management fee up to now is accrued at the previous fee
+                self.internal_accrue_management_fee();
+                Event::SetManagementFee {
+                    old_management_fee: &self.management_fee,
+                    new_management_fee: &(new_value as u16),
+                }
+                .emit();
+                self.management_fee = new_value as u16;
+            }
+            ConfigKey::CONFIG_CHANGE_DELAY => {
+                Event::SetConfigChangeDelay {
+                    old_config_change_delay: &self.config_change_delay.into(),
+                    new_config_change_delay: &new_value.into(),
+                }
+                .emit();
+                self.config_change_delay = new_value;
+            }
+            ConfigKey::MAX_FEE_INCREASE => {
+                Event::SetMaxFeeIncrease {
+                    old_max_fee_increase: &self.max_fee_increase,
+                    new_max_fee_increase: &(new_value as u16),
+                }
+                .emit();
+                self.max_fee_increase = new_value as u16;
+            }
+        }
+    }
+
+    /// Removes the pending change of a config, if any, emitting a cancellation event.
+    fn internal_cancel_config_change(&mut self, key: ConfigKey) -> bool {
+        let Some(index) = self
+            .pending_config_changes
+            .iter()
+            .position(|change| change.key == key)
+        else {
+            return false;
+        };
+        let change = self.pending_config_changes.remove(index);
+
+        Event::ConfigChangeCancelled {
+            key: &key,
+            new_value: &change.new_value,
+        }
+        .emit();
+        true
+    }
+
+    /// Changes a config, replacing any pending change of it. Changes that tighten the config apply immediately.
+    /// Loosening changes are proposed and can be executed after the config change delay, and fee increases are
+    /// limited to the maximum fee increase. All changes apply immediately while no TruNEAR is in circulation.
+    pub(crate) fn internal_change_config(&mut self, key: ConfigKey, new_value: u64) {
+        let old_value = self.internal_config_value(key);
+        self.internal_cancel_config_change(key);
+
+        if !Self::is_loosening(key, old_value, new_value) || self.token.total_supply == 0 {
+            self.internal_apply_config_change(key, new_value);
+            return;
+        }
+
+        if matches!(
+            key,
+            ConfigKey::FEE | ConfigKey::DISTRIBUTION_FEE | ConfigKey::MANAGEMENT_FEE
+        ) {
+            require!(
+                new_value - old_value <= self.max_fee_increase as u64,
+                ERR_FEE_INCREASE_TOO_LARGE
+            );
+        }
+
+        if self.config_change_delay == 0 {
+            self.internal_apply_config_change(key, new_value);
+            return;
+        }
+
+        let current_epoch = env::epoch_height();
+        let change = PendingConfigChange {
+            key,
+            old_value: old_value.into(),
+            new_value: new_value.into(),
+            proposed_at: current_epoch.into(),
+            executable_at: (current_epoch + self.config_change_delay).into(),
+        };
+
+        Event::ConfigChangeProposed {
+            key: &key,
+            old_value: &change.old_value,
+            new_value: &change.new_value,
+            executable_at: &change.executable_at,
+        }
+        .emit();
+        self.pending_config_changes.push(change);
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns the proposed config changes waiting for their timelock to expire.
+    pub fn get_pending_config_changes(&self) -> Vec<PendingConfigChange> {
+        self.pending_config_changes.clone()
+    }
+
+    /// Sets the number of epochs before a proposed config change can be executed.
+    /// Reducing the delay is itself subject to the current delay.
+    pub fn set_config_change_delay(&mut self, config_change_delay: U64) {
+        self.check_owner();
+        self.internal_change_config(ConfigKey::CONFIG_CHANGE_DELAY, config_change_delay.0);
+    }
+
+    /// Sets the maximum increase of the fee or distribution fee in a single change.
+    /// Raising the maximum is itself subject to the config change delay.
+    pub fn set_max_fee_increase(&mut self, max_fee_increase: u16) {
+        self.check_owner();
+        require!(max_fee_increase < FEE_PRECISION, ERR_FEE_TOO_LARGE);
+        self.internal_change_config(ConfigKey::MAX_FEE_INCREASE, max_fee_increase as u64);
+    }
+
+    /// Cancels the pending change of a config.
+    pub fn cancel_config_change(&mut self, key: ConfigKey) {
+        self.check_owner();
+        require!(
+            self.internal_cancel_config_change(key),
+            ERR_NO_PENDING_CONFIG_CHANGE
+        );
+    }
+
+    /// Executes the pending change of a config once its timelock has expired.
+    /// Fees accrued before a fee change are collected at the previous fee.
+    pub fn execute_config_change(&mut self, key: ConfigKey) {
+        self.check_owner();
+        let index = self
+            .pending_config_changes
+            .iter()
+            .position(|change| change.key == key)
+            .expect(ERR_NO_PENDING_CONFIG_CHANGE);
+        require!(
+            self.pending_config_changes[index].executable_at.0 <= env::epoch_height(),
+            ERR_CONFIG_CHANGE_NOT_READY
+        );
+
+        let change = self.pending_config_changes.remove(index);
+        self.internal_apply_config_change(key, change.new_value.0);
+
+        Event::ConfigChangeExecuted {
+            key: &key,
+            old_value: &change.old_value,
+            new_value: &change.new_value,
+        }
+        .emit();
+    }
+}
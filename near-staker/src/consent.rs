@@ -0,0 +1,93 @@
+use near_sdk::{env, near, require, AccountId};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::whitelist::WhitelistTrait;
+use crate::*;
+
+impl NearStaker {
+    /// Returns whether the allocation to the recipient is waiting for the recipient to accept it.
+    pub(crate) fn internal_is_pending_allocation(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> bool {
+        self.pending_allocations
+            .contains(&(allocator.clone(), recipient.clone()))
+    }
+
+    /// Marks a new allocation as pending when recipients must consent to their allocations
+    /// and the recipient isn't whitelisted.
+    pub(crate) fn internal_request_consent(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) {
+        if self.recipient_consent_required && !self.is_whitelisted(recipient.clone()) {
+            self.pending_allocations
+                .insert((allocator.clone(), recipient.clone()));
+        }
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns whether allocations to recipients that aren't whitelisted stay pending until the recipient accepts them.
+    pub fn is_recipient_consent_required(&self) -> bool {
+        self.recipient_consent_required
+    }
+
+    /// Sets whether new allocations to recipients that aren't whitelisted stay pending until the recipient
+    /// accepts them. The requirement is enabled by default.
+    /// Allocations that are already pending stay pending when the requirement is removed.
+    pub fn set_recipient_consent_required(&mut self, required: bool) {
+        self.check_owner();
+        self.recipient_consent_required = required;
+        Event::RecipientConsentRequiredSet {
+            required: &required,
+        }
+        .emit();
+    }
+
+    /// Accepts a pending allocation from the distributor to the caller, so its rewards can be distributed.
+    pub fn accept_allocation(&mut self, distributor: AccountId) {
+        self.check_not_paused();
+
+        let recipient = env::predecessor_account_id();
+        require!(
+            self.pending_allocations
+                .remove(&(distributor.clone(), recipient.clone())),
+            ERR_ALLOCATION_NOT_PENDING
+        );
+
+        Event::AllocationAccepted {
+            user: &distributor,
+            recipient: &recipient,
+        }
+        .emit();
+    }
+
+    /// Rejects a pending allocation from the distributor to the caller, or leaves an accepted one.
+    /// The allocation is removed along with its undistributed rewards, and the storage deposit is
+    /// refunded to the distributor.
+    pub fn reject_allocation(&mut self, distributor: AccountId) {
+        self.check_not_paused();
+
+        let recipient = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        let allocation = self
+            .internal_allocation(&distributor, &recipient)
+            .expect(ERR_NO_ALLOCATIONS_TO_RECIPIENT);
+        let allocation = self.internal_resolve_allocation(&distributor, &recipient, &allocation);
+
+        Event::AllocationRejected {
+            user: &distributor,
+            recipient: &recipient,
+            near_amount: &allocation.near_amount.into(),
+            pending: &self.internal_is_pending_allocation(&distributor, &recipient),
+        }
+        .emit();
+
+        self.internal_delete_allocation(&distributor, &recipient);
+    }
+}
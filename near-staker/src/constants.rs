@@ -8,4 +8,10 @@ pub const NO_ARGS: Vec<u8> = vec![];
 pub const XCC_GAS: Gas = Gas::from_tgas(30); // approx gas needed for cross-contract calls
 pub const VIEW_GAS: Gas = Gas::from_tgas(5); // approx gas needed for view calls
 pub const NUM_EPOCHS_TO_UNLOCK: u64 = 4; // number of epochs until unstaked amount can be withdrawn
-pub const STORAGE_BYTES: u128 = 200; // approx bytes used to add unstake requests and allocations
+pub const NANOSECONDS_PER_YEAR: u64 = 31_536_000_000_000_000; // 365 days, used to accrue the annual management fee
+pub const DEFAULT_CONFIG_CHANGE_DELAY: u64 = NUM_EPOCHS_TO_UNLOCK + 1; // lets holders unstake and withdraw before a fee increase applies
+pub const DEFAULT_MAX_FEE_INCREASE: u16 = 500; // maximum fee increase in basis points in a single change
+pub const MAX_EXPIRY_EPOCHS: u64 = 1460; // about two years ahead, bounding the expiry epochs pending a share price
+pub const DEFAULT_DISTRIBUTION_PAGE_SIZE: u32 = 50; // recipients distributed to per keeper call by default
+pub const STORAGE_BYTES: u128 = 200; // approx bytes used to add unstake requests
+pub const ALLOCATION_STORAGE_BYTES: u128 = 1500; // approx bytes used to add allocations with their allowance, ledger, expiry, consent and index entries
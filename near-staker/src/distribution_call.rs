@@ -0,0 +1,121 @@
+use near_contract_standards::fungible_token::receiver::ext_ft_receiver;
+use near_sdk::{env, json_types::U128, log, near, require, AccountId, NearToken, PromiseOrValue};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::*;
+
+#[near]
+impl NearStaker {
+    /// Distributes TruNEAR staking rewards to a recipient with the semantics of `ft_transfer_call`.
+    /// The recipient is notified of the rewards through `ft_on_transfer` with the given `msg`, and any
+    /// TruNEAR it returns as unused is refunded to the distributor. Returns the amount of TruNEAR used by the recipient.
+    pub fn distribute_rewards_call(
+        &mut self,
+        recipient: AccountId,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.check_not_paused();
+        self.check_whitelisted();
+        self.check_contract_in_sync();
+        self.check_not_locked();
+
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.internal_allocation(&distributor, &recipient).is_some(),
+            ERR_NO_ALLOCATIONS_TO_RECIPIENT
+        );
+
+        let global_price = self.current_share_price();
+        let distribution_info = match self.internal_distribute(
+            distributor.clone(),
+            recipient.clone(),
+            global_price,
+            false,
+            NearToken::from_yoctonear(0),
+            None,
+        ) {
+            Err(error) => env::panic_str(error.to_string().as_str()),
+            Ok(None) => {
+                log!("No rewards to distribute");
+                return PromiseOrValue::Value(U128(0));
+            }
+            Ok(Some(distribution_info)) => distribution_info,
+        };
+
+        // emit Distribute Rewards event
+        self.internal_emit_distributed_rewards(
+            &distributor,
+            &recipient,
+            &distribution_info,
+            false,
+            false,
+            self.get_total_allocated(distributor.clone()),
+        );
+
+        // notify the recipient of the rewards and resolve any unused TruNEAR
+        ext_ft_receiver::ext(recipient.clone())
+            .with_static_gas(XCC_GAS)
+            .ft_on_transfer(
+                distributor.clone(),
+                U128(distribution_info.shares_amount),
+                msg,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(XCC_GAS)
+                    .with_unused_gas_weight(0)
+                    .resolve_distribution_call(
+                        distributor,
+                        recipient,
+                        U128(distribution_info.shares_amount),
+                        U128(distribution_info.near_amount),
+                    ),
+            )
+            .into()
+    }
+
+    #[private]
+    /// Refunds the TruNEAR the recipient didn't use to the distributor, deducting it from the allocation ledger.
+    /// Returns the amount of TruNEAR used by the recipient.
+    pub fn resolve_distribution_call(
+        &mut self,
+        distributor: AccountId,
+        recipient: AccountId,
+        shares: U128,
+        near_amount: U128,
+    ) -> U128 {
+        let (used_shares, _) =
+            self.token
+                .internal_ft_resolve_transfer(&distributor, recipient.clone(), shares);
+        self.internal_checkpoint_percentage_allocations(&distributor);
+        self.internal_checkpoint_percentage_allocations(&recipient);
+        let refunded_shares = shares.0 - used_shares;
+        if refunded_shares == 0 {
+            return U128(used_shares);
+        }
+
+        // the refunded rewards were not distributed, and the NEAR value is deducted at the distribution share price
+        let refunded_near_amount = (U256::from(near_amount.0) * U256::from(refunded_shares)
+            / U256::from(shares.0))
+        .as_u128();
+        if let Some(ledger) = self
+            .distribution_ledgers
+            .get_mut(&(distributor.clone(), recipient.clone()))
+        {
+            ledger.shares_distributed = ledger.shares_distributed.saturating_sub(refunded_shares);
+            ledger.near_distributed = ledger.near_distributed.saturating_sub(refunded_near_amount);
+        }
+
+        Event::DistributionRefunded {
+            user: &distributor,
+            recipient: &recipient,
+            shares: &U128(refunded_shares),
+            near_amount: &U128(refunded_near_amount),
+        }
+        .emit();
+
+        U128(used_shares)
+    }
+}
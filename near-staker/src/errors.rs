@@ -2,6 +2,7 @@
 
 pub const ERR_NOT_INITIALIZED: &str = "Contract is not initialized";
 pub const ERR_NOT_IN_SYNC: &str = "Contract is not in sync";
+pub const ERR_SHARE_PRICE_OVERFLOW: &str = "Share price arithmetic overflow";
 
 /// Staker errors ///
 
@@ -17,6 +18,13 @@ pub const ERR_FEE_TOO_LARGE: &str = "Fee cannot be larger than fee precision";
 pub const ERR_MIN_DEPOSIT_TOO_SMALL: &str = "Minimum deposit amount is too small";
 pub const ERR_STAKE_BELOW_MIN_DEPOSIT: &str = "Deposit amount is below minimum deposit";
 pub const ERR_NO_PENDING_OWNER: &str = "No pending owner set";
+pub const ERR_FEE_INCREASE_TOO_LARGE: &str = "Fee increase exceeds the maximum fee increase";
+pub const ERR_NO_PENDING_CONFIG_CHANGE: &str = "No pending change for this config";
+pub const ERR_CONFIG_CHANGE_NOT_READY: &str = "Config change cannot be executed yet";
+pub const ERR_NO_FEE_OVERRIDE: &str = "Account has no fee override";
+pub const ERR_INVALID_FEE_SPLIT: &str = "Fee split basis points must sum to fee precision";
+pub const ERR_FEE_SPLIT_ZERO_SHARE: &str = "Fee split beneficiary must have a non-zero share";
+pub const ERR_FEE_SPLIT_DUPLICATE: &str = "Fee split beneficiary is listed more than once";
 pub const ERR_NOT_PENDING_OWNER: &str = "Only the pending owner can claim ownership";
 
 // delegation pool errors
@@ -31,6 +39,40 @@ pub const ERR_INSUFFICIENT_FUNDS_ON_POOL: &str = "Insufficient funds on delegati
 pub const ERR_ALLOCATION_UNDER_ONE_NEAR: &str = "Allocated amount must be at least 1 NEAR";
 pub const ERR_INVALID_RECIPIENT: &str = "Cannot allocate to this recipient";
 pub const ERR_NO_ALLOCATIONS: &str = "User has no allocations";
+pub const ERR_ALLOCATION_ALREADY_EXISTS: &str = "User already has an allocation to this recipient";
+pub const ERR_ALLOCATION_TYPE_MISMATCH: &str =
+    "The allocation to this recipient is of a different type";
+pub const ERR_INVALID_ALLOCATION_PERCENTAGE: &str =
+    "Allocated percentage must be between 1 and 10000 basis points";
+pub const ERR_NO_DISTRIBUTION_ALLOWANCE: &str =
+    "The distributor has not approved any allocation rewards to claim";
+pub const ERR_NO_DISTRIBUTION_KEEPER: &str = "The distributor has not authorized a keeper";
+pub const ERR_NOT_DISTRIBUTION_KEEPER: &str = "Only the distributor's keeper can call this method";
+pub const ERR_INVALID_DISTRIBUTION_INTERVAL: &str =
+    "Distribution interval must be at least one epoch";
+pub const ERR_INVALID_EXPIRY_EPOCH: &str = "The expiry epoch must be after the current epoch";
+pub const ERR_EXPIRY_EPOCH_TOO_FAR: &str = "The expiry epoch is too far in the future";
+pub const ERR_ALLOCATION_EXPIRED: &str = "The allocation has expired";
+pub const ERR_ALLOCATION_NOT_EXPIRED: &str = "The allocation has not expired";
+pub const ERR_EXPIRED_ALLOCATION_HAS_REWARDS: &str =
+    "The expired allocation has undistributed rewards";
+pub const ERR_ALLOCATION_PERCENTAGE_EXCEEDED: &str = "Allocated percentages cannot exceed 100%";
+pub const ERR_ALLOCATION_PENDING: &str = "The allocation has not been accepted by the recipient";
+pub const ERR_ALLOCATION_NOT_PENDING: &str = "There is no pending allocation to accept";
+pub const ERR_ALLOCATION_NOT_COVERED: &str =
+    "The allocated amount is not covered by the withdrawable balance";
+pub const ERR_RECIPIENT_NOT_WHITELISTED: &str =
+    "The recipient must be whitelisted to withdraw unstaked rewards";
+
+// reward campaign errors
+pub const ERR_CAMPAIGN_UNDER_ONE_NEAR: &str = "Campaign amount must be at least 1 NEAR";
+pub const ERR_CAMPAIGN_NO_EPOCHS: &str = "Campaign must last at least one epoch";
+pub const ERR_CAMPAIGN_DOES_NOT_EXIST: &str = "Reward campaign does not exist";
+
+// rounding reserve errors
+pub const ERR_NO_RESERVE_DEPOSIT: &str = "Rounding reserve deposit must be greater than zero";
+pub const ERR_EXCESSIVE_RESERVE_WITHDRAWAL: &str =
+    "Rounding reserve withdrawal exceeds the remaining reserve";
 
 // user errors
 pub const ERR_INVALID_UNSTAKE_AMOUNT: &str = "Invalid unstake amount";
@@ -40,6 +82,7 @@ pub const ERR_INSUFFICIENT_NEAR_BALANCE: &str = "Attached deposit too small";
 pub const ERR_EXCESSIVE_DEALLOCATION: &str = "Cannot deallocate more than is allocated";
 pub const ERR_INVALID_NONCE: &str = "Invalid nonce";
 pub const ERR_INSUFFICIENT_TRUNEAR_BALANCE: &str = "Insufficient TruNEAR balance";
+pub const ERR_INSUFFICIENT_UNLOCKED_TRUNEAR_BALANCE: &str = "Insufficient unlocked TruNEAR balance";
 pub const ERR_UNSTAKE_AMOUNT_TOO_LOW: &str = "Unstake amount is too low";
 pub const ERR_SENDER_MUST_BE_RECEIVER: &str = "Sender must have requested the unlock";
 pub const ERR_WITHDRAW_NOT_READY: &str = "Withdraw not ready";
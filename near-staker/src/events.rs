@@ -1,3 +1,6 @@
+use crate::ConfigKey;
+use crate::FeeBeneficiary;
+use crate::PoolTotalStakedUpdate;
 use crate::UserStatus;
 use crate::ValidatorState;
 use near_sdk::{
@@ -41,6 +44,59 @@ pub enum Event<'a> {
         old_distribution_fee: &'a u16,
         new_distribution_fee: &'a u16,
     },
+    #[serde(rename = "set_config_change_delay_event")]
+    SetConfigChangeDelay {
+        old_config_change_delay: &'a U64,
+        new_config_change_delay: &'a U64,
+    },
+    #[serde(rename = "set_max_fee_increase_event")]
+    SetMaxFeeIncrease {
+        old_max_fee_increase: &'a u16,
+        new_max_fee_increase: &'a u16,
+    },
+    #[serde(rename = "config_change_proposed_event")]
+    ConfigChangeProposed {
+        key: &'a ConfigKey,
+        old_value: &'a U64,
+        new_value: &'a U64,
+        executable_at: &'a U64,
+    },
+    #[serde(rename = "config_change_cancelled_event")]
+    ConfigChangeCancelled {
+        key: &'a ConfigKey,
+        new_value: &'a U64,
+    },
+    #[serde(rename = "config_change_executed_event")]
+    ConfigChangeExecuted {
+        key: &'a ConfigKey,
+        old_value: &'a U64,
+        new_value: &'a U64,
+    },
+    #[serde(rename = "set_auto_collect_fees_event")]
+    SetAutoCollectFees {
+        old_auto_collect_fees: &'a bool,
+        new_auto_collect_fees: &'a bool,
+    },
+    #[serde(rename = "set_fee_split_event")]
+    SetFeeSplit {
+        old_fee_split: &'a Vec<FeeBeneficiary>,
+        new_fee_split: &'a Vec<FeeBeneficiary>,
+    },
+    #[serde(rename = "fee_override_set_event")]
+    FeeOverrideSet {
+        account_id: &'a AccountId,
+        fee: &'a u16,
+        distribution_fee: &'a u16,
+    },
+    #[serde(rename = "fee_override_removed_event")]
+    FeeOverrideRemoved {
+        account_id: &'a AccountId,
+    },
+    #[serde(rename = "set_management_fee_event")]
+    SetManagementFee {
+        old_management_fee: &'a u16,
+        new_management_fee: &'a u16,
+    },
     SetMinDepositEvent {
         old_min_deposit: &'a U128,
         new_min_deposit: &'a U128,
@@ -76,6 +132,18 @@ pub enum Event<'a> {
         epoch: &'a U64,
         pool_id: &'a AccountId,
     },
+    #[serde(rename = "donation_event")]
+    Donation {
+        donor: &'a AccountId,
+        amount: &'a U128,
+        amount_staked: &'a U128,
+        total_staked: &'a U128,
+        total_supply: &'a U128,
+        share_price_num: &'a String,
+        share_price_denom: &'a String,
+        epoch: &'a U64,
+        pool_id: &'a AccountId,
+    },
     UnstakedEvent {
         user_id: &'a AccountId,
         amount: &'a U128,
@@ -111,22 +179,111 @@ pub enum Event<'a> {
         total_allocated_share_price_num: &'a String,
         total_allocated_share_price_denom: &'a String,
     },
-    DistributedRewardsEvent {
-        user: AccountId,
-        recipient: AccountId,
-        shares: U128,
-        near_amount: U128,
-        user_balance: U128,
-        recipient_balance: U128,
-        fees: U128,
-        treasury_balance: U128,
-        share_price_num: String,
-        share_price_denom: String,
-        in_near: bool,
-        total_allocated_amount: U128,
-        total_allocated_share_price_num: String,
-        total_allocated_share_price_denom: String,
+    #[serde(rename = "allocated_percentage_event")]
+    AllocatedPercentage {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        basis_points: &'a u16,
+        total_basis_points: &'a u16,
+        share_price_num: &'a String,
+        share_price_denom: &'a String,
+        total_allocated_basis_points: &'a u16,
+    },
+    #[serde(rename = "deallocated_percentage_event")]
+    DeallocatedPercentage {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        basis_points: &'a u16,
+        total_basis_points: &'a u16,
+        share_price_num: &'a String,
+        share_price_denom: &'a String,
+        total_allocated_basis_points: &'a u16,
+    },
+    #[serde(rename = "shares_locked_event")]
+    SharesLocked {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        shares: &'a U128,
+        locked_balance: &'a U128,
+    },
+    #[serde(rename = "shares_unlocked_event")]
+    SharesUnlocked {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        shares: &'a U128,
+        locked_balance: &'a U128,
+    },
+    #[serde(rename = "distribution_allowance_set_event")]
+    DistributionAllowanceSet {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        old_allowance: &'a U128,
+        new_allowance: &'a U128,
+    },
+    #[serde(rename = "allocation_rewards_claimed_event")]
+    AllocationRewardsClaimed {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        shares: &'a U128,
+        remaining_allowance: &'a U128,
+    },
+    #[serde(rename = "allocation_reassigned_event")]
+    AllocationReassigned {
+        user: &'a AccountId,
+        old_recipient: &'a AccountId,
+        new_recipient: &'a AccountId,
+        near_amount: &'a U128,
+        share_price_num: &'a String,
+        share_price_denom: &'a String,
+        settled: &'a bool,
+    },
+    #[serde(rename = "allocations_migrated_event")]
+    AllocationsMigrated {
+        user: &'a AccountId,
+        allocations: &'a u32,
+    },
+    #[serde(rename = "recipient_consent_required_set_event")]
+    RecipientConsentRequiredSet {
+        required: &'a bool,
+    },
+    #[serde(rename = "allocation_accepted_event")]
+    AllocationAccepted {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+    },
+    #[serde(rename = "allocation_rejected_event")]
+    AllocationRejected {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        near_amount: &'a U128,
+        pending: &'a bool,
+    },
+    #[serde(rename = "strict_allocation_mode_set_event")]
+    StrictAllocationModeSet {
+        user: &'a AccountId,
+        enabled: &'a bool,
+    },
+    #[serde(rename = "allocation_expiry_set_event")]
+    AllocationExpirySet {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        expires_at_epoch: &'a Option<U64>,
     },
+    #[serde(rename = "expired_allocation_removed_event")]
+    ExpiredAllocationRemoved {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        caller: &'a AccountId,
+        near_amount: &'a U128,
+    },
+    #[serde(rename = "distribution_refunded_event")]
+    DistributionRefunded {
+        user: &'a AccountId,
+        recipient: &'a AccountId,
+        shares: &'a U128,
+        near_amount: &'a U128,
+    },
+    DistributedRewardsEvent(Box<DistributedRewards>),
     WithdrawalEvent {
         user: &'a AccountId,
         amount: &'a U128,
@@ -135,15 +292,120 @@ pub enum Event<'a> {
         delegation_pool: &'a AccountId,
     },
     FeesCollectedEvent {
+        beneficiary: &'a AccountId,
         shares_minted: &'a U128,
+        beneficiary_balance: &'a U128,
         treasury_balance: &'a U128,
         share_price_num: &'a String,
         share_price_denom: &'a String,
         epoch: &'a U64,
     },
+    #[serde(rename = "management_fee_accrued_event")]
+    ManagementFeeAccrued {
+        amount: &'a U128,
+        accrued_management_fees: &'a U128,
+        epoch: &'a U64,
+    },
+    #[serde(rename = "fee_rebated_event")]
+    FeeRebated {
+        account_id: &'a AccountId,
+        shares_rebated: &'a U128,
+        epoch: &'a U64,
+    },
+    #[serde(rename = "total_staked_updated_event")]
+    TotalStakedUpdated {
+        pools: &'a Vec<PoolTotalStakedUpdate>,
+        old_total_staked: &'a U128,
+        total_staked: &'a U128,
+        total_supply: &'a U128,
+        share_price_num: &'a String,
+        share_price_denom: &'a String,
+        epoch: &'a U64,
+    },
+    #[serde(rename = "total_staked_update_failed_event")]
+    TotalStakedUpdateFailed {
+        failed_pools: &'a Vec<AccountId>,
+        epoch: &'a U64,
+    },
+    #[serde(rename = "reward_campaign_created_event")]
+    RewardCampaignCreated {
+        campaign_id: &'a U64,
+        funder: &'a AccountId,
+        amount: &'a U128,
+        start_epoch: &'a U64,
+        num_epochs: &'a U64,
+    },
+    #[serde(rename = "reward_campaign_cancelled_event")]
+    RewardCampaignCancelled {
+        campaign_id: &'a U64,
+        refunded_amount: &'a U128,
+        released_amount: &'a U128,
+    },
+    #[serde(rename = "reward_campaigns_released_event")]
+    RewardCampaignsReleased {
+        amount: &'a U128,
+        pool_id: &'a AccountId,
+        epoch: &'a U64,
+    },
     DistributedAllEvent {
         user: &'a AccountId,
     },
+    #[serde(rename = "distributed_batch_event")]
+    DistributedBatch {
+        user: &'a AccountId,
+        from_recipient: Option<&'a AccountId>,
+        next_recipient: Option<&'a AccountId>,
+        distributions: &'a u32,
+    },
+    #[serde(rename = "distribution_keeper_set_event")]
+    DistributionKeeperSet {
+        user: &'a AccountId,
+        keeper: &'a AccountId,
+        interval: &'a U64,
+    },
+    #[serde(rename = "distribution_keeper_removed_event")]
+    DistributionKeeperRemoved {
+        user: &'a AccountId,
+        keeper: &'a AccountId,
+    },
+    #[serde(rename = "distributed_all_for_event")]
+    DistributedAllFor {
+        user: &'a AccountId,
+        keeper: &'a AccountId,
+        from_recipient: Option<&'a AccountId>,
+        next_recipient: Option<&'a AccountId>,
+        distributions: &'a u32,
+    },
+    #[serde(rename = "rounding_reserve_funded_event")]
+    RoundingReserveFunded {
+        funder: &'a AccountId,
+        amount: &'a U128,
+        reserve_balance: &'a U128,
+    },
+    #[serde(rename = "rounding_reserve_withdrawn_event")]
+    RoundingReserveWithdrawn {
+        recipient: &'a AccountId,
+        amount: &'a U128,
+        reserve_balance: &'a U128,
+    },
+    #[serde(rename = "set_rounding_reserve_threshold_event")]
+    SetRoundingReserveThreshold {
+        old_threshold: &'a U128,
+        new_threshold: &'a U128,
+    },
+    #[serde(rename = "rounding_subsidy_recorded_event")]
+    RoundingSubsidyRecorded {
+        pool_id: &'a AccountId,
+        stake_subsidy: &'a U128,
+        unstake_subsidy: &'a U128,
+        withdraw_subsidy: &'a U128,
+        total_subsidy: &'a U128,
+    },
+    #[serde(rename = "rounding_reserve_low_event")]
+    RoundingReserveLow {
+        remaining_reserve: &'a U128,
+        threshold: &'a U128,
+    },
     // Whitelist events
     AgentAddedEvent {
         account_id: &'a AccountId,
@@ -158,6 +420,30 @@ pub enum Event<'a> {
     },
 }
 
+/// The data of a `DistributedRewardsEvent`, boxed to keep the size of the `Event` enum down.
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributedRewards {
+    pub user: AccountId,
+    pub recipient: AccountId,
+    pub shares: U128,
+    pub near_amount: U128,
+    pub user_balance: U128,
+    pub recipient_balance: U128,
+    pub fees: U128,
+    pub treasury_balance: U128,
+    pub share_price_num: String,
+    pub share_price_denom: String,
+    pub in_near: bool,
+    pub via_unstake: bool,
+    pub total_allocated_amount: U128,
+    pub total_allocated_share_price_num: String,
+    pub total_allocated_share_price_denom: String,
+    pub total_shares_distributed: U128,
+    pub total_near_distributed: U128,
+    pub total_fees_paid: U128,
+}
+
 impl Event<'_> {
     pub fn emit(&self) {
         emit_event(&self);
@@ -0,0 +1,196 @@
+use near_sdk::json_types::U64;
+use near_sdk::{env, near, require, AccountId};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::math::SharePrice;
+use crate::*;
+
+impl NearStaker {
+    /// Returns the epoch from which the allocation to the recipient stops accruing rewards.
+    pub(crate) fn internal_allocation_expiry(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> Option<u64> {
+        self.allocation_expiries
+            .get(&(allocator.clone(), recipient.clone()))
+            .copied()
+    }
+
+    /// Returns whether the allocation to the recipient has reached its expiry epoch.
+    pub(crate) fn internal_is_expired_allocation(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> bool {
+        self.internal_allocation_expiry(allocator, recipient)
+            .is_some_and(|epoch| env::epoch_height() >= epoch)
+    }
+
+    /// Returns the share price up to which the allocation accrues rewards. Once the share price at the
+    /// allocation expiry has been recorded, rewards are capped at that share price.
+    pub(crate) fn internal_accrual_share_price(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+        global_share_price: SharePrice,
+    ) -> SharePrice {
+        match self
+            .internal_allocation_expiry(allocator, recipient)
+            .and_then(|epoch| self.expiry_share_prices.get(&epoch))
+        {
+            Some(expiry_share_price)
+                if expiry_share_price.scaled() < global_share_price.scaled() =>
+            {
+                *expiry_share_price
+            }
+            _ => global_share_price,
+        }
+    }
+
+    /// Sets or removes the expiry epoch of the allocation to the recipient, keeping count of the allocations
+    /// expiring at each epoch. The share price recorded for an epoch is removed along with its last allocation.
+    pub(crate) fn internal_set_allocation_expiry(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+        expires_at_epoch: Option<u64>,
+    ) {
+        let key = (allocator.clone(), recipient.clone());
+        let previous_epoch = match expires_at_epoch {
+            Some(epoch) => self.allocation_expiries.insert(key, epoch),
+            None => self.allocation_expiries.remove(&key),
+        };
+
+        if let Some(epoch) = expires_at_epoch {
+            *self.expiry_allocation_counts.entry(epoch).or_insert(0) += 1;
+            if !self.expiry_share_prices.contains_key(&epoch) {
+                self.pending_expiry_epochs.insert(epoch, ());
+            }
+        }
+        if let Some(epoch) = previous_epoch {
+            let count = self.expiry_allocation_counts.entry(epoch).or_insert(1);
+            *count -= 1;
+            if *count == 0 {
+                self.expiry_allocation_counts.remove(&epoch);
+                self.pending_expiry_epochs.remove(&epoch);
+                self.expiry_share_prices.remove(&epoch);
+            }
+        }
+    }
+
+    /// Records the current share price for the allocation expiry epochs that have been reached.
+    /// Called before each total staked update is applied, so the rewards synced at or after the expiry epoch
+    /// don't accrue to the expired allocations.
+    pub(crate) fn internal_record_expiry_share_prices(&mut self) {
+        let share_price = self.current_share_price();
+        let reached_epochs: Vec<u64> = self
+            .pending_expiry_epochs
+            .range(..=env::epoch_height())
+            .map(|(epoch, _)| *epoch)
+            .collect();
+        for epoch in reached_epochs {
+            self.pending_expiry_epochs.remove(&epoch);
+            self.expiry_share_prices.insert(epoch, share_price);
+        }
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns the epoch from which the allocation to the recipient stops accruing rewards, if it has one.
+    pub fn get_allocation_expiry(
+        &self,
+        distributor: AccountId,
+        recipient: AccountId,
+    ) -> Option<U64> {
+        self.internal_allocation_expiry(&distributor, &recipient)
+            .map(U64)
+    }
+
+    /// Returns the share price recorded for an allocation expiry epoch.
+    pub fn get_expiry_share_price(&self, epoch: U64) -> Option<SharePrice> {
+        self.expiry_share_prices.get(&epoch.0).copied()
+    }
+
+    /// Sets the epoch from which the caller's allocation to the recipient stops accruing rewards,
+    /// or removes the expiry when no epoch is provided. The expiry can be at most `MAX_EXPIRY_EPOCHS`
+    /// epochs ahead. Expired allocations cannot be changed.
+    pub fn set_allocation_expiry(&mut self, recipient: AccountId, expires_at_epoch: Option<U64>) {
+        self.check_not_paused();
+        self.check_whitelisted();
+
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.allocations.contains_key(&distributor),
+            ERR_NO_ALLOCATIONS
+        );
+        require!(
+            self.internal_allocation(&distributor, &recipient).is_some(),
+            ERR_NO_ALLOCATIONS_TO_RECIPIENT
+        );
+        require!(
+            !self.internal_is_expired_allocation(&distributor, &recipient),
+            ERR_ALLOCATION_EXPIRED
+        );
+
+        if let Some(epoch) = expires_at_epoch {
+            require!(epoch.0 > env::epoch_height(), ERR_INVALID_EXPIRY_EPOCH);
+            require!(
+                epoch.0 <= env::epoch_height() + MAX_EXPIRY_EPOCHS,
+                ERR_EXPIRY_EPOCH_TOO_FAR
+            );
+        }
+        self.internal_set_allocation_expiry(
+            &distributor,
+            &recipient,
+            expires_at_epoch.map(|epoch| epoch.0),
+        );
+
+        Event::AllocationExpirySet {
+            user: &distributor,
+            recipient: &recipient,
+            expires_at_epoch: &expires_at_epoch,
+        }
+        .emit();
+    }
+
+    /// Removes an expired allocation once its rewards have been distributed and refunds the storage
+    /// deposit to the distributor. Callable by anyone.
+    pub fn remove_expired_allocation(&mut self, distributor: AccountId, recipient: AccountId) {
+        self.check_not_paused();
+
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.allocations.contains_key(&distributor),
+            ERR_NO_ALLOCATIONS
+        );
+        let allocation = self
+            .internal_allocation(&distributor, &recipient)
+            .expect(ERR_NO_ALLOCATIONS_TO_RECIPIENT);
+        let expiry_share_price_recorded = self
+            .internal_allocation_expiry(&distributor, &recipient)
+            .is_some_and(|epoch| self.expiry_share_prices.contains_key(&epoch));
+        require!(expiry_share_price_recorded, ERR_ALLOCATION_NOT_EXPIRED);
+
+        let share_price =
+            self.internal_accrual_share_price(&distributor, &recipient, self.current_share_price());
+        let allocation = self.internal_resolve_allocation(&distributor, &recipient, &allocation);
+        require!(
+            Self::internal_calculate_distribution_amount(&allocation, share_price) == 0,
+            ERR_EXPIRED_ALLOCATION_HAS_REWARDS
+        );
+
+        Event::ExpiredAllocationRemoved {
+            user: &distributor,
+            recipient: &recipient,
+            caller: &env::predecessor_account_id(),
+            near_amount: &allocation.near_amount.into(),
+        }
+        .emit();
+
+        self.internal_delete_allocation(&distributor, &recipient);
+    }
+}
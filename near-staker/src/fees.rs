@@ -0,0 +1,269 @@
+use near_contract_standards::fungible_token::FungibleTokenCore;
+use near_sdk::{env, json_types::U128, near, require, AccountId};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::math::*;
+use crate::*;
+
+impl NearStaker {
+    /// Splits fee shares across the fee split, or assigns them to the treasury if no split is set.
+    /// Rounding dust goes to the first beneficiary.
+    pub(crate) fn split_fee_shares(
+        fee_split: &[FeeBeneficiary],
+        treasury: &AccountId,
+        fee_shares: u128,
+    ) -> Vec<(AccountId, u128)> {
+        if fee_split.is_empty() {
+            return vec![(treasury.clone(), fee_shares)];
+        }
+
+        let mut shares: Vec<(AccountId, u128)> = fee_split
+            .iter()
+            .map(|beneficiary| {
+                let beneficiary_shares = mul_div_with_rounding(
+                    U256::from(fee_shares),
+                    U256::from(beneficiary.basis_points),
+                    U256::from(FEE_PRECISION),
+                    false,
+                );
+                (beneficiary.account_id.clone(), beneficiary_shares.as_u128())
+            })
+            .collect();
+        let dust = fee_shares - shares.iter().map(|(_, amount)| amount).sum::<u128>();
+        shares[0].1 += dust;
+
+        shares
+            .into_iter()
+            .filter(|(_, amount)| *amount > 0)
+            .collect()
+    }
+
+    /// Returns the distribution fee charged to the given distributor.
+    /// An override can only lower the distribution fee below the global distribution fee.
+    pub(crate) fn internal_distribution_fee(&self, distributor: &AccountId) -> u16 {
+        self.fee_overrides
+            .get(distributor)
+            .map_or(self.distribution_fee, |fee_override| {
+                fee_override.distribution_fee.min(self.distribution_fee)
+            })
+    }
+
+    /// Mints to each account with a fee override its part of the collected fee shares that exceeds its fee.
+    /// The rebate is calculated on the shares the account held throughout the fee period, so shares acquired
+    /// since the last fee collection do not earn a rebate. Returns the total shares rebated.
+    pub(crate) fn internal_rebate_fees(&mut self, fee_shares: u128) -> u128 {
+        let rebates = self.internal_calculate_fee_rebates(fee_shares);
+
+        let epoch = env::epoch_height().into();
+        rebates
+            .into_iter()
+            .map(|(account_id, rebate)| {
+                self.internal_mint(rebate, account_id.clone());
+                Event::FeeRebated {
+                    account_id: &account_id,
+                    shares_rebated: &U128(rebate),
+                    epoch: &epoch,
+                }
+                .emit();
+                rebate
+            })
+            .sum()
+    }
+
+    /// Returns the fee shares rebated to each account with a fee override below the global fee.
+    pub(crate) fn internal_calculate_fee_rebates(
+        &self,
+        fee_shares: u128,
+    ) -> Vec<(AccountId, u128)> {
+        if self.fee == 0 {
+            return vec![];
+        }
+        let shares_supply = self.token.total_supply;
+
+        self.fee_overrides
+            .iter()
+            .filter(|(_, fee_override)| fee_override.fee < self.fee)
+            .map(|(account_id, fee_override)| {
+                let eligible_shares = fee_override
+                    .rebate_eligible_shares
+                    .min(self.token.ft_balance_of(account_id.clone()).0);
+                // the account's part of the fee shares, scaled by the part of the fee it is exempt from
+                let rebate = mul_div_with_rounding(
+                    mul256(fee_shares, eligible_shares),
+                    U256::from(self.fee - fee_override.fee),
+                    mul256(shares_supply, self.fee as u128),
+                    false,
+                );
+                (account_id.clone(), rebate.as_u128())
+            })
+            .filter(|(_, rebate)| *rebate > 0)
+            .collect()
+    }
+
+    /// Updates the rebate eligible shares of accounts with fee overrides. A new fee period starts with the
+    /// current balances after fees are collected, otherwise eligible shares only decrease with the balances.
+    pub(crate) fn internal_update_rebate_eligible_shares(&mut self, fees_collected: bool) {
+        for (account_id, fee_override) in self.fee_overrides.iter_mut() {
+            let balance = self.token.accounts.get(account_id).unwrap_or(0);
+            fee_override.rebate_eligible_shares = if fees_collected {
+                balance
+            } else {
+                fee_override.rebate_eligible_shares.min(balance)
+            };
+        }
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns the NEAR fees accrued since the last fee collection, including management fees, and the TruNEAR
+    /// that would be minted for them right now, split into the TruNEAR received by the fee beneficiaries and the
+    /// TruNEAR rebated to fee overrides.
+    pub fn get_pending_fees(&self) -> PendingFeesInfo {
+        let share_price = self.current_share_price();
+        let (near_amount, performance_shares) = self.internal_pending_fees(share_price);
+        let management_shares =
+            share_price.convert_to_shares(self.accrued_management_fees, Rounding::Down);
+        let rebated_shares: u128 = self
+            .internal_calculate_fee_rebates(performance_shares)
+            .iter()
+            .map(|(_, rebate)| rebate)
+            .sum();
+
+        PendingFeesInfo {
+            near_amount: (near_amount + self.accrued_management_fees).into(),
+            shares_amount: (performance_shares + management_shares - rebated_shares).into(),
+            rebated_shares: rebated_shares.into(),
+        }
+    }
+
+    /// Enables or disables collecting fees automatically after each successful total staked update.
+    pub fn set_auto_collect_fees(&mut self, auto_collect_fees: bool) {
+        self.check_owner();
+        Event::SetAutoCollectFees {
+            old_auto_collect_fees: &self.auto_collect_fees,
+            new_auto_collect_fees: &auto_collect_fees,
+        }
+        .emit();
+        self.auto_collect_fees = auto_collect_fees;
+    }
+
+    /// Returns the beneficiaries that collected fees are split across.
+    pub fn get_fee_split(&self) -> Vec<FeeBeneficiary> {
+        if self.fee_split.is_empty() {
+            return vec![FeeBeneficiary {
+                account_id: self.treasury.clone(),
+                basis_points: FEE_PRECISION,
+            }];
+        }
+        self.fee_split.clone()
+    }
+
+    /// Sets the beneficiaries that staking and distribution fees are split across.
+    /// The basis points must sum to FEE_PRECISION. An empty split sends all fees to the treasury.
+    pub fn set_fee_split(&mut self, fee_split: Vec<FeeBeneficiary>) {
+        self.check_owner();
+        if !fee_split.is_empty() {
+            require!(
+                fee_split
+                    .iter()
+                    .all(|beneficiary| beneficiary.basis_points > 0),
+                ERR_FEE_SPLIT_ZERO_SHARE
+            );
+            require!(
+                fee_split
+                    .iter()
+                    .map(|beneficiary| beneficiary.basis_points as u32)
+                    .sum::<u32>()
+                    == FEE_PRECISION as u32,
+                ERR_INVALID_FEE_SPLIT
+            );
+            for (i, beneficiary) in fee_split.iter().enumerate() {
+                require!(
+                    !fee_split[..i]
+                        .iter()
+                        .any(|other| other.account_id == beneficiary.account_id),
+                    ERR_FEE_SPLIT_DUPLICATE
+                );
+            }
+        }
+
+        Event::SetFeeSplit {
+            old_fee_split: &self.fee_split,
+            new_fee_split: &fee_split,
+        }
+        .emit();
+
+        // register the beneficiaries if they don't have a TruNEAR account
+        for beneficiary in fee_split.iter() {
+            if !self.token.accounts.contains_key(&beneficiary.account_id) {
+                self.token.accounts.insert(&beneficiary.account_id, &0);
+            }
+        }
+        self.fee_split = fee_split;
+    }
+
+    /// Returns all fee overrides.
+    pub fn get_fee_overrides(&self) -> Vec<FeeOverrideInfo> {
+        let mut fee_overrides: Vec<FeeOverrideInfo> = self
+            .fee_overrides
+            .keys()
+            .filter_map(|account_id| self.get_fee_override(account_id.clone()))
+            .collect();
+        fee_overrides.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        fee_overrides
+    }
+
+    /// Returns the fee override of an account, if any.
+    pub fn get_fee_override(&self, account_id: AccountId) -> Option<FeeOverrideInfo> {
+        self.fee_overrides
+            .get(&account_id)
+            .map(|fee_override| FeeOverrideInfo {
+                account_id,
+                fee: fee_override.fee,
+                distribution_fee: fee_override.distribution_fee,
+            })
+    }
+
+    /// Sets the fees charged to an account instead of the global fees. A fee or distribution fee above the
+    /// corresponding global fee is not charged. The fee rebate of a new override starts from the next fee collection.
+    pub fn set_fee_override(&mut self, account_id: AccountId, fee: u16, distribution_fee: u16) {
+        self.check_owner();
+        require!(fee < FEE_PRECISION, ERR_FEE_TOO_LARGE);
+        require!(distribution_fee < FEE_PRECISION, ERR_FEE_TOO_LARGE);
+
+        self.fee_overrides
+            .entry(account_id.clone())
+            .and_modify(|fee_override| {
+                fee_override.fee = fee;
+                fee_override.distribution_fee = distribution_fee;
+            })
+            .or_insert(FeeOverride {
+                fee,
+                distribution_fee,
+                rebate_eligible_shares: 0,
+            });
+
+        Event::FeeOverrideSet {
+            account_id: &account_id,
+            fee: &fee,
+            distribution_fee: &distribution_fee,
+        }
+        .emit();
+    }
+
+    /// Removes the fee override of an account. Fees accrued since the last fee collection are not rebated.
+    pub fn remove_fee_override(&mut self, account_id: AccountId) {
+        self.check_owner();
+        require!(
+            self.fee_overrides.remove(&account_id).is_some(),
+            ERR_NO_FEE_OVERRIDE
+        );
+
+        Event::FeeOverrideRemoved {
+            account_id: &account_id,
+        }
+        .emit();
+    }
+}
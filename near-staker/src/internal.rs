@@ -1,6 +1,7 @@
 // Private Methods
 use near_contract_standards::fungible_token::events::{FtBurn, FtMint};
 use near_contract_standards::fungible_token::FungibleTokenCore;
+use near_sdk::store::{IterableSet, TreeMap};
 use near_sdk::{
     env, json_types::U128, log, require, serde_json::json, AccountId, NearToken, Promise,
 };
@@ -36,6 +37,18 @@ impl NearStaker {
         );
     }
 
+    /// Checks that the account holds enough TruNEAR that isn't locked by escrowed allocations.
+    pub(crate) fn check_unlocked_balance(&self, account_id: &AccountId, amount: u128) {
+        require!(
+            self.token
+                .ft_balance_of(account_id.clone())
+                .0
+                .saturating_sub(self.internal_locked_shares(account_id))
+                >= amount,
+            ERR_INSUFFICIENT_UNLOCKED_TRUNEAR_BALANCE
+        );
+    }
+
     /// Checks that the caller is a whitelisted contract user.
     pub(crate) fn check_whitelisted(&self) {
         require!(
@@ -44,6 +57,15 @@ impl NearStaker {
         );
     }
 
+    /// Checks that the caller is the owner or a whitelisted contract user.
+    pub(crate) fn check_owner_or_whitelisted(&self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.owner_id == caller || self.is_whitelisted(caller),
+            ERR_USER_NOT_WHITELISTED
+        );
+    }
+
     /// Checks that the deposit amount is greater than the staker's minimum deposit amount.
     pub(crate) fn check_min_deposit_amount(&self, amount: u128) {
         require!(amount >= self.min_deposit, ERR_STAKE_BELOW_MIN_DEPOSIT);
@@ -68,6 +90,21 @@ impl NearStaker {
 
     /// Internal Methods ///
 
+    /// Returns the current share price.
+    pub(crate) fn current_share_price(&self) -> SharePrice {
+        // accrued management fees are owed to the fee beneficiaries, so they are excluded from the staked
+        // assets while leaving the taxable rewards unchanged. The share price falls when the accrued
+        // management fees exceed the rewards
+        Self::internal_share_price(
+            self.total_staked
+                .saturating_sub(self.accrued_management_fees),
+            self.token.ft_total_supply().0,
+            self.tax_exempt_stake
+                .saturating_sub(self.accrued_management_fees),
+            self.fee,
+        )
+    }
+
     /// Stakes the specified amount of NEAR tokens into the specified delegation pool.
     pub(crate) fn internal_deposit_and_stake(
         &mut self,
@@ -90,12 +127,34 @@ impl NearStaker {
         amount: u128,
         caller: AccountId,
     ) -> Promise {
+        Self::stake_on_pool(pool_id.clone(), amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(XCC_GAS)
+                .finalize_deposit_and_stake(pool_id, U128(amount), caller),
+        )
+    }
+
+    /// Sends the stake promises to the staking pool upon donation.
+    pub(crate) fn send_donation_promises(
+        pool_id: AccountId,
+        amount: u128,
+        donor: AccountId,
+    ) -> Promise {
+        Self::stake_on_pool(pool_id.clone(), amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(XCC_GAS)
+                .finalize_donation(pool_id, U128(amount), donor),
+        )
+    }
+
+    /// Stakes the given amount on the pool and fetches the staker's new total balance on it.
+    pub(crate) fn stake_on_pool(pool_id: AccountId, amount: u128) -> Promise {
         let staker_id: AccountId = env::current_account_id();
 
         let staker_arg = json!({ "account_id": staker_id }).to_string().into_bytes();
 
         // we first call deposit_and_stake followed by get_account_total_balance to ensure the stake has been added
-        Promise::new(pool_id.clone())
+        Promise::new(pool_id)
             .function_call(
                 "deposit_and_stake".to_owned(),
                 NO_ARGS,
@@ -108,35 +167,26 @@ impl NearStaker {
                 NO_DEPOSIT,
                 VIEW_GAS,
             )
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(XCC_GAS)
-                    .finalize_deposit_and_stake(pool_id, U128(amount), caller),
-            )
     }
 
     /// Unstakes NEAR from the specified pool, withdrawing first if necessary.
+    /// The attached storage deposit is refunded to the storage payer if the unstake fails.
     pub(crate) fn send_unstake_promises(
         &mut self,
         pool_id: AccountId,
         amount: u128,
         caller: AccountId,
         attached_near: NearToken,
+        storage_payer: AccountId,
     ) -> Promise {
         // ensure amount of shares burned is greater than 0
-        let (share_price_num, share_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
-        );
+        let share_price = self.current_share_price();
 
-        let shares_amount =
-            Self::convert_to_shares(amount, share_price_num, share_price_denom, false);
+        let shares_amount = share_price.convert_to_shares(amount, Rounding::Down);
         if shares_amount == 0 {
             log!("Failed to unstake: {}", ERR_UNSTAKE_AMOUNT_TOO_LOW);
             self.is_locked = false;
-            return Promise::new(caller).transfer(attached_near);
+            return Promise::new(storage_payer).transfer(attached_near);
         }
 
         // burn user shares and update total staked to keep share price the same
@@ -187,24 +237,42 @@ impl NearStaker {
                 NO_DEPOSIT,
                 VIEW_GAS,
             );
+        let (share_price_num, share_price_denom) = share_price.to_strings();
         promise.then(
             Self::ext(env::current_account_id())
                 .with_static_gas(XCC_GAS)
-                .finalize_unstake(
+                .finalize_unstake(UnstakeDetails {
                     pool_id,
-                    U128(amount),
+                    amount: U128(amount),
                     caller,
                     pre_unstake_staker_balance,
-                    share_price_num.to_string(),
-                    share_price_denom.to_string(),
-                    U128(shares_amount),
+                    share_price_num,
+                    share_price_denom,
+                    shares_amount: U128(shares_amount),
                     withdraw_occurred,
                     attached_near,
-                    env::epoch_height(),
-                ),
+                    unstake_epoch: env::epoch_height(),
+                    storage_payer,
+                }),
         )
     }
 
+    /// Checks that there is no pending unstake from previous epochs on the pool. If there is, we cannot unlock as
+    /// it would push back the pending unstake by a further four epochs.
+    pub(crate) fn check_unstake_not_locked(&self, pool_id: &AccountId) {
+        let pool_last_unstake = self.delegation_pools.get(pool_id).unwrap().last_unstake;
+        let current_epoch = env::epoch_height();
+
+        // we can unlock if the last unstake happened in the same epoch or more than 4 epochs ago (there is withdrawable stake)
+        if let Some(last_unstake) = pool_last_unstake {
+            require!(
+                last_unstake == current_epoch
+                    || last_unstake + NUM_EPOCHS_TO_UNLOCK <= current_epoch,
+                ERR_UNSTAKE_LOCKED
+            );
+        }
+    }
+
     /// Unstakes the specified amount of NEAR tokens from the specified delegation pool.
     pub(crate) fn internal_unstake(
         &mut self,
@@ -220,47 +288,75 @@ impl NearStaker {
             ERR_STORAGE_DEPOSIT_TOO_SMALL
         );
 
-        // We must check that there is no pending unstake from previous epochs on the pool. If there is, we cannot unlock as
-        // it would push back the pending unstake by a further four epochs.
-        let pool_last_unstake = self.delegation_pools.get(&pool_id).unwrap().last_unstake;
-        let current_epoch = env::epoch_height();
-
-        // we can unlock if the last unstake happened in the same epoch or more than 4 epochs ago (there is withdrawable stake)
-        if let Some(last_unstake) = pool_last_unstake {
-            require!(
-                last_unstake == current_epoch
-                    || last_unstake + NUM_EPOCHS_TO_UNLOCK <= current_epoch,
-                ERR_UNSTAKE_LOCKED
-            );
-        }
+        self.check_unstake_not_locked(&pool_id);
 
         // if the total staked is up to date, check the requested unstake amount
         let amount = self.internal_check_unstake_amount(&pool_id, amount, &caller);
 
-        self.send_unstake_promises(pool_id, amount, caller, attached_near)
+        self.send_unstake_promises(pool_id, amount, caller.clone(), attached_near, caller)
     }
 
-    /// Updates the total staked amount.   
-    pub(crate) fn internal_update_stake(&self) -> Promise {
+    /// Updates the total staked amount, staking the released campaign rewards on the default pool.
+    pub(crate) fn internal_update_stake(&self, released_rewards: u128) -> Promise {
         let staker_id = env::current_account_id();
         let staker_arg = json!({ "account_id": staker_id }).to_string().into_bytes();
 
         // For each pool, we first call ping on each pool to ensure the pool is synced and up to date.
         // We then fetch the staked + unstaked (total) balance of our staker on the pool.
         let combined_promises = self.delegation_pools_list.iter().flat_map(|pool_id| {
-            vec![Promise::new(pool_id.clone())
-                .function_call("ping".to_owned(), NO_ARGS, NO_DEPOSIT, XCC_GAS)
-                .function_call(
-                    "get_account_total_balance".to_owned(),
-                    staker_arg.to_owned(),
-                    NO_DEPOSIT,
-                    VIEW_GAS,
-                )]
+            let mut promise = Promise::new(pool_id.clone()).function_call(
+                "ping".to_owned(),
+                NO_ARGS,
+                NO_DEPOSIT,
+                XCC_GAS,
+            );
+            // Released campaign rewards are staked in the same batch as the default pool balance query,
+            // so they are staked if and only if the default pool promise succeeds.
+            if released_rewards > 0 && *pool_id == self.default_delegation_pool {
+                promise = promise.function_call(
+                    "deposit_and_stake".to_owned(),
+                    NO_ARGS,
+                    NearToken::from_yoctonear(released_rewards),
+                    XCC_GAS,
+                );
+            }
+            vec![promise.function_call(
+                "get_account_total_balance".to_owned(),
+                staker_arg.to_owned(),
+                NO_DEPOSIT,
+                VIEW_GAS,
+            )]
         });
 
         combined_promises.reduce(|acc, p| acc.and(p)).unwrap()
     }
 
+    /// Returns the campaign rewards that can be released at the given epoch. Rewards are only released
+    /// while the default delegation pool is enabled.
+    pub(crate) fn internal_releasable_campaign_rewards(&self, epoch: u64) -> u128 {
+        let default_pool = self
+            .delegation_pools
+            .get(&self.default_delegation_pool)
+            .unwrap();
+        if default_pool.state != ValidatorState::ENABLED {
+            return 0;
+        }
+
+        self.reward_campaigns
+            .values()
+            .map(|campaign| campaign.releasable_amount(epoch))
+            .sum()
+    }
+
+    /// Marks the campaign rewards releasable at the given epoch as released and removes completed campaigns.
+    pub(crate) fn internal_release_campaign_rewards(&mut self, epoch: u64) {
+        self.reward_campaigns.values_mut().for_each(|campaign| {
+            campaign.released_amount = campaign.vested_amount(epoch);
+        });
+        self.reward_campaigns
+            .retain(|_, campaign| campaign.released_amount < campaign.total_amount);
+    }
+
     /// Executes the unstake requested associated with the given nonce.
     pub(crate) fn internal_withdraw(&mut self, unstake_nonce: U128) -> Option<Promise> {
         let sender = env::predecessor_account_id();
@@ -328,51 +424,94 @@ impl NearStaker {
 
     /// Calculates fees of the taxable amount and mints shares to the treasury.
     pub(crate) fn internal_collect_fees(&mut self) {
-        let (share_price_num, share_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
-        );
-
-        let taxable_amount = self.total_staked.saturating_sub(self.tax_exempt_stake);
+        let share_price = self.current_share_price();
 
-        let near_amount_increase_treasury = mul_div_with_rounding(
-            U256::from(taxable_amount),
-            U256::from(self.fee),
-            U256::from(FEE_PRECISION),
-            false,
-        );
+        let (near_amount_increase_treasury, performance_shares) =
+            self.internal_pending_fees(share_price);
+        let management_shares =
+            share_price.convert_to_shares(self.accrued_management_fees, Rounding::Down);
 
         log!(
-            "NEAR collected as fees: {}",
-            near_amount_increase_treasury.to_string()
-        );
-
-        let share_increase_treasury = Self::convert_to_shares(
-            near_amount_increase_treasury.as_u128(),
-            share_price_num,
-            share_price_denom,
-            false,
+            "NEAR collected as fees: {}. NEAR collected as management fees: {}",
+            near_amount_increase_treasury,
+            self.accrued_management_fees
         );
 
+        let share_increase_treasury = performance_shares + management_shares;
         if share_increase_treasury > 0 {
-            // mint the shares to the treasury
-            self.internal_mint(share_increase_treasury, self.treasury.clone());
+            // rebate the performance fees charged above their override to accounts with fee overrides
+            let shares_rebated = self.internal_rebate_fees(performance_shares);
+            let share_increase_treasury = share_increase_treasury - shares_rebated;
 
-            // update tax exempt stake
+            // update tax exempt stake and reset the accrued management fees
             self.tax_exempt_stake = self.total_staked;
-
-            // emit FeesCollected event
-            Event::FeesCollectedEvent {
-                shares_minted: &U128(share_increase_treasury),
-                treasury_balance: &self.ft_balance_of(self.treasury.clone()),
-                share_price_num: &share_price_num.to_string(),
-                share_price_denom: &share_price_denom.to_string(),
-                epoch: &env::epoch_height().into(),
+            self.accrued_management_fees = 0;
+
+            // mint the shares to the fee beneficiaries
+            let fee_shares =
+                Self::split_fee_shares(&self.fee_split, &self.treasury, share_increase_treasury);
+            for (beneficiary, shares_minted) in fee_shares {
+                self.internal_mint(shares_minted, beneficiary.clone());
+
+                // emit FeesCollected event
+                Event::FeesCollectedEvent {
+                    beneficiary: &beneficiary,
+                    shares_minted: &U128(shares_minted),
+                    beneficiary_balance: &self.ft_balance_of(beneficiary.clone()),
+                    treasury_balance: &self.ft_balance_of(self.treasury.clone()),
+                    share_price_num: &share_price.num.to_string(),
+                    share_price_denom: &share_price.denom.to_string(),
+                    epoch: &env::epoch_height().into(),
+                }
+                .emit();
             }
-            .emit();
         };
+        self.internal_update_rebate_eligible_shares(share_increase_treasury > 0);
+    }
+
+    /// Accrues the management fee on the total staked for the time elapsed since the last accrual.
+    pub(crate) fn internal_accrue_management_fee(&mut self) {
+        let now = env::block_timestamp();
+        let elapsed = now.saturating_sub(self.management_fee_accrued_at);
+        self.management_fee_accrued_at = now;
+        if self.management_fee == 0 || elapsed == 0 {
+            return;
+        }
+
+        let staked_assets = self
+            .total_staked
+            .saturating_sub(self.accrued_management_fees);
+        let amount = mul_div_with_rounding(
+            mul256(staked_assets, self.management_fee as u128),
+            U256::from(elapsed),
+            mul256(FEE_PRECISION as u128, NANOSECONDS_PER_YEAR as u128),
+            false,
+        )
+        .as_u128();
+        self.accrued_management_fees += amount;
+
+        Event::ManagementFeeAccrued {
+            amount: &U128(amount),
+            accrued_management_fees: &U128(self.accrued_management_fees),
+            epoch: &env::epoch_height().into(),
+        }
+        .emit();
+    }
+
+    /// Returns the NEAR fees accrued on the taxable amount and the TruNEAR they are worth at the given share price.
+    pub(crate) fn internal_pending_fees(&self, share_price: SharePrice) -> (u128, u128) {
+        let taxable_amount = self.total_staked.saturating_sub(self.tax_exempt_stake);
+
+        let near_amount = mul_div_with_rounding(
+            U256::from(taxable_amount),
+            U256::from(self.fee),
+            U256::from(FEE_PRECISION),
+            false,
+        )
+        .as_u128();
+        let shares_amount = share_price.convert_to_shares(near_amount, Rounding::Down);
+
+        (near_amount, shares_amount)
     }
 
     /// Mints an amount of TruNEAR tokens to a specified account.
@@ -384,6 +523,7 @@ impl NearStaker {
             .insert(&recipient, &(account_balance + shares_amount));
 
         self.token.total_supply += shares_amount;
+        self.internal_checkpoint_percentage_allocations(&recipient);
 
         // emit a mint event
         FtMint {
@@ -406,6 +546,7 @@ impl NearStaker {
         self.token.accounts.insert(&user, &new_user_balance);
 
         self.token.total_supply -= shares_burned;
+        self.internal_checkpoint_percentage_allocations(&user);
 
         // emit a burn event
         FtBurn {
@@ -421,43 +562,58 @@ impl NearStaker {
         &mut self,
         distributor: AccountId,
         recipient: AccountId,
-        global_price_num: U256,
-        global_price_denom: U256,
+        global_price: SharePrice,
         in_near: bool,
         attached_near: NearToken,
+        max_shares: Option<u128>,
     ) -> Result<Option<DistributionInfo>, Box<dyn std::error::Error>> {
-        let allocation = self
-            .allocations
-            .get_mut(&distributor)
-            .unwrap()
-            .get_mut(&recipient)
-            .unwrap();
-
-        // check if there are any rewards to distribute. If not, return no distribution
-        if allocation.share_price_num / allocation.share_price_denom
-            == global_price_num / global_price_denom
-        {
+        if self.internal_is_pending_allocation(&distributor, &recipient) {
+            return Err(ERR_ALLOCATION_PENDING.into());
+        }
+        let distribution_fee = self.internal_distribution_fee(&distributor);
+        let stored_allocation = self.internal_allocation(&distributor, &recipient).unwrap();
+        let accrual_price =
+            self.internal_accrual_share_price(&distributor, &recipient, global_price);
+
+        // check if there are any rewards to distribute. If not, return no distribution. The allocation keeps
+        // its share price when the share price has fallen, so only rewards above it are distributed
+        if accrual_price.scaled() <= stored_allocation.share_price.scaled() {
             return Ok(None);
         }
 
         // calculate the amount of rewards that have accumulated
-        let mut shares_to_move = Self::internal_calculate_distribution_amount(
-            allocation,
-            global_price_num,
-            global_price_denom,
-        );
+        let allocation =
+            self.internal_resolve_allocation(&distributor, &recipient, &stored_allocation);
+        let mut shares_to_move =
+            Self::internal_calculate_distribution_amount(&allocation, accrual_price);
+        if shares_to_move == 0 {
+            return Ok(None);
+        }
+
+        // when the distribution is capped, the allocation share price is moved only as far as the
+        // distributed shares, so the rest of the rewards remain to be distributed
+        let share_price = match max_shares {
+            Some(max_shares) if shares_to_move > max_shares => {
+                let allocated_shares = allocation
+                    .share_price
+                    .convert_to_shares(allocation.near_amount, Rounding::Down);
+                shares_to_move = max_shares;
+                SharePrice::from_assets_and_shares(
+                    allocation.near_amount,
+                    U256::from(allocated_shares - max_shares),
+                )
+            }
+            _ => accrual_price,
+        };
 
         // calculate the distribution fee if applicable
-        let fees = shares_to_move * (self.distribution_fee as u128) / (FEE_PRECISION as u128);
+        let fees = shares_to_move * (distribution_fee as u128) / (FEE_PRECISION as u128);
         shares_to_move -= fees;
 
         // calculate the amount of rewards in NEAR
-        let near_amount = NearToken::from_yoctonear(Self::convert_to_assets(
-            shares_to_move,
-            global_price_num,
-            global_price_denom,
-            false,
-        ));
+        let near_amount = NearToken::from_yoctonear(
+            global_price.convert_to_assets(shares_to_move, Rounding::Down),
+        );
 
         let refund_amount;
         if in_near {
@@ -470,7 +626,13 @@ impl NearStaker {
             refund_amount = attached_near.checked_sub(near_amount).unwrap();
             Promise::new(recipient.clone()).transfer(near_amount);
         } else {
-            let distributor_trunear_balance = self.token.ft_balance_of(distributor.clone()).0;
+            // the rewards on escrowed allocations are paid from the locked TruNEAR backing them,
+            // while other allocations can only be paid from unlocked TruNEAR
+            let mut distributor_trunear_balance = self.token.ft_balance_of(distributor.clone()).0;
+            if !self.internal_is_escrowed_allocation(&distributor, &recipient) {
+                distributor_trunear_balance = distributor_trunear_balance
+                    .saturating_sub(self.internal_locked_shares(&distributor));
+            }
             if shares_to_move > distributor_trunear_balance {
                 // if don't have enough TruNEAR return an error
                 return Err(ERR_INSUFFICIENT_TRUNEAR_BALANCE.into());
@@ -487,24 +649,223 @@ impl NearStaker {
                 .internal_transfer(&distributor, &recipient, shares_to_move, None);
         }
 
-        // transfer fees to the treasury
+        // transfer fees to the fee beneficiaries
+        let mut beneficiaries = vec![];
         if fees > 0 {
-            self.token
-                .internal_transfer(&distributor, &self.treasury, fees, None);
+            for (beneficiary, fee_shares) in
+                Self::split_fee_shares(&self.fee_split, &self.treasury, fees)
+            {
+                self.token
+                    .internal_transfer(&distributor, &beneficiary, fee_shares, None);
+                beneficiaries.push(beneficiary);
+            }
         }
 
         // update the allocation and return the distribution info
-        allocation.share_price_num = global_price_num;
-        allocation.share_price_denom = global_price_denom;
+        self.internal_insert_allocation(
+            &distributor,
+            &recipient,
+            Allocation {
+                near_amount: stored_allocation.near_amount,
+                share_price,
+            },
+        );
+        for account in [&distributor, &recipient].into_iter().chain(&beneficiaries) {
+            self.internal_checkpoint_percentage_allocations(account);
+        }
+        let key = (distributor, recipient);
+        self.last_distribution_epochs
+            .insert(key.clone(), env::epoch_height());
+        let ledger = self.distribution_ledgers.entry(key).or_default();
+        ledger.shares_distributed += shares_to_move;
+        ledger.near_distributed += near_amount.as_yoctonear();
+        ledger.fees_paid += fees;
+        let ledger = *ledger;
 
         Ok(Some(DistributionInfo {
             shares_amount: shares_to_move,
             near_amount: near_amount.as_yoctonear(),
             refund_amount: refund_amount.as_yoctonear(),
             fees,
-            share_price_num: allocation.share_price_num,
-            share_price_denom: allocation.share_price_denom,
+            share_price,
+            ledger,
+        }))
+    }
+
+    /// Emits the event of a distribution of the allocation rewards to the recipient, with the balances after
+    /// the distribution and the given totals of the distributor's allocations.
+    pub(crate) fn internal_emit_distributed_rewards(
+        &self,
+        distributor: &AccountId,
+        recipient: &AccountId,
+        distribution_info: &DistributionInfo,
+        in_near: bool,
+        via_unstake: bool,
+        total_allocated: (U128, String, String),
+    ) {
+        let (
+            total_allocated_amount,
+            total_allocated_share_price_num,
+            total_allocated_share_price_denom,
+        ) = total_allocated;
+
+        Event::DistributedRewardsEvent(Box::new(DistributedRewards {
+            user: distributor.clone(),
+            recipient: recipient.clone(),
+            shares: U128(distribution_info.shares_amount),
+            near_amount: U128(distribution_info.near_amount),
+            user_balance: self.ft_balance_of(distributor.clone()),
+            recipient_balance: self.ft_balance_of(recipient.clone()),
+            fees: distribution_info.fees.into(),
+            treasury_balance: self.ft_balance_of(self.treasury.clone()),
+            share_price_num: distribution_info.share_price.num.to_string(),
+            share_price_denom: distribution_info.share_price.denom.to_string(),
+            in_near,
+            via_unstake,
+            total_allocated_amount,
+            total_allocated_share_price_num,
+            total_allocated_share_price_denom,
+            total_shares_distributed: distribution_info.ledger.shares_distributed.into(),
+            total_near_distributed: distribution_info.ledger.near_distributed.into(),
+            total_fees_paid: distribution_info.ledger.fees_paid.into(),
         }))
+        .emit();
+    }
+
+    /// Distributes the rewards accrued on the allocation to the recipient as part of a distribution to several
+    /// recipients and emits the distribution event, reporting the distributor's total allocated amount before
+    /// the distribution at the global share price. Returns `None` if there were no rewards to distribute.
+    pub(crate) fn internal_distribute_and_emit(
+        &mut self,
+        distributor: &AccountId,
+        recipient: &AccountId,
+        global_price: SharePrice,
+        in_near: bool,
+        attached_near: NearToken,
+        total_allocated_amount: U128,
+    ) -> Result<Option<DistributionInfo>, Box<dyn std::error::Error>> {
+        let distribution_info = self.internal_distribute(
+            distributor.clone(),
+            recipient.clone(),
+            global_price,
+            in_near,
+            attached_near,
+            None,
+        )?;
+        if let Some(distribution_info) = &distribution_info {
+            self.internal_emit_distributed_rewards(
+                distributor,
+                recipient,
+                distribution_info,
+                in_near,
+                false,
+                (
+                    total_allocated_amount,
+                    global_price.num.to_string(),
+                    global_price.denom.to_string(),
+                ),
+            );
+        }
+        Ok(distribution_info)
+    }
+
+    /// Distributes the rewards accrued on the allocations to a batch of up to `limit` recipients, ordered by
+    /// account ID and starting at `from_recipient` or the first recipient after it. The distribution is paid for
+    /// with the attached NEAR and the distributor's TruNEAR, and the NEAR that wasn't needed is refunded.
+    /// Distributions that fail or have no rewards are logged and skipped. Returns the number of distributions
+    /// and the recipient the next batch starts at.
+    pub(crate) fn internal_distribute_batch(
+        &mut self,
+        distributor: &AccountId,
+        from_recipient: Option<&AccountId>,
+        limit: u32,
+        in_near: bool,
+    ) -> (u32, Option<AccountId>) {
+        let (recipients, next_recipient) =
+            self.internal_allocation_recipients_from(distributor, from_recipient, limit);
+
+        // ensure distributor has enough NEAR and TruNEAR to complete the distribution to the batch
+        let (required_shares, required_near) =
+            self.internal_distribution_amounts(distributor, &recipients, in_near);
+        if self.ft_balance_of(distributor.clone()).0 < required_shares {
+            env::panic_str(ERR_INSUFFICIENT_TRUNEAR_BALANCE);
+        }
+        if env::attached_deposit().as_yoctonear() < required_near {
+            env::panic_str(ERR_INSUFFICIENT_NEAR_BALANCE);
+        }
+
+        let (total_allocated_amount, _, _) = self.get_total_allocated(distributor.clone());
+        let global_price = self.current_share_price();
+
+        // this holds the amount of NEAR we will need to refund to the distributor at the end of the distribution
+        let mut refund_near_amount = env::attached_deposit();
+        let mut distributions = 0;
+        for recipient in recipients.iter() {
+            match self.internal_distribute_and_emit(
+                distributor,
+                recipient,
+                global_price,
+                in_near,
+                refund_near_amount,
+                total_allocated_amount,
+            ) {
+                Err(error) => log!("Error distributing rewards: {}", error),
+                Ok(None) => log!("No rewards to distribute to {}", recipient),
+                Ok(Some(distribution_info)) => {
+                    // update the near amount left for the next distribution
+                    refund_near_amount = NearToken::from_yoctonear(distribution_info.refund_amount);
+                    distributions += 1;
+                }
+            }
+        }
+
+        // refund any excess NEAR to distributor
+        if refund_near_amount.as_yoctonear() > 0 {
+            Promise::new(distributor.clone()).transfer(refund_near_amount);
+        }
+        (distributions, next_recipient)
+    }
+
+    /// Distributes the rewards accrued on an allocation in TruNEAR before the allocation is changed,
+    /// emitting the distribution event. Unlike `distribute_rewards`, this doesn't require the contract
+    /// to be in sync or unlocked, so the allocation can always be changed. Rewards that can't be distributed,
+    /// such as on an allocation the recipient hasn't accepted or when escrowed allocations lock the TruNEAR
+    /// needed, are left on the allocation. Returns whether the rewards were settled.
+    pub(crate) fn internal_settle_rewards(
+        &mut self,
+        distributor: &AccountId,
+        recipient: &AccountId,
+    ) -> bool {
+        let global_price = self.current_share_price();
+        let distribution_info = match self.internal_distribute(
+            distributor.clone(),
+            recipient.clone(),
+            global_price,
+            false,
+            NearToken::from_yoctonear(0),
+            None,
+        ) {
+            Err(error) => {
+                log!("Rewards not settled to {}: {}", recipient, error);
+                return false;
+            }
+            Ok(None) => {
+                log!("No rewards to distribute");
+                return true;
+            }
+            Ok(Some(distribution_info)) => distribution_info,
+        };
+
+        // emit Distribute Rewards event
+        self.internal_emit_distributed_rewards(
+            distributor,
+            recipient,
+            &distribution_info,
+            false,
+            false,
+            self.get_total_allocated(distributor.clone()),
+        );
+        true
     }
 
     /// Performs checks on the amount the user requested to unstake
@@ -525,6 +886,7 @@ impl NearStaker {
         } else {
             amount
         };
+        self.check_allocation_coverage(caller, unstake_amount);
 
         // check if there's enough staked balance to unstake on the pool
         require!(
@@ -552,8 +914,12 @@ impl NearStaker {
             epoch: _,
         } = self.unstake_requests.remove(&unstake_nonce.0).unwrap();
 
-        // transfer the withdrawn NEAR plus storage costs to the user and update the contract balance
-        let total_transfer_amount = near_amount + Self::get_storage_cost().0;
+        // transfer the withdrawn NEAR plus the storage deposit paid for the request to the user and update the contract balance
+        let storage_deposit = self
+            .unstake_storage_deposits
+            .remove(&unstake_nonce.0)
+            .unwrap_or(Self::get_storage_cost().0);
+        let total_transfer_amount = near_amount + storage_deposit;
         Promise::new(user.clone()).transfer(NearToken::from_yoctonear(total_transfer_amount));
 
         Event::WithdrawalEvent {
@@ -574,9 +940,9 @@ impl NearStaker {
         shares_supply: u128,
         tax_exempt_stake: u128,
         fee: u16,
-    ) -> (U256, U256) {
+    ) -> SharePrice {
         if shares_supply == 0 {
-            return (U256::from(SHARE_PRICE_SCALING_FACTOR), U256::from(1));
+            return SharePrice::initial();
         };
         // the taxable amount is the accrued rewards that we have not yet accounted for
         let taxable_amount = total_staked.saturating_sub(tax_exempt_stake);
@@ -587,86 +953,583 @@ impl NearStaker {
         let price_num = mul256(taxed_total_staked, SHARE_PRICE_SCALING_FACTOR);
         let price_denom = mul256(shares_supply, FEE_PRECISION as u128);
 
-        (price_num, price_denom)
-    }
-
-    /// Converts an amount of NEAR tokens to the equivalent TruNEAR amount using the specified rounding.
-    pub(crate) fn convert_to_shares(
-        assets: u128,
-        share_price_num: U256,
-        share_price_denom: U256,
-        rounding_up: bool,
-    ) -> u128 {
-        let shares = mul_div_with_rounding(
-            U256::from(assets),
-            share_price_denom,
-            share_price_num / U256::from(SHARE_PRICE_SCALING_FACTOR),
-            rounding_up,
-        );
-        shares.as_u128()
-    }
-
-    /// Converts an amount of TruNEAR tokens to the equivalent NEAR amount using the specified rounding.
-    pub(crate) fn convert_to_assets(
-        shares: u128,
-        share_price_num: U256,
-        share_price_denom: U256,
-        rounding_up: bool,
-    ) -> u128 {
-        mul_div_with_rounding(
-            U256::from(shares),
-            share_price_num / U256::from(SHARE_PRICE_SCALING_FACTOR),
-            share_price_denom,
-            rounding_up,
-        )
-        .as_u128()
+        SharePrice::new(price_num, price_denom)
     }
 
     /// Calculates the updated allocation values.
     pub(crate) fn calculate_updated_allocation(
         existing: &Allocation,
         amount: u128,
-        global_share_price_num: U256,
-        global_share_price_denom: U256,
+        global_share_price: SharePrice,
     ) -> Allocation {
-        let share_price_denom = mul_div_with_rounding(
-            U256::from(existing.near_amount),
-            existing.share_price_denom,
-            existing.share_price_num / SHARE_PRICE_SCALING_FACTOR,
-            false,
-        ) + mul_div_with_rounding(
-            U256::from(amount),
-            global_share_price_denom,
-            global_share_price_num / SHARE_PRICE_SCALING_FACTOR,
-            false,
-        );
+        // the new allocation share price is the total allocated NEAR over the sum of the shares
+        // each part of the allocation was worth at the price it was allocated at
+        let shares = existing
+            .share_price
+            .checked_to_shares(U256::from(existing.near_amount), Rounding::Down)
+            .and_then(|existing_shares| {
+                global_share_price
+                    .checked_to_shares(U256::from(amount), Rounding::Down)
+                    .and_then(|new_shares| existing_shares.checked_add(new_shares))
+            })
+            .expect(ERR_SHARE_PRICE_OVERFLOW);
 
-        let share_price_num = mul256(existing.near_amount + amount, SHARE_PRICE_SCALING_FACTOR);
         Allocation {
             near_amount: existing.near_amount + amount,
-            share_price_num,
-            share_price_denom,
+            share_price: SharePrice::from_assets_and_shares(existing.near_amount + amount, shares),
         }
     }
 
-    /// Calculates the distribution amount for the given allocation.
+    /// Allocates an amount of NEAR to a recipient at the current share price and emits the allocation event.
+    pub(crate) fn internal_allocate(
+        &mut self,
+        allocator: AccountId,
+        recipient: AccountId,
+        amount: u128,
+    ) {
+        require!(
+            !self.internal_is_expired_allocation(&allocator, &recipient),
+            ERR_ALLOCATION_EXPIRED
+        );
+        let global_share_price = self.current_share_price();
+
+        let attached_deposit = env::attached_deposit();
+
+        let (updated_allocation, storage_cost) = match self
+            .internal_allocation(&allocator, &recipient)
+        {
+            // updates the recipients allocation if it exists
+            Some(existing) => (
+                Self::calculate_updated_allocation(&existing, amount, global_share_price),
+                NearToken::from_near(0),
+            ),
+            // inserts a new allocation if one doesn't exist for this recipient
+            None => {
+                let storage_cost = NearToken::from_yoctonear(Self::get_allocation_storage_cost().0);
+                require!(
+                    attached_deposit >= storage_cost,
+                    ERR_STORAGE_DEPOSIT_TOO_SMALL
+                );
+                self.internal_request_consent(&allocator, &recipient);
+                self.allocation_storage_deposits.insert(
+                    (allocator.clone(), recipient.clone()),
+                    storage_cost.as_yoctonear(),
+                );
+                (
+                    Allocation {
+                        near_amount: amount,
+                        share_price: global_share_price,
+                    },
+                    storage_cost,
+                )
+            }
+        };
+
+        self.internal_insert_allocation(&allocator, &recipient, updated_allocation);
+        self.internal_index_allocation(&allocator, &recipient);
+        self.check_allocation_coverage(&allocator, 0);
+        let (
+            total_allocated_amount,
+            total_allocated_share_price_num,
+            total_allocated_share_price_denom,
+        ) = self.get_total_allocated(allocator.clone());
+
+        // refund any excess NEAR to allocator
+        if attached_deposit > storage_cost {
+            Promise::new(allocator.clone())
+                .transfer(attached_deposit.checked_sub(storage_cost).unwrap());
+        }
+
+        // emit event
+        Event::AllocatedEvent {
+            user: &allocator,
+            recipient: &recipient,
+            amount: &amount.into(),
+            total_amount: &updated_allocation.near_amount.into(),
+            share_price_num: &updated_allocation.share_price.num.to_string(),
+            share_price_denom: &updated_allocation.share_price.denom.to_string(),
+            total_allocated_amount: &total_allocated_amount,
+            total_allocated_share_price_num: &total_allocated_share_price_num,
+            total_allocated_share_price_denom: &total_allocated_share_price_denom,
+        }
+        .emit();
+    }
+
+    /// Returns the allocation to the recipient, if there is one. Allocations that haven't been migrated
+    /// yet are read from the legacy storage.
+    pub(crate) fn internal_allocation(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> Option<Allocation> {
+        match self.allocations.get(allocator) {
+            Some(user_allocations) => user_allocations.get(recipient).copied(),
+            None => self
+                .legacy_allocations
+                .get(allocator)
+                .and_then(|legacy_allocations| legacy_allocations.get(recipient))
+                .copied(),
+        }
+    }
+
+    /// Returns a page of the allocator's allocations ordered by recipient. Allocations that haven't been
+    /// migrated yet are read from the legacy storage.
+    pub(crate) fn internal_allocations_page(
+        &self,
+        allocator: &AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<(AccountId, Allocation)> {
+        let from_index = from_index.unwrap_or(0) as usize;
+        let limit = limit.map_or(usize::MAX, |limit| limit as usize);
+        match self.allocations.get(allocator) {
+            Some(user_allocations) => user_allocations
+                .iter()
+                .skip(from_index)
+                .take(limit)
+                .map(|(recipient, allocation)| (recipient.clone(), *allocation))
+                .collect(),
+            None => self
+                .internal_legacy_allocations(allocator)
+                .into_iter()
+                .skip(from_index)
+                .take(limit)
+                .collect(),
+        }
+    }
+
+    /// Returns up to `limit` recipients of the allocator's migrated allocations, starting at `from_recipient`
+    /// or the first recipient after it, along with the recipient the next page starts at.
+    pub(crate) fn internal_allocation_recipients_from(
+        &self,
+        allocator: &AccountId,
+        from_recipient: Option<&AccountId>,
+        limit: u32,
+    ) -> (Vec<AccountId>, Option<AccountId>) {
+        let Some(user_allocations) = self.allocations.get(allocator) else {
+            return (vec![], None);
+        };
+        // one more recipient is taken to find where the next page starts
+        let page_size = (limit as usize).saturating_add(1);
+        let mut recipients: Vec<AccountId> = match from_recipient {
+            Some(from_recipient) => user_allocations
+                .range(from_recipient.clone()..)
+                .take(page_size)
+                .map(|(recipient, _)| recipient.clone())
+                .collect(),
+            None => user_allocations.keys().take(page_size).cloned().collect(),
+        };
+        let next_recipient = if recipients.len() > limit as usize {
+            recipients.pop()
+        } else {
+            None
+        };
+        (recipients, next_recipient)
+    }
+
+    /// Returns the allocator's allocations that haven't been migrated yet, ordered by recipient.
+    fn internal_legacy_allocations(&self, allocator: &AccountId) -> Vec<(AccountId, Allocation)> {
+        let mut legacy_allocations: Vec<(AccountId, Allocation)> = self
+            .legacy_allocations
+            .get(allocator)
+            .into_iter()
+            .flatten()
+            .map(|(recipient, allocation)| (recipient.clone(), *allocation))
+            .collect();
+        legacy_allocations.sort_by(|(a, _), (b, _)| a.cmp(b));
+        legacy_allocations
+    }
+
+    /// Returns the totals of the NEAR allocated by the allocator and the TruNEAR it was worth, including
+    /// the allocations that haven't been migrated yet.
+    pub(crate) fn internal_allocation_totals(&self, allocator: &AccountId) -> AllocationTotals {
+        if let Some(totals) = self.allocation_totals.get(allocator) {
+            return *totals;
+        }
+        self.internal_legacy_allocations(allocator).iter().fold(
+            AllocationTotals::default(),
+            |mut totals, (_, allocation)| {
+                totals.near_amount += allocation.near_amount;
+                totals.shares += Self::allocated_shares(allocation);
+                totals
+            },
+        )
+    }
+
+    /// Stores the allocation to the recipient and updates the allocator's allocation totals.
+    /// Each allocator's allocations are kept in their own ordered map, so only the updated allocation is read and written.
+    pub(crate) fn internal_insert_allocation(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+        allocation: Allocation,
+    ) {
+        let previous = self
+            .allocations
+            .entry(allocator.clone())
+            .or_insert_with(|| {
+                TreeMap::new([b"i".as_slice(), &env::sha256(allocator.as_bytes())].concat())
+            })
+            .insert(recipient.clone(), allocation);
+        self.internal_update_allocation_totals(allocator, previous, Some(allocation));
+    }
+
+    /// Removes the allocation to the recipient and updates the allocator's allocation totals.
+    pub(crate) fn internal_remove_allocation(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> Option<Allocation> {
+        let removed = self
+            .allocations
+            .get_mut(allocator)
+            .and_then(|user_allocations| user_allocations.remove(recipient));
+        self.internal_update_allocation_totals(allocator, removed, None);
+        removed
+    }
+
+    /// Replaces the contribution of an allocation to the allocator's allocation totals.
+    fn internal_update_allocation_totals(
+        &mut self,
+        allocator: &AccountId,
+        removed: Option<Allocation>,
+        added: Option<Allocation>,
+    ) {
+        let totals = self.allocation_totals.entry(allocator.clone()).or_default();
+        if let Some(allocation) = removed {
+            totals.near_amount -= allocation.near_amount;
+            totals.shares -= Self::allocated_shares(&allocation);
+        }
+        if let Some(allocation) = added {
+            totals.near_amount += allocation.near_amount;
+            totals.shares += Self::allocated_shares(&allocation);
+        }
+    }
+
+    /// Returns the TruNEAR the allocated amount was worth at the allocation share price.
+    pub(crate) fn allocated_shares(allocation: &Allocation) -> U256 {
+        allocation
+            .share_price
+            .checked_to_shares(U256::from(allocation.near_amount), Rounding::Down)
+            .expect(ERR_SHARE_PRICE_OVERFLOW)
+    }
+
+    /// Moves the allocator's allocations made before allocations were stored in per-allocator maps,
+    /// and adds them to the index of allocations by recipient. Returns whether there was anything to move.
+    pub(crate) fn internal_migrate_allocations(&mut self, allocator: &AccountId) -> bool {
+        let Some(legacy_allocations) = self.legacy_allocations.remove(allocator) else {
+            return false;
+        };
+
+        let mut legacy_allocations: Vec<(AccountId, Allocation)> =
+            legacy_allocations.into_iter().collect();
+        legacy_allocations.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (recipient, allocation) in legacy_allocations.iter() {
+            self.internal_insert_allocation(allocator, recipient, *allocation);
+            self.internal_index_allocation(allocator, recipient);
+        }
+
+        Event::AllocationsMigrated {
+            user: allocator,
+            allocations: &(legacy_allocations.len() as u32),
+        }
+        .emit();
+        true
+    }
+
+    /// Returns the basis points allocated to the recipient if the allocation is a percentage allocation.
+    pub(crate) fn internal_allocation_basis_points(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> Option<u16> {
+        self.allocation_percentages
+            .get(allocator)
+            .and_then(|user_percentages| user_percentages.get(recipient))
+            .copied()
+    }
+
+    /// Returns the rewards distributed over the lifetime of the allocation to the recipient.
+    pub(crate) fn internal_distribution_ledger(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> DistributionLedger {
+        self.distribution_ledgers
+            .get(&(allocator.clone(), recipient.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Removes the state kept alongside an allocation once the allocation is removed.
+    pub(crate) fn internal_clear_allocation_state(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) {
+        let key = (allocator.clone(), recipient.clone());
+        self.distribution_allowances.remove(&key);
+        self.last_distribution_epochs.remove(&key);
+        self.distribution_ledgers.remove(&key);
+        self.pending_allocations.remove(&key);
+        self.percentage_allocation_shares.remove(&key);
+        self.internal_set_allocation_expiry(allocator, recipient, None);
+        if let Some(distributors) = self.allocations_to.get_mut(recipient) {
+            distributors.remove(allocator);
+        }
+    }
+
+    /// Removes the allocation along with its state and refunds the storage deposit to the allocator,
+    /// releasing the TruNEAR locked by the allocation if it is escrowed.
+    pub(crate) fn internal_delete_allocation(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) {
+        let locked_shares_before = self.internal_locked_shares(allocator);
+        let escrowed = self.internal_is_escrowed_allocation(allocator, recipient);
+
+        self.internal_clear_allocation_state(allocator, recipient);
+        self.internal_remove_allocation(allocator, recipient);
+        if let Some(user_percentages) = self.allocation_percentages.get_mut(allocator) {
+            user_percentages.remove(recipient);
+        }
+        if let Some(recipients) = self.escrowed_allocations.get_mut(allocator) {
+            recipients.remove(recipient);
+        }
+
+        self.internal_refund_allocation_storage(allocator, recipient);
+
+        if escrowed {
+            let locked_shares = self.internal_locked_shares(allocator);
+            Event::SharesUnlocked {
+                user: allocator,
+                recipient,
+                shares: &U128(locked_shares_before - locked_shares),
+                locked_balance: &U128(locked_shares),
+            }
+            .emit();
+        }
+    }
+
+    /// Refunds the storage deposit paid for a removed allocation to the allocator. Allocations made before
+    /// deposits were recorded paid the unstake storage cost.
+    pub(crate) fn internal_refund_allocation_storage(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) {
+        let storage_deposit = self
+            .allocation_storage_deposits
+            .remove(&(allocator.clone(), recipient.clone()))
+            .unwrap_or(Self::get_storage_cost().0);
+        Promise::new(allocator.clone()).transfer(NearToken::from_yoctonear(storage_deposit));
+    }
+
+    /// Adds the allocation to the reverse index of the allocations made to each recipient.
+    pub(crate) fn internal_index_allocation(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) {
+        self.allocations_to
+            .entry(recipient.clone())
+            .or_insert_with(|| {
+                IterableSet::new([b"y".as_slice(), &env::sha256(recipient.as_bytes())].concat())
+            })
+            .insert(allocator.clone());
+    }
+
+    /// Returns whether the allocation to the recipient is backed by locked TruNEAR.
+    pub(crate) fn internal_is_escrowed_allocation(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+    ) -> bool {
+        self.escrowed_allocations
+            .get(allocator)
+            .is_some_and(|recipients| recipients.contains(recipient))
+    }
+
+    /// Returns the TruNEAR locked by the allocator's escrowed allocations. This is the TruNEAR the allocated
+    /// amounts were worth when their rewards were last distributed, so it includes the undistributed rewards.
+    pub(crate) fn internal_locked_shares(&self, allocator: &AccountId) -> u128 {
+        let (Some(recipients), Some(user_allocations)) = (
+            self.escrowed_allocations.get(allocator),
+            self.allocations.get(allocator),
+        ) else {
+            return 0;
+        };
+
+        recipients
+            .iter()
+            .filter_map(|recipient| user_allocations.get(recipient))
+            .map(|allocation| {
+                allocation
+                    .share_price
+                    .convert_to_shares(allocation.near_amount, Rounding::Down)
+            })
+            .sum()
+    }
+
+    /// Returns the sum of the basis points allocated by the allocator's percentage allocations.
+    pub(crate) fn internal_total_allocated_basis_points(&self, allocator: &AccountId) -> u16 {
+        self.allocation_percentages
+            .get(allocator)
+            .map_or(0, |user_percentages| user_percentages.values().sum())
+    }
+
+    /// Returns the allocation with its NEAR amount. For percentage allocations this is the allocated share
+    /// of the allocator's TruNEAR balance as of its last checkpoint, valued at the allocation share price.
+    pub(crate) fn internal_resolve_allocation(
+        &self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+        allocation: &Allocation,
+    ) -> Allocation {
+        match self.internal_allocation_basis_points(allocator, recipient) {
+            None => *allocation,
+            Some(_) => {
+                let allocated_shares = self
+                    .percentage_allocation_shares
+                    .get(&(allocator.clone(), recipient.clone()))
+                    .copied()
+                    .unwrap_or(0);
+                Allocation {
+                    near_amount: allocation
+                        .share_price
+                        .convert_to_assets(allocated_shares, Rounding::Down),
+                    share_price: allocation.share_price,
+                }
+            }
+        }
+    }
+
+    /// Moves the TruNEAR allocated by a percentage allocation to the given basis points of the allocator's
+    /// current balance. Added TruNEAR is allocated at the current share price, so it only earns rewards
+    /// from now on, while removed TruNEAR gives up its part of the accrued rewards, as when deallocating.
+    /// Expired allocations no longer follow the allocator's balance.
+    pub(crate) fn internal_checkpoint_percentage_allocation(
+        &mut self,
+        allocator: &AccountId,
+        recipient: &AccountId,
+        basis_points: u16,
+    ) {
+        if self.internal_is_expired_allocation(allocator, recipient) {
+            return;
+        }
+        let Some(allocation) = self.internal_allocation(allocator, recipient) else {
+            return;
+        };
+
+        let key = (allocator.clone(), recipient.clone());
+        let checkpointed_shares = self
+            .percentage_allocation_shares
+            .get(&key)
+            .copied()
+            .unwrap_or(0);
+        let allocated_shares = self.token.ft_balance_of(allocator.clone()).0
+            * (basis_points as u128)
+            / (FEE_PRECISION as u128);
+        if allocated_shares == checkpointed_shares {
+            return;
+        }
+
+        if allocated_shares > checkpointed_shares {
+            let global_share_price = self.current_share_price();
+            let existing = self.internal_resolve_allocation(allocator, recipient, &allocation);
+            let added_amount = global_share_price
+                .convert_to_assets(allocated_shares - checkpointed_shares, Rounding::Down);
+            let share_price = if existing.near_amount + added_amount == 0 {
+                global_share_price
+            } else {
+                Self::calculate_updated_allocation(&existing, added_amount, global_share_price)
+                    .share_price
+            };
+            self.internal_insert_allocation(
+                allocator,
+                recipient,
+                Allocation {
+                    near_amount: 0,
+                    share_price,
+                },
+            );
+        }
+        self.percentage_allocation_shares
+            .insert(key, allocated_shares);
+    }
+
+    /// Checkpoints the percentage allocations of an account after a change to its TruNEAR balance,
+    /// so that rewards are not paid retroactively on TruNEAR added since the allocation share price was set.
+    pub(crate) fn internal_checkpoint_percentage_allocations(&mut self, account: &AccountId) {
+        let Some(user_percentages) = self.allocation_percentages.get(account) else {
+            return;
+        };
+        let user_percentages: Vec<(AccountId, u16)> = user_percentages
+            .iter()
+            .map(|(recipient, basis_points)| (recipient.clone(), *basis_points))
+            .collect();
+        for (recipient, basis_points) in user_percentages {
+            self.internal_checkpoint_percentage_allocation(account, &recipient, basis_points);
+        }
+    }
+
+    /// Returns the amounts of TruNEAR and NEAR required to distribute to the given recipients of the distributor.
+    /// For NEAR distributions, fees are deducted from the required NEAR amount and accounted as required TruNEAR,
+    /// while for TruNEAR distributions the required TruNEAR amount includes the distribution fees.
+    pub(crate) fn internal_distribution_amounts(
+        &self,
+        distributor: &AccountId,
+        recipients: &[AccountId],
+        in_near: bool,
+    ) -> (u128, u128) {
+        let global_price = self.current_share_price();
+
+        let required_shares: u128 = recipients
+            .iter()
+            .filter(|recipient| !self.internal_is_pending_allocation(distributor, recipient))
+            .map(|recipient| {
+                let allocation = self.internal_resolve_allocation(
+                    distributor,
+                    recipient,
+                    &self
+                        .internal_allocation(distributor, recipient)
+                        .expect(ERR_NO_ALLOCATIONS_TO_RECIPIENT),
+                );
+                let accrual_price =
+                    self.internal_accrual_share_price(distributor, recipient, global_price);
+                Self::internal_calculate_distribution_amount(&allocation, accrual_price)
+            })
+            .sum();
+
+        if in_near {
+            let fees = required_shares * (self.internal_distribution_fee(distributor) as u128)
+                / (FEE_PRECISION as u128);
+            let required_near =
+                global_price.convert_to_assets(required_shares - fees, Rounding::Down);
+            (fees, required_near)
+        } else {
+            (required_shares, 0)
+        }
+    }
+
+    /// Calculates the distribution amount for the given allocation. Percentage allocations must be
+    /// resolved with `internal_resolve_allocation` first, and expired allocations must be given the
+    /// share price from `internal_accrual_share_price` so their rewards are capped at expiry.
     pub(crate) fn internal_calculate_distribution_amount(
         allocation: &Allocation,
-        global_share_price_num: U256,
-        global_share_price_denom: U256,
+        global_share_price: SharePrice,
     ) -> u128 {
-        let distribution_amount = mul_div_with_rounding(
-            U256::from(allocation.near_amount),
-            allocation.share_price_denom,
-            allocation.share_price_num / U256::from(SHARE_PRICE_SCALING_FACTOR),
-            false,
-        ) - mul_div_with_rounding(
-            U256::from(allocation.near_amount),
-            global_share_price_denom,
-            global_share_price_num / U256::from(SHARE_PRICE_SCALING_FACTOR),
-            false,
-        );
-
-        distribution_amount.as_u128()
+        let near_amount = U256::from(allocation.near_amount);
+        let allocated_shares = allocation
+            .share_price
+            .checked_to_shares(near_amount, Rounding::Down)
+            .expect(ERR_SHARE_PRICE_OVERFLOW);
+        let current_shares = global_share_price
+            .checked_to_shares(near_amount, Rounding::Down)
+            .expect(ERR_SHARE_PRICE_OVERFLOW);
+
+        // the share price can fall below the allocation share price, in which case there are no rewards
+        allocated_shares.saturating_sub(current_shares).as_u128()
     }
 }
@@ -0,0 +1,139 @@
+use near_sdk::json_types::U64;
+use near_sdk::{env, log, near, require, AccountId, NearToken};
+
+use crate::constants::DEFAULT_DISTRIBUTION_PAGE_SIZE;
+use crate::errors::*;
+use crate::events::Event;
+use crate::whitelist::WhitelistTrait;
+use crate::*;
+
+#[near]
+impl NearStaker {
+    /// Returns the keeper authorized to distribute the distributor's allocation rewards and the distribution interval.
+    pub fn get_distribution_keeper(&self, distributor: AccountId) -> Option<DistributionSchedule> {
+        self.distribution_schedules.get(&distributor).cloned()
+    }
+
+    /// Returns the epoch in which the allocation rewards were last distributed to the recipient.
+    pub fn get_last_distribution_epoch(
+        &self,
+        distributor: AccountId,
+        recipient: AccountId,
+    ) -> Option<U64> {
+        self.last_distribution_epochs
+            .get(&(distributor, recipient))
+            .map(|epoch| U64(*epoch))
+    }
+
+    /// Authorizes a keeper to distribute the caller's allocation rewards in TruNEAR,
+    /// at most once every `interval` epochs per recipient. Replaces any previous keeper.
+    pub fn set_distribution_keeper(&mut self, keeper: AccountId, interval: U64) {
+        self.check_not_paused();
+        self.check_whitelisted();
+        require!(interval.0 > 0, ERR_INVALID_DISTRIBUTION_INTERVAL);
+
+        let distributor = env::predecessor_account_id();
+        self.distribution_schedules.insert(
+            distributor.clone(),
+            DistributionSchedule {
+                keeper: keeper.clone(),
+                interval,
+            },
+        );
+
+        Event::DistributionKeeperSet {
+            user: &distributor,
+            keeper: &keeper,
+            interval: &interval,
+        }
+        .emit();
+    }
+
+    /// Revokes the keeper authorized to distribute the caller's allocation rewards.
+    pub fn remove_distribution_keeper(&mut self) {
+        let distributor = env::predecessor_account_id();
+        let schedule = self
+            .distribution_schedules
+            .remove(&distributor)
+            .expect(ERR_NO_DISTRIBUTION_KEEPER);
+
+        Event::DistributionKeeperRemoved {
+            user: &distributor,
+            keeper: &schedule.keeper,
+        }
+        .emit();
+    }
+
+    /// Distributes the distributor's allocation rewards in TruNEAR to a page of recipients, ordered by account ID,
+    /// starting at `from_recipient` or the first recipient after it. Only callable by the distributor's keeper.
+    /// Recipients that received a distribution less than the distribution interval ago are skipped.
+    pub fn distribute_all_for(
+        &mut self,
+        distributor: AccountId,
+        from_recipient: Option<AccountId>,
+        limit: Option<u32>,
+    ) {
+        self.check_not_paused();
+        self.check_contract_in_sync();
+        self.check_not_locked();
+
+        let keeper = env::predecessor_account_id();
+        let schedule = self
+            .distribution_schedules
+            .get(&distributor)
+            .cloned()
+            .expect(ERR_NO_DISTRIBUTION_KEEPER);
+        require!(schedule.keeper == keeper, ERR_NOT_DISTRIBUTION_KEEPER);
+        require!(
+            self.is_whitelisted(distributor.clone()),
+            ERR_USER_NOT_WHITELISTED
+        );
+
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.allocations.contains_key(&distributor),
+            ERR_NO_ALLOCATIONS
+        );
+        let (recipients, next_recipient) = self.internal_allocation_recipients_from(
+            &distributor,
+            from_recipient.as_ref(),
+            limit.unwrap_or(DEFAULT_DISTRIBUTION_PAGE_SIZE),
+        );
+
+        let current_epoch = env::epoch_height();
+        let global_price = self.current_share_price();
+        let (total_allocated_amount, _, _) = self.get_total_allocated(distributor.clone());
+
+        let mut distributions = 0;
+        for recipient in recipients {
+            let last_epoch =
+                self.get_last_distribution_epoch(distributor.clone(), recipient.clone());
+            if last_epoch.is_some_and(|epoch| current_epoch < epoch.0 + schedule.interval.0) {
+                log!("Distribution interval has not elapsed for {}", recipient);
+                continue;
+            }
+
+            match self.internal_distribute_and_emit(
+                &distributor,
+                &recipient,
+                global_price,
+                false,
+                NearToken::from_near(0),
+                total_allocated_amount,
+            ) {
+                Err(error) => log!("Error distributing rewards: {}", error),
+                Ok(None) => log!("No rewards to distribute to {}", recipient),
+                Ok(Some(_)) => distributions += 1,
+            }
+        }
+
+        Event::DistributedAllFor {
+            user: &distributor,
+            keeper: &keeper,
+            from_recipient: from_recipient.as_ref(),
+            next_recipient: next_recipient.as_ref(),
+            distributions: &distributions,
+        }
+        .emit();
+    }
+}
@@ -1,5 +1,5 @@
 use near_contract_standards::fungible_token::{FungibleToken, FungibleTokenCore};
-use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::store::{IterableMap, IterableSet, LookupMap, LookupSet, TreeMap};
 use near_sdk::{
     env,
     json_types::Base64VecU8,
@@ -9,22 +9,34 @@ use near_sdk::{
 };
 
 use std::collections::HashMap;
+mod campaigns;
+mod claims;
+mod config;
+mod consent;
 mod constants;
+mod distribution_call;
 pub mod errors;
 mod events;
+mod expiry;
 mod external;
+mod fees;
 mod internal;
+mod keepers;
 mod math;
+mod rounding;
+mod strict;
 mod trunear;
 mod types;
+mod unstake_distribution;
 mod upgrade;
 pub mod whitelist;
 
 use crate::constants::*;
 use crate::errors::*;
 use crate::events::Event;
+use crate::math::{Rounding, SharePrice};
 use crate::types::*;
-use crate::upgrade::VersionedNearStaker;
+use crate::upgrade::{NearStakerV1, VersionedNearStaker};
 
 // Define the contract structure
 #[near(contract_state)]
@@ -47,6 +59,12 @@ pub struct NearStaker {
     pub fee: u16,
     /// The treasury distribution fee charged upon distributing allocation rewards.
     pub distribution_fee: u16,
+    /// The annual treasury management fee charged on the total staked.
+    pub management_fee: u16,
+    /// The block timestamp in nanoseconds up to which the management fee has been accrued.
+    pub management_fee_accrued_at: u64,
+    /// The management fees in NEAR accrued since the last fee collection.
+    accrued_management_fees: u128,
     /// The minimum NEAR amount a user can deposit.
     pub min_deposit: u128,
     /// The delegation pools.
@@ -57,10 +75,50 @@ pub struct NearStaker {
     pub total_staked: u128,
     /// Epoch when total_staked was last updated.
     pub total_staked_last_updated_at: u64,
-    /// Allocations.
-    allocations: LookupMap<AccountId, HashMap<AccountId, Allocation>>,
+    /// The allocations of each allocator, ordered by recipient.
+    allocations: LookupMap<AccountId, TreeMap<AccountId, Allocation>>,
+    /// Allocations made before allocations were stored in per-allocator maps, moved by `migrate_allocations`.
+    legacy_allocations: LookupMap<AccountId, HashMap<AccountId, Allocation>>,
+    /// The totals of the NEAR allocated by each allocator and the TruNEAR it was worth when allocated.
+    allocation_totals: LookupMap<AccountId, AllocationTotals>,
+    /// The basis points of the allocator's TruNEAR position allocated by percentage allocations.
+    allocation_percentages: LookupMap<AccountId, IterableMap<AccountId, u16>>,
+    /// The TruNEAR allocated by each percentage allocation as of the last change to the allocator's balance.
+    percentage_allocation_shares: LookupMap<(AccountId, AccountId), u128>,
+    /// The recipients of the allocator's escrowed allocations, backed by locked TruNEAR.
+    escrowed_allocations: LookupMap<AccountId, IterableSet<AccountId>>,
+    /// The TruNEAR the allocator approved each recipient to claim as allocation rewards.
+    distribution_allowances: LookupMap<(AccountId, AccountId), u128>,
+    /// The keepers authorized to distribute allocation rewards on behalf of distributors.
+    distribution_schedules: LookupMap<AccountId, DistributionSchedule>,
+    /// The epoch of the last distribution of each allocation.
+    last_distribution_epochs: LookupMap<(AccountId, AccountId), u64>,
+    /// The rewards distributed over the lifetime of each allocation.
+    distribution_ledgers: LookupMap<(AccountId, AccountId), DistributionLedger>,
+    /// The recipient from which each distributor's next batch distribution continues.
+    distribution_cursors: LookupMap<AccountId, AccountId>,
+    /// The distributors allocating to each recipient.
+    allocations_to: LookupMap<AccountId, IterableSet<AccountId>>,
+    /// The storage deposit paid for each allocation, refunded when the allocation is removed.
+    allocation_storage_deposits: LookupMap<(AccountId, AccountId), u128>,
+    /// Whether new allocations to recipients that aren't whitelisted stay pending until the recipient accepts them.
+    recipient_consent_required: bool,
+    /// The allocations waiting for their recipient to accept them.
+    pending_allocations: LookupSet<(AccountId, AccountId)>,
+    /// The allocators that opted into strict allocation mode, whose allocations must be covered by their balance.
+    strict_allocators: LookupSet<AccountId>,
+    /// The epoch from which each allocation stops accruing rewards.
+    allocation_expiries: LookupMap<(AccountId, AccountId), u64>,
+    /// The share price recorded before the first total staked update at or after each allocation expiry epoch is applied.
+    expiry_share_prices: LookupMap<u64, SharePrice>,
+    /// The number of allocations expiring at each epoch, so the recorded share price is removed with the last one.
+    expiry_allocation_counts: LookupMap<u64, u32>,
+    /// The allocation expiry epochs whose share price is yet to be recorded.
+    pending_expiry_epochs: TreeMap<u64, ()>,
     /// Unstake requests.
     unstake_requests: LookupMap<u128, UnstakeRequest>,
+    /// The storage deposit paid for each unstake request, refunded when the request is withdrawn.
+    unstake_storage_deposits: LookupMap<u128, u128>,
     /// The most recent unstake nonce.
     pub unstake_nonce: u128,
     /// Total amount of NEAR staked in the staker for which no fees are charged/have already been charged.
@@ -71,6 +129,28 @@ pub struct NearStaker {
     token: FungibleToken,
     /// Reentrancy flag when contract is in the middle of a cross-contract call.
     is_locked: bool,
+    /// Reward campaigns that are streaming NEAR into the total staked.
+    reward_campaigns: HashMap<u64, RewardCampaign>,
+    /// The most recent reward campaign nonce.
+    pub reward_campaign_nonce: u64,
+    /// Proposed fee increases and config loosenings waiting for their timelock to expire.
+    pending_config_changes: Vec<PendingConfigChange>,
+    /// The number of epochs before a proposed config change can be executed.
+    pub config_change_delay: u64,
+    /// The maximum increase of the fee or distribution fee in a single change.
+    pub max_fee_increase: u16,
+    /// Whether fees are collected automatically after each successful total staked update.
+    pub auto_collect_fees: bool,
+    /// The beneficiaries that collected fees are split across. Fees go to the treasury if empty.
+    fee_split: Vec<FeeBeneficiary>,
+    /// Fees charged to specific accounts instead of the global fees.
+    fee_overrides: HashMap<AccountId, FeeOverride>,
+    /// The cumulative rounding subsidy per pool.
+    rounding_subsidies: HashMap<AccountId, RoundingSubsidy>,
+    /// The NEAR designated to cover rounding subsidies.
+    rounding_reserve: u128,
+    /// The remaining reserve below which a low reserve event is emitted.
+    pub rounding_reserve_threshold: u128,
 }
 
 #[near(serializers = [borsh])]
@@ -123,11 +203,34 @@ impl NearStaker {
             is_paused: false,
             fee: 0,
             distribution_fee: 0,
+            management_fee: 0,
+            management_fee_accrued_at: env::block_timestamp(),
+            accrued_management_fees: 0,
             min_deposit: ONE_NEAR,
             delegation_pools,
             delegation_pools_list: vec![default_delegation_pool],
-            allocations: LookupMap::new(b"a".to_vec()),
+            allocations: LookupMap::new(b"n".to_vec()),
+            legacy_allocations: LookupMap::new(b"a".to_vec()),
+            allocation_totals: LookupMap::new(b"z".to_vec()),
+            allocation_percentages: LookupMap::new(b"p".to_vec()),
+            percentage_allocation_shares: LookupMap::new(b"h".to_vec()),
+            escrowed_allocations: LookupMap::new(b"e".to_vec()),
+            distribution_allowances: LookupMap::new(b"d".to_vec()),
+            distribution_schedules: LookupMap::new(b"k".to_vec()),
+            last_distribution_epochs: LookupMap::new(b"l".to_vec()),
+            distribution_ledgers: LookupMap::new(b"g".to_vec()),
+            distribution_cursors: LookupMap::new(b"c".to_vec()),
+            allocations_to: LookupMap::new(b"r".to_vec()),
+            allocation_storage_deposits: LookupMap::new(b"b".to_vec()),
+            recipient_consent_required: true,
+            pending_allocations: LookupSet::new(b"q".to_vec()),
+            strict_allocators: LookupSet::new(b"m".to_vec()),
+            allocation_expiries: LookupMap::new(b"x".to_vec()),
+            expiry_share_prices: LookupMap::new(b"s".to_vec()),
+            expiry_allocation_counts: LookupMap::new(b"E".to_vec()),
+            pending_expiry_epochs: TreeMap::new(b"P".to_vec()),
             unstake_requests: LookupMap::new(b"u".to_vec()),
+            unstake_storage_deposits: LookupMap::new(b"v".to_vec()),
             unstake_nonce: 0,
             total_staked: 0,
             total_staked_last_updated_at: env::epoch_height(),
@@ -135,6 +238,17 @@ impl NearStaker {
             tax_exempt_stake: 0,
             withdrawn_amount: 0,
             is_locked: false,
+            reward_campaigns: HashMap::new(),
+            reward_campaign_nonce: 0,
+            pending_config_changes: vec![],
+            config_change_delay: DEFAULT_CONFIG_CHANGE_DELAY,
+            max_fee_increase: DEFAULT_MAX_FEE_INCREASE,
+            auto_collect_fees: false,
+            fee_split: vec![],
+            fee_overrides: HashMap::new(),
+            rounding_subsidies: HashMap::new(),
+            rounding_reserve: 0,
+            rounding_reserve_threshold: 0,
         }
     }
 
@@ -199,7 +313,7 @@ impl NearStaker {
         self.unstake_nonce.into()
     }
 
-    /// Returns the allocation and unstake storage cost
+    /// Returns the unstake storage cost
     pub fn get_storage_cost() -> U128 {
         env::storage_byte_cost()
             .saturating_mul(STORAGE_BYTES)
@@ -207,50 +321,169 @@ impl NearStaker {
             .into()
     }
 
-    /// Returns all allocations for a given user.
-    pub fn get_allocations(&self, allocator: AccountId) -> Vec<AllocationInfo> {
-        self.allocations
-            .get(&allocator)
-            .expect(ERR_NO_ALLOCATIONS)
+    /// Returns the storage cost of a new allocation
+    pub fn get_allocation_storage_cost() -> U128 {
+        env::storage_byte_cost()
+            .saturating_mul(ALLOCATION_STORAGE_BYTES)
+            .as_yoctonear()
+            .into()
+    }
+
+    /// Returns a page of the allocations made by a given user, ordered by recipient.
+    pub fn get_allocations(
+        &self,
+        allocator: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<AllocationInfo> {
+        self.internal_allocations_page(&allocator, from_index, limit)
             .iter()
-            .map(|(recipient, allocation)| AllocationInfo {
-                recipient: recipient.clone(),
-                near_amount: allocation.near_amount.into(),
-                share_price_num: allocation.share_price_num.to_string(),
-                share_price_denom: allocation.share_price_denom.to_string(),
+            .map(|(recipient, allocation)| {
+                let ledger = self.internal_distribution_ledger(&allocator, recipient);
+                AllocationInfo {
+                    recipient: recipient.clone(),
+                    near_amount: self
+                        .internal_resolve_allocation(&allocator, recipient, allocation)
+                        .near_amount
+                        .into(),
+                    share_price_num: allocation.share_price.num.to_string(),
+                    share_price_denom: allocation.share_price.denom.to_string(),
+                    basis_points: self.internal_allocation_basis_points(&allocator, recipient),
+                    escrowed: self.internal_is_escrowed_allocation(&allocator, recipient),
+                    pending: self.internal_is_pending_allocation(&allocator, recipient),
+                    expires_at_epoch: self
+                        .internal_allocation_expiry(&allocator, recipient)
+                        .map(U64),
+                    shares_distributed: ledger.shares_distributed.into(),
+                    near_distributed: ledger.near_distributed.into(),
+                    fees_paid: ledger.fees_paid.into(),
+                    last_distribution_epoch: self
+                        .get_last_distribution_epoch(allocator.clone(), recipient.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a page of the allocations made to a given recipient, ordered by distributor, with the
+    /// TruNEAR rewards the recipient would receive if they were distributed now.
+    pub fn get_allocations_to(
+        &self,
+        recipient: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<RecipientAllocationInfo> {
+        let Some(distributors) = self.allocations_to.get(&recipient) else {
+            return vec![];
+        };
+
+        let global_price = self.current_share_price();
+        let mut distributors: Vec<&AccountId> = distributors.iter().collect();
+        distributors.sort();
+        distributors
+            .into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .filter_map(|distributor| {
+                // allocations are read from the previous storage layout until they are migrated, and
+                // distributors whose allocation was removed are skipped
+                let stored_allocation = self.internal_allocation(distributor, &recipient)?;
+                let allocation =
+                    self.internal_resolve_allocation(distributor, &recipient, &stored_allocation);
+                let accrual_price =
+                    self.internal_accrual_share_price(distributor, &recipient, global_price);
+                let shares =
+                    Self::internal_calculate_distribution_amount(&allocation, accrual_price);
+                let fees = shares * (self.internal_distribution_fee(distributor) as u128)
+                    / (FEE_PRECISION as u128);
+                Some(RecipientAllocationInfo {
+                    distributor: distributor.clone(),
+                    near_amount: allocation.near_amount.into(),
+                    pending_rewards: (shares - fees).into(),
+                    basis_points: self.internal_allocation_basis_points(distributor, &recipient),
+                    escrowed: self.internal_is_escrowed_allocation(distributor, &recipient),
+                    pending: self.internal_is_pending_allocation(distributor, &recipient),
+                    expires_at_epoch: self
+                        .internal_allocation_expiry(distributor, &recipient)
+                        .map(U64),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns a page of the rewards pending distribution to the recipients of a distributor, ordered by recipient,
+    /// with the distribution fee and the NEAR value of the net rewards at the current share price.
+    pub fn get_pending_rewards(
+        &self,
+        distributor: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<PendingRewardsInfo> {
+        let global_price = self.current_share_price();
+        let distribution_fee = self.internal_distribution_fee(&distributor);
+        self.internal_allocations_page(&distributor, from_index, limit)
+            .iter()
+            .map(|(recipient, allocation)| {
+                let allocation =
+                    self.internal_resolve_allocation(&distributor, recipient, allocation);
+                let accrual_price =
+                    self.internal_accrual_share_price(&distributor, recipient, global_price);
+                let shares =
+                    Self::internal_calculate_distribution_amount(&allocation, accrual_price);
+                let fees = shares * (distribution_fee as u128) / (FEE_PRECISION as u128);
+                PendingRewardsInfo {
+                    recipient: recipient.clone(),
+                    shares: shares.into(),
+                    fees: fees.into(),
+                    net_shares: (shares - fees).into(),
+                    near_amount: global_price
+                        .convert_to_assets(shares - fees, Rounding::Down)
+                        .into(),
+                }
             })
             .collect()
     }
 
     /// Returns the total amount of NEAR allocated by a user and their average allocation share price.
     pub fn get_total_allocated(&self, allocator: AccountId) -> (U128, String, String) {
-        let total_allocation = match self.allocations.get(&allocator) {
-            Some(user_allocations) => {
-                user_allocations
-                    .iter()
-                    .map(|(_, allocation)| allocation)
-                    .fold(Allocation::default(), |acc, allocation| {
-                        // this can only be true for the first iteration
-                        if acc.near_amount == 0 {
-                            *allocation
-                        } else {
-                            Self::calculate_updated_allocation(
-                                &acc,
-                                allocation.near_amount,
-                                allocation.share_price_num,
-                                allocation.share_price_denom,
-                            )
-                        }
-                    })
+        // fixed allocations are kept in running totals, while percentage allocations depend on the
+        // allocator's current balance and are resolved here
+        let totals = self.internal_allocation_totals(&allocator);
+        let fixed_allocation = if totals.near_amount == 0 {
+            Allocation::default()
+        } else {
+            Allocation {
+                near_amount: totals.near_amount,
+                share_price: SharePrice::from_assets_and_shares(totals.near_amount, totals.shares),
             }
-
-            None => Allocation::default(),
         };
+        let total_allocation = self
+            .allocation_percentages
+            .get(&allocator)
+            .into_iter()
+            .flat_map(|user_percentages| user_percentages.keys())
+            .filter_map(|recipient| {
+                self.internal_allocation(&allocator, recipient)
+                    .map(|allocation| {
+                        self.internal_resolve_allocation(&allocator, recipient, &allocation)
+                    })
+            })
+            .fold(fixed_allocation, |acc, allocation| {
+                if acc.near_amount == 0 {
+                    allocation
+                } else {
+                    Self::calculate_updated_allocation(
+                        &acc,
+                        allocation.near_amount,
+                        allocation.share_price,
+                    )
+                }
+            });
 
+        let (share_price_num, share_price_denom) = total_allocation.share_price.to_strings();
         (
             total_allocation.near_amount.into(),
-            total_allocation.share_price_num.to_string(),
-            total_allocation.share_price_denom.to_string(),
+            share_price_num,
+            share_price_denom,
         )
     }
 
@@ -262,62 +495,35 @@ impl NearStaker {
         recipient: Option<AccountId>,
         in_near: bool,
     ) -> (U128, U128) {
-        let user_allocations = self.allocations.get(distributor);
-
         // if the distributor has no allocations no TruNEAR or NEAR is needed
-        if user_allocations.is_none() {
+        let user_allocations = self.internal_allocations_page(distributor, None, None);
+        if user_allocations.is_empty() {
             return (U128(0), U128(0));
-        };
+        }
 
-        let (global_price_num, global_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
-        );
+        let recipients: Vec<AccountId> = match recipient {
+            Some(r) => {
+                require!(
+                    self.internal_allocation(distributor, &r).is_some(),
+                    ERR_NO_ALLOCATIONS_TO_RECIPIENT
+                );
+                vec![r]
+            }
+            None => user_allocations
+                .into_iter()
+                .map(|(recipient, _)| recipient)
+                .collect(),
+        };
 
-        let required_shares;
-        if let Some(r) = recipient {
-            // calculate the amount of shares required to distribute to a single recipient
-            let allocation = user_allocations
-                .unwrap()
-                .get(&r)
-                .expect(ERR_NO_ALLOCATIONS_TO_RECIPIENT);
-
-            required_shares = Self::internal_calculate_distribution_amount(
-                allocation,
-                global_price_num,
-                global_price_denom,
-            );
-        } else {
-            // calculate the amount of shares required to distribute to all recipients
-            required_shares = user_allocations
-                .unwrap()
-                .iter()
-                .map(|(_, allocation)| allocation)
-                .fold(0, |acc, a| {
-                    acc + Self::internal_calculate_distribution_amount(
-                        a,
-                        global_price_num,
-                        global_price_denom,
-                    )
-                });
-        }
+        let (required_shares, required_near) =
+            self.internal_distribution_amounts(distributor, &recipients, in_near);
+        (U128(required_shares), U128(required_near))
+    }
 
-        if in_near {
-            // for NEAR distributions fees are deducted from the required NEAR amount and accounted as required TruNEAR
-            let fees = required_shares * (self.distribution_fee as u128) / (FEE_PRECISION as u128);
-            let required_near = Self::convert_to_assets(
-                required_shares - fees,
-                global_price_num,
-                global_price_denom,
-                false,
-            );
-            (U128::from(fees), U128::from(required_near))
-        } else {
-            // for TruNEAR distributions the required TruNEAR amount includes the distribution fees
-            (U128::from(required_shares), U128(0))
-        }
+    /// Returns the recipient from which the next `distribute_batch` call of the distributor continues,
+    /// or `None` if it starts from the first recipient.
+    pub fn get_distribution_cursor(&self, distributor: AccountId) -> Option<AccountId> {
+        self.distribution_cursors.get(&distributor).cloned()
     }
 
     /// Returns some of the Staker internal state
@@ -328,6 +534,7 @@ impl NearStaker {
             default_delegation_pool: self.default_delegation_pool.clone(),
             fee: self.fee,
             dist_fee: self.distribution_fee,
+            management_fee: self.management_fee,
             min_deposit: U128::from(self.min_deposit),
             is_paused: self.is_paused,
             current_epoch: env::epoch_height().into(),
@@ -336,31 +543,27 @@ impl NearStaker {
 
     /// Returns the current TruNEAR share price in NEAR.
     pub fn share_price(&self) -> (String, String) {
-        let (num, denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
-        );
-
-        (num.to_string(), denom.to_string())
+        self.current_share_price().to_strings()
     }
 
     /// Returns the maximum amount of NEAR a user can withdraw from the vault, rounding the result up.
     pub fn max_withdraw(&self, account_id: AccountId) -> U128 {
-        let (share_price_num, share_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
-        );
-        let shares_balance = self.ft_balance_of(account_id).0;
-        let assets =
-            Self::convert_to_assets(shares_balance, share_price_num, share_price_denom, true);
+        let shares_balance = self
+            .ft_balance_of(account_id.clone())
+            .0
+            .saturating_sub(self.internal_locked_shares(&account_id));
+        let assets = self
+            .current_share_price()
+            .convert_to_assets(shares_balance, Rounding::Up);
 
         U128(assets)
     }
 
+    /// Returns the TruNEAR locked by the account's escrowed allocations, which cannot be transferred or unstaked.
+    pub fn get_locked_balance(&self, account_id: AccountId) -> U128 {
+        self.internal_locked_shares(&account_id).into()
+    }
+
     /// Returns whether the contract is locked.
     pub fn get_is_locked(&self) -> bool {
         self.is_locked
@@ -422,28 +625,28 @@ impl NearStaker {
         }
     }
 
-    /// Sets the treasury fee charged on rewards.
+    /// Sets the treasury fee charged on rewards. Fee increases are proposed and can be executed
+    /// after the config change delay.
     pub fn set_fee(&mut self, new_fee: u16) {
         self.check_owner();
         require!(new_fee < FEE_PRECISION, ERR_FEE_TOO_LARGE);
-        Event::SetFeeEvent {
-            old_fee: &self.fee,
-            new_fee: &new_fee,
-        }
-        .emit();
-        self.fee = new_fee;
+        self.internal_change_config(ConfigKey::FEE, new_fee as u64);
+    }
+
+    /// Sets the annual treasury management fee charged on the total staked. Fee increases are proposed
+    /// and can be executed after the config change delay.
+    pub fn set_management_fee(&mut self, new_management_fee: u16) {
+        self.check_owner();
+        require!(new_management_fee < FEE_PRECISION, ERR_FEE_TOO_LARGE);
+        self.internal_change_config(ConfigKey::MANAGEMENT_FEE, new_management_fee as u64);
     }
 
-    /// Sets the treasury fee charged on rewards distribution.
+    /// Sets the treasury fee charged on rewards distribution. Fee increases are proposed and can be
+    /// executed after the config change delay.
     pub fn set_distribution_fee(&mut self, new_distribution_fee: u16) {
         self.check_owner();
         require!(new_distribution_fee < FEE_PRECISION, ERR_FEE_TOO_LARGE);
-        Event::SetDistributionFeeEvent {
-            old_distribution_fee: &self.distribution_fee,
-            new_distribution_fee: &new_distribution_fee,
-        }
-        .emit();
-        self.distribution_fee = new_distribution_fee;
+        self.internal_change_config(ConfigKey::DISTRIBUTION_FEE, new_distribution_fee as u64);
     }
 
     /// Sets a given pool as the new default delegation pool.
@@ -576,10 +779,13 @@ impl NearStaker {
         self.check_not_paused();
         self.check_not_locked();
         self.is_locked = true;
-        self.internal_update_stake().then(
+
+        let release_epoch = env::epoch_height();
+        let released_rewards = self.internal_releasable_campaign_rewards(release_epoch);
+        self.internal_update_stake(released_rewards).then(
             Self::ext(env::current_account_id())
                 .with_static_gas(XCC_GAS)
-                .total_staked_callback(),
+                .total_staked_callback(U128(released_rewards), release_epoch),
         )
     }
 
@@ -625,6 +831,24 @@ impl NearStaker {
         )
     }
 
+    #[payable]
+    /// Donates NEAR to a specific pool as rewards for all TruNEAR holders. No TruNEAR is minted for the donation.
+    /// Donations are added to the tax exempt stake, so no treasury fee is charged on them.
+    pub fn donate(&mut self, pool_id: AccountId) -> Promise {
+        self.check_not_paused();
+        self.check_not_locked();
+        self.is_locked = true;
+
+        self.check_owner_or_whitelisted();
+
+        let amount = env::attached_deposit().as_yoctonear();
+        self.check_pool(pool_id.clone());
+        self.check_min_deposit_amount(amount);
+        self.check_contract_in_sync();
+
+        Self::send_donation_promises(pool_id, amount, env::predecessor_account_id())
+    }
+
     /// Unstakes NEAR from default pool.
     #[payable]
     pub fn unstake(&mut self, amount: U128) -> Promise {
@@ -669,71 +893,71 @@ impl NearStaker {
 
         let allocator = env::predecessor_account_id();
         let amount = amount.0;
+        self.internal_migrate_allocations(&allocator);
 
         require!(recipient != allocator, ERR_INVALID_RECIPIENT);
         require!(amount >= ONE_NEAR, ERR_ALLOCATION_UNDER_ONE_NEAR);
+        require!(
+            self.internal_allocation_basis_points(&allocator, &recipient)
+                .is_none()
+                && !self.internal_is_escrowed_allocation(&allocator, &recipient),
+            ERR_ALLOCATION_TYPE_MISMATCH
+        );
+
+        self.internal_allocate(allocator, recipient, amount);
+    }
 
-        let (global_share_price_num, global_share_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
+    /// Allocates NEAR staking rewards to a recipient and locks the allocator's TruNEAR backing the allocation,
+    /// so the rewards on the locked TruNEAR are guaranteed to the recipient. Locked TruNEAR cannot be transferred
+    /// or unstaked and is released when deallocating. Requires a storage deposit for new allocations that is
+    /// refunded upon deallocation.
+    #[payable]
+    pub fn allocate_escrowed(&mut self, recipient: AccountId, amount: U128) {
+        self.check_not_paused();
+        self.check_whitelisted();
+        self.check_contract_in_sync();
+        self.check_not_locked();
+
+        let allocator = env::predecessor_account_id();
+        let amount = amount.0;
+        self.internal_migrate_allocations(&allocator);
+
+        require!(recipient != allocator, ERR_INVALID_RECIPIENT);
+        require!(amount >= ONE_NEAR, ERR_ALLOCATION_UNDER_ONE_NEAR);
+        let has_allocation = self.internal_allocation(&allocator, &recipient).is_some();
+        require!(
+            !has_allocation || self.internal_is_escrowed_allocation(&allocator, &recipient),
+            ERR_ALLOCATION_TYPE_MISMATCH
         );
 
-        let mut storage_cost = NearToken::from_near(0);
-        let attached_deposit = env::attached_deposit();
+        // the allocator must hold enough unlocked TruNEAR to back the allocated amount
+        let locked_shares_before = self.internal_locked_shares(&allocator);
+        let shares = self
+            .current_share_price()
+            .convert_to_shares(amount, Rounding::Up);
+        require!(
+            shares
+                <= self
+                    .ft_balance_of(allocator.clone())
+                    .0
+                    .saturating_sub(locked_shares_before),
+            ERR_INSUFFICIENT_UNLOCKED_TRUNEAR_BALANCE
+        );
 
-        let allocation = self
-            .allocations
+        self.escrowed_allocations
             .entry(allocator.clone())
-            .or_default() // fetches the users allocations or creates a new hashmap
-            .entry(recipient.clone()) //fetches the allocation to the recipient
-            .and_modify(|allocation| {
-                //updates the recipients allocation if it exists
-                *allocation = Self::calculate_updated_allocation(
-                    allocation,
-                    amount,
-                    global_share_price_num,
-                    global_share_price_denom,
-                )
-            })
             .or_insert_with(|| {
-                storage_cost = NearToken::from_yoctonear(Self::get_storage_cost().0);
-                if attached_deposit < storage_cost {
-                    env::panic_str(ERR_STORAGE_DEPOSIT_TOO_SMALL);
-                }
-                Allocation {
-                    // inserts a new allocation if one doesn't exist for this recipient
-                    near_amount: amount,
-                    share_price_num: global_share_price_num,
-                    share_price_denom: global_share_price_denom,
-                }
-            });
-
-        let updated_allocation = *allocation;
-        let (
-            total_allocated_amount,
-            total_allocated_share_price_num,
-            total_allocated_share_price_denom,
-        ) = self.get_total_allocated(allocator.clone());
-
-        // refund any excess NEAR to allocator
-        if attached_deposit > storage_cost {
-            Promise::new(allocator.clone())
-                .transfer(attached_deposit.checked_sub(storage_cost).unwrap());
-        }
+                IterableSet::new([b"f".as_slice(), &env::sha256(allocator.as_bytes())].concat())
+            })
+            .insert(recipient.clone());
+        self.internal_allocate(allocator.clone(), recipient.clone(), amount);
 
-        // emit event
-        Event::AllocatedEvent {
+        let locked_shares = self.internal_locked_shares(&allocator);
+        Event::SharesLocked {
             user: &allocator,
             recipient: &recipient,
-            amount: &amount.into(),
-            total_amount: &updated_allocation.near_amount.into(),
-            share_price_num: &updated_allocation.share_price_num.to_string(),
-            share_price_denom: &updated_allocation.share_price_denom.to_string(),
-            total_allocated_amount: &total_allocated_amount,
-            total_allocated_share_price_num: &total_allocated_share_price_num,
-            total_allocated_share_price_denom: &total_allocated_share_price_denom,
+            shares: &U128(locked_shares - locked_shares_before),
+            locked_balance: &U128(locked_shares),
         }
         .emit();
     }
@@ -744,13 +968,27 @@ impl NearStaker {
         self.check_whitelisted();
 
         let deallocator = env::predecessor_account_id();
-        let user_allocations = self
-            .allocations
-            .get_mut(&deallocator)
-            .expect(ERR_NO_ALLOCATIONS);
+        self.internal_migrate_allocations(&deallocator);
+        require!(
+            self.internal_allocation_basis_points(&deallocator, &recipient)
+                .is_none(),
+            ERR_ALLOCATION_TYPE_MISMATCH
+        );
+
+        // the rewards on escrowed TruNEAR belong to the recipient, so they are distributed before the
+        // principal is released, unless the recipient hasn't accepted the allocation
+        let escrowed = self.internal_is_escrowed_allocation(&deallocator, &recipient);
+        if escrowed && !self.internal_is_pending_allocation(&deallocator, &recipient) {
+            self.internal_settle_rewards(&deallocator, &recipient);
+        }
+        let locked_shares_before = self.internal_locked_shares(&deallocator);
 
-        let allocation = user_allocations
-            .get_mut(&recipient)
+        require!(
+            self.allocations.contains_key(&deallocator),
+            ERR_NO_ALLOCATIONS
+        );
+        let mut allocation = self
+            .internal_allocation(&deallocator, &recipient)
             .expect(ERR_NO_ALLOCATIONS_TO_RECIPIENT);
 
         require!(
@@ -759,17 +997,15 @@ impl NearStaker {
         );
 
         let remaining_amount = allocation.near_amount - amount.0;
-        let share_price_num = allocation.share_price_num;
-        let share_price_denom = allocation.share_price_denom;
+        let share_price = allocation.share_price;
 
         if remaining_amount == 0 {
-            user_allocations.remove(&recipient);
-            // refund the storage cost to the deallocator
-            Promise::new(deallocator.clone())
-                .transfer(NearToken::from_yoctonear(Self::get_storage_cost().0));
+            self.internal_remove_allocation(&deallocator, &recipient);
+            self.internal_refund_allocation_storage(&deallocator, &recipient);
         } else {
             require!(remaining_amount >= ONE_NEAR, ERR_ALLOCATION_UNDER_ONE_NEAR);
             allocation.near_amount = remaining_amount;
+            self.internal_insert_allocation(&deallocator, &recipient, allocation);
         }
 
         let (
@@ -784,50 +1020,334 @@ impl NearStaker {
             recipient: &recipient,
             amount: &amount,
             total_amount: &remaining_amount.into(),
-            share_price_num: &share_price_num.to_string(),
-            share_price_denom: &share_price_denom.to_string(),
+            share_price_num: &share_price.num.to_string(),
+            share_price_denom: &share_price.denom.to_string(),
             total_allocated_amount: &total_allocated_amount,
             total_allocated_share_price_num: &total_allocated_share_price_num,
             total_allocated_share_price_denom: &total_allocated_share_price_denom,
         }
         .emit();
+
+        if remaining_amount == 0 {
+            self.internal_clear_allocation_state(&deallocator, &recipient);
+        }
+
+        if escrowed {
+            if remaining_amount == 0 {
+                self.escrowed_allocations
+                    .get_mut(&deallocator)
+                    .unwrap()
+                    .remove(&recipient);
+            }
+
+            let locked_shares = self.internal_locked_shares(&deallocator);
+            Event::SharesUnlocked {
+                user: &deallocator,
+                recipient: &recipient,
+                shares: &U128(locked_shares_before - locked_shares),
+                locked_balance: &U128(locked_shares),
+            }
+            .emit();
+        }
     }
 
+    /// Allocates a percentage of the staking rewards earned by the caller's TruNEAR position to a recipient.
+    /// The allocated amount follows the caller's TruNEAR balance, and the percentages allocated to all
+    /// recipients cannot exceed 100%. Requires a storage deposit for new allocations that is refunded upon deallocation.
     #[payable]
-    /// Distributes NEAR staking rewards to a recipient. When distributing rewards in NEAR, the distributor must attach the full amount.
-    pub fn distribute_rewards(&mut self, recipient: AccountId, in_near: bool) {
+    pub fn allocate_percentage(&mut self, recipient: AccountId, basis_points: u16) {
         self.check_not_paused();
         self.check_whitelisted();
         self.check_contract_in_sync();
         self.check_not_locked();
 
-        let distributor = env::predecessor_account_id();
+        let allocator = env::predecessor_account_id();
+        self.internal_migrate_allocations(&allocator);
+
+        require!(recipient != allocator, ERR_INVALID_RECIPIENT);
+        require!(
+            basis_points > 0 && basis_points <= FEE_PRECISION,
+            ERR_INVALID_ALLOCATION_PERCENTAGE
+        );
+
+        let existing_allocation = self.internal_allocation(&allocator, &recipient);
+        let existing_basis_points = self.internal_allocation_basis_points(&allocator, &recipient);
+        require!(
+            existing_allocation.is_none() || existing_basis_points.is_some(),
+            ERR_ALLOCATION_TYPE_MISMATCH
+        );
+        require!(
+            !self.internal_is_expired_allocation(&allocator, &recipient),
+            ERR_ALLOCATION_EXPIRED
+        );
+
+        let total_basis_points = existing_basis_points.unwrap_or(0) + basis_points;
+        require!(
+            self.internal_total_allocated_basis_points(&allocator) + basis_points <= FEE_PRECISION,
+            ERR_ALLOCATION_PERCENTAGE_EXCEEDED
+        );
+
+        let attached_deposit = env::attached_deposit();
+
+        let storage_cost = match existing_allocation {
+            Some(_) => NearToken::from_near(0),
+            None => {
+                let storage_cost = NearToken::from_yoctonear(Self::get_allocation_storage_cost().0);
+                require!(
+                    attached_deposit >= storage_cost,
+                    ERR_STORAGE_DEPOSIT_TOO_SMALL
+                );
+                self.internal_request_consent(&allocator, &recipient);
+                self.allocation_storage_deposits.insert(
+                    (allocator.clone(), recipient.clone()),
+                    storage_cost.as_yoctonear(),
+                );
+                self.internal_insert_allocation(
+                    &allocator,
+                    &recipient,
+                    Allocation {
+                        near_amount: 0,
+                        share_price: self.current_share_price(),
+                    },
+                );
+                self.internal_index_allocation(&allocator, &recipient);
+                storage_cost
+            }
+        };
 
+        // the added percentage is allocated at the current share price, so the rewards
+        // accrued on the existing percentage are preserved
+        self.allocation_percentages
+            .entry(allocator.clone())
+            .or_insert_with(|| {
+                IterableMap::new([b"j".as_slice(), &env::sha256(allocator.as_bytes())].concat())
+            })
+            .insert(recipient.clone(), total_basis_points);
+        self.internal_checkpoint_percentage_allocation(&allocator, &recipient, total_basis_points);
+        let allocation = self.internal_allocation(&allocator, &recipient).unwrap();
+        self.check_allocation_coverage(&allocator, 0);
+
+        // refund any excess NEAR to allocator
+        if attached_deposit > storage_cost {
+            Promise::new(allocator.clone())
+                .transfer(attached_deposit.checked_sub(storage_cost).unwrap());
+        }
+
+        Event::AllocatedPercentage {
+            user: &allocator,
+            recipient: &recipient,
+            basis_points: &basis_points,
+            total_basis_points: &total_basis_points,
+            share_price_num: &allocation.share_price.num.to_string(),
+            share_price_denom: &allocation.share_price.denom.to_string(),
+            total_allocated_basis_points: &self.internal_total_allocated_basis_points(&allocator),
+        }
+        .emit();
+    }
+
+    /// Deallocates a percentage of the staking rewards allocated to a recipient.
+    pub fn deallocate_percentage(&mut self, recipient: AccountId, basis_points: u16) {
+        self.check_not_paused();
+        self.check_whitelisted();
+
+        let deallocator = env::predecessor_account_id();
+        self.internal_migrate_allocations(&deallocator);
+        require!(
+            self.allocations.contains_key(&deallocator),
+            ERR_NO_ALLOCATIONS
+        );
+        let existing_basis_points = self
+            .internal_allocation_basis_points(&deallocator, &recipient)
+            .expect(ERR_NO_ALLOCATIONS_TO_RECIPIENT);
+        require!(
+            basis_points <= existing_basis_points,
+            ERR_EXCESSIVE_DEALLOCATION
+        );
+
+        let remaining_basis_points = existing_basis_points - basis_points;
+        let share_price = self
+            .internal_allocation(&deallocator, &recipient)
+            .unwrap()
+            .share_price;
+
+        let user_percentages = self.allocation_percentages.get_mut(&deallocator).unwrap();
+        if remaining_basis_points == 0 {
+            user_percentages.remove(&recipient);
+            self.internal_clear_allocation_state(&deallocator, &recipient);
+            self.internal_remove_allocation(&deallocator, &recipient);
+            self.internal_refund_allocation_storage(&deallocator, &recipient);
+        } else {
+            user_percentages.insert(recipient.clone(), remaining_basis_points);
+            self.internal_checkpoint_percentage_allocation(
+                &deallocator,
+                &recipient,
+                remaining_basis_points,
+            );
+        }
+
+        Event::DeallocatedPercentage {
+            user: &deallocator,
+            recipient: &recipient,
+            basis_points: &basis_points,
+            total_basis_points: &remaining_basis_points,
+            share_price_num: &share_price.num.to_string(),
+            share_price_denom: &share_price.denom.to_string(),
+            total_allocated_basis_points: &self.internal_total_allocated_basis_points(&deallocator),
+        }
+        .emit();
+    }
+
+    /// Moves an allocation to a new recipient without a new storage deposit. When `settle_first` is set, the
+    /// rewards accrued so far are distributed to the old recipient in TruNEAR, otherwise they carry over to
+    /// the new recipient. Any distribution allowance approved for the old recipient is revoked.
+    /// Returns whether the rewards were settled, as rewards that can't be distributed to the old recipient,
+    /// such as on a pending allocation, carry over instead.
+    pub fn reassign_allocation(
+        &mut self,
+        old_recipient: AccountId,
+        new_recipient: AccountId,
+        settle_first: bool,
+    ) -> bool {
+        self.check_not_paused();
+        self.check_whitelisted();
+
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        require!(new_recipient != distributor, ERR_INVALID_RECIPIENT);
         let user_allocations = self
             .allocations
             .get(&distributor)
             .expect(ERR_NO_ALLOCATIONS);
-
         require!(
-            user_allocations.contains_key(&recipient),
+            user_allocations.contains_key(&old_recipient),
             ERR_NO_ALLOCATIONS_TO_RECIPIENT
         );
+        require!(
+            !user_allocations.contains_key(&new_recipient),
+            ERR_ALLOCATION_ALREADY_EXISTS
+        );
+
+        let settled = settle_first && self.internal_settle_rewards(&distributor, &old_recipient);
+
+        // move the allocation along with its share price, type, expiry and last distribution epoch
+        let last_distribution_epoch =
+            self.get_last_distribution_epoch(distributor.clone(), old_recipient.clone());
+        let expiry = self.internal_allocation_expiry(&distributor, &old_recipient);
+        // the expiry is set on the new recipient first, so the share price recorded for it is kept
+        if let Some(epoch) = expiry {
+            self.internal_set_allocation_expiry(&distributor, &new_recipient, Some(epoch));
+        }
+        let percentage_shares = self
+            .percentage_allocation_shares
+            .get(&(distributor.clone(), old_recipient.clone()))
+            .copied();
+        self.internal_clear_allocation_state(&distributor, &old_recipient);
+
+        let allocation = self
+            .internal_remove_allocation(&distributor, &old_recipient)
+            .unwrap();
+        self.internal_insert_allocation(&distributor, &new_recipient, allocation);
+
+        if let Some(user_percentages) = self.allocation_percentages.get_mut(&distributor) {
+            if let Some(basis_points) = user_percentages.remove(&old_recipient) {
+                user_percentages.insert(new_recipient.clone(), basis_points);
+            }
+        }
+        if let Some(recipients) = self.escrowed_allocations.get_mut(&distributor) {
+            if recipients.remove(&old_recipient) {
+                recipients.insert(new_recipient.clone());
+            }
+        }
+        let key = (distributor.clone(), new_recipient.clone());
+        if let Some(storage_deposit) = self
+            .allocation_storage_deposits
+            .remove(&(distributor.clone(), old_recipient.clone()))
+        {
+            self.allocation_storage_deposits
+                .insert(key.clone(), storage_deposit);
+        }
+        if let Some(epoch) = last_distribution_epoch {
+            self.last_distribution_epochs.insert(key.clone(), epoch.0);
+        }
+        if let Some(shares) = percentage_shares {
+            self.percentage_allocation_shares
+                .insert(key.clone(), shares);
+        }
+        self.internal_index_allocation(&distributor, &new_recipient);
+        self.internal_request_consent(&distributor, &new_recipient);
+
+        let allocation =
+            self.internal_resolve_allocation(&distributor, &new_recipient, &allocation);
+        Event::AllocationReassigned {
+            user: &distributor,
+            old_recipient: &old_recipient,
+            new_recipient: &new_recipient,
+            near_amount: &allocation.near_amount.into(),
+            share_price_num: &allocation.share_price.num.to_string(),
+            share_price_denom: &allocation.share_price.denom.to_string(),
+            settled: &settled,
+        }
+        .emit();
+
+        settled
+    }
+
+    /// Adds the allocations made by a user to the index of allocations by recipient. Allocations made before
+    /// the index was introduced are only returned by `get_allocations_to` once indexed.
+    pub fn index_allocations(&mut self, allocator: AccountId) {
+        if self.internal_migrate_allocations(&allocator) {
+            return;
+        }
+        let recipients: Vec<AccountId> = self
+            .allocations
+            .get(&allocator)
+            .expect(ERR_NO_ALLOCATIONS)
+            .keys()
+            .cloned()
+            .collect();
+        for recipient in recipients {
+            self.internal_index_allocation(&allocator, &recipient);
+        }
+    }
+
+    /// Moves the allocations of the given users made before allocations were stored in per-allocator maps.
+    /// Allocations are also moved the first time their allocator changes or distributes them. Callable by anyone.
+    pub fn migrate_allocations(&mut self, allocators: Vec<AccountId>) {
+        for allocator in allocators {
+            self.internal_migrate_allocations(&allocator);
+        }
+    }
+
+    #[payable]
+    /// Distributes NEAR staking rewards to a recipient. When distributing rewards in NEAR, the distributor must attach the full amount.
+    pub fn distribute_rewards(&mut self, recipient: AccountId, in_near: bool) {
+        self.check_not_paused();
+        self.check_whitelisted();
+        self.check_contract_in_sync();
+        self.check_not_locked();
 
-        let (global_price_num, global_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.total_supply,
-            self.tax_exempt_stake,
-            self.fee,
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+
+        require!(
+            self.allocations.contains_key(&distributor),
+            ERR_NO_ALLOCATIONS
+        );
+        require!(
+            self.internal_allocation(&distributor, &recipient).is_some(),
+            ERR_NO_ALLOCATIONS_TO_RECIPIENT
         );
+
+        let global_price = self.current_share_price();
         let attached_near = env::attached_deposit();
 
         let distribution_info_result = self.internal_distribute(
             distributor.clone(),
             recipient.clone(),
-            global_price_num,
-            global_price_denom,
+            global_price,
             in_near,
             attached_near,
+            None,
         );
 
         match distribution_info_result {
@@ -850,30 +1370,15 @@ impl NearStaker {
                         .transfer(NearToken::from_yoctonear(distribution_info.refund_amount));
                 }
 
-                let (
-                    total_allocated_amount,
-                    total_allocated_share_price_num,
-                    total_allocated_share_price_denom,
-                ) = self.get_total_allocated(distributor.clone());
-
                 // emit Distribute Rewards event
-                Event::DistributedRewardsEvent {
-                    user: distributor.clone(),
-                    recipient: recipient.clone(),
-                    shares: U128(distribution_info.shares_amount),
-                    near_amount: U128(distribution_info.near_amount),
-                    user_balance: self.ft_balance_of(distributor),
-                    recipient_balance: self.ft_balance_of(recipient),
-                    fees: distribution_info.fees.into(),
-                    treasury_balance: self.ft_balance_of(self.treasury.clone()),
-                    share_price_num: distribution_info.share_price_num.to_string(),
-                    share_price_denom: distribution_info.share_price_denom.to_string(),
+                self.internal_emit_distributed_rewards(
+                    &distributor,
+                    &recipient,
+                    &distribution_info,
                     in_near,
-                    total_allocated_amount,
-                    total_allocated_share_price_num,
-                    total_allocated_share_price_denom,
-                }
-                .emit();
+                    false,
+                    self.get_total_allocated(distributor.clone()),
+                );
             }
         }
     }
@@ -888,92 +1393,69 @@ impl NearStaker {
 
         // check if distributor has allocations
         let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
         require!(
             self.allocations.contains_key(&distributor),
             ERR_NO_ALLOCATIONS
         );
 
-        // ensure distributor has enough NEAR and TruNEAR to complete the distribution
-        let (required_shares, required_near) =
-            self.get_rewards_distribution_amounts(&distributor, None, in_near);
-        if self.ft_balance_of(distributor.clone()).0 < required_shares.0 {
-            env::panic_str(ERR_INSUFFICIENT_TRUNEAR_BALANCE);
-        }
-        if env::attached_deposit().as_yoctonear() < required_near.0 {
-            env::panic_str(ERR_INSUFFICIENT_NEAR_BALANCE);
-        }
-
-        let (total_allocated_amount, _, _) = self.get_total_allocated(distributor.clone());
-
-        let (global_price_num, global_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.total_supply,
-            self.tax_exempt_stake,
-            self.fee,
-        );
+        // all recipients are distributed to as a single batch
+        self.internal_distribute_batch(&distributor, None, u32::MAX, in_near);
 
-        // this holds the amount of NEAR we will need to refund to the distributor at the end of the distribution
-        let mut refund_near_amount = env::attached_deposit();
+        // emit DistributedAllEvent
+        Event::DistributedAllEvent { user: &distributor }.emit();
+    }
 
-        let distributor_allocations = self.allocations.get(&distributor).cloned().unwrap();
+    #[payable]
+    /// Distributes NEAR staking rewards to a batch of recipients, ordered by account ID. The batch starts at
+    /// `from_recipient` or the first recipient after it, or where the previous batch left off when not provided,
+    /// and the distributor only needs the NEAR and TruNEAR required by the recipients in the batch. The cursor
+    /// wraps back to the first recipient once the last batch has been distributed.
+    pub fn distribute_batch(
+        &mut self,
+        in_near: bool,
+        from_recipient: Option<AccountId>,
+        limit: Option<u32>,
+    ) {
+        self.check_not_paused();
+        self.check_whitelisted();
+        self.check_contract_in_sync();
+        self.check_not_locked();
 
-        let mut distributed_rewards_events: Vec<Event> = vec![];
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.allocations.contains_key(&distributor),
+            ERR_NO_ALLOCATIONS
+        );
 
-        distributor_allocations.keys().for_each(|recipient| {
-            let distribution_info_result = self.internal_distribute(
-                distributor.clone(),
-                recipient.clone(),
-                global_price_num,
-                global_price_denom,
-                in_near,
-                refund_near_amount,
-            );
+        let from_recipient =
+            from_recipient.or_else(|| self.get_distribution_cursor(distributor.clone()));
+        let (distributions, next_recipient) = self.internal_distribute_batch(
+            &distributor,
+            from_recipient.as_ref(),
+            limit.unwrap_or(DEFAULT_DISTRIBUTION_PAGE_SIZE),
+            in_near,
+        );
 
-            match distribution_info_result {
-                Err(error) => {
-                    log!("Error distributing rewards: {}", error);
-                }
-                Ok(distribution_info_opt) => {
-                    match distribution_info_opt {
-                        None => log!("No rewards to distribute to {}", recipient),
-                        Some(distribution_info) => {
-                            // update the near amount left for the next distribution
-                            refund_near_amount =
-                                NearToken::from_yoctonear(distribution_info.refund_amount);
-                            distributed_rewards_events.push(Event::DistributedRewardsEvent {
-                                user: distributor.clone(),
-                                recipient: recipient.clone(),
-                                shares: U128(distribution_info.shares_amount),
-                                near_amount: U128(distribution_info.near_amount),
-                                user_balance: self.ft_balance_of(distributor.clone()),
-                                recipient_balance: self.ft_balance_of(recipient.clone()),
-                                fees: distribution_info.fees.into(),
-                                treasury_balance: self.ft_balance_of(self.treasury.clone()),
-                                share_price_num: distribution_info.share_price_num.to_string(),
-                                share_price_denom: distribution_info.share_price_denom.to_string(),
-                                in_near,
-                                total_allocated_amount,
-                                total_allocated_share_price_num: global_price_num.to_string(),
-                                total_allocated_share_price_denom: global_price_denom.to_string(),
-                            });
-                        }
-                    };
-                }
+        // move the cursor past the batch, wrapping back to the first recipient after the last batch
+        match &next_recipient {
+            Some(next_recipient) => {
+                self.distribution_cursors
+                    .insert(distributor.clone(), next_recipient.clone());
+            }
+            None => {
+                self.distribution_cursors.remove(&distributor);
             }
-        });
-
-        // refund any excess NEAR to distributor
-        if refund_near_amount.as_yoctonear() > 0 {
-            Promise::new(distributor.clone()).transfer(refund_near_amount);
         }
 
-        // emit DistributedRewardsEvent events
-        distributed_rewards_events
-            .iter()
-            .for_each(|event| event.emit());
-
-        // emit DistributedAllEvent
-        Event::DistributedAllEvent { user: &distributor }.emit();
+        Event::DistributedBatch {
+            user: &distributor,
+            from_recipient: from_recipient.as_ref(),
+            next_recipient: next_recipient.as_ref(),
+            distributions: &distributions,
+        }
+        .emit();
     }
 
     /// Withdraws the unstaked amount associated with the unstake_nonce.
@@ -997,7 +1479,7 @@ impl NearStaker {
         );
 
         // read the current contract state
-        let state = env::state_read().expect(ERR_NOT_INITIALIZED);
+        let state: NearStakerV1 = env::state_read().expect(ERR_NOT_INITIALIZED);
 
         // perform the migration from the previous version and return the new contract state
         VersionedNearStaker::V1(state).into()
@@ -1037,6 +1519,12 @@ impl NearStaker {
         // we add the amount withdrawn to the total amount of not yet claimed withdrawals
         self.withdrawn_amount += withdrawn_amount.0;
 
+        // the requested withdrawal amount that didn't reach the staker is covered by the staker
+        let withdraw_subsidy =
+            Self::withdraw_shortfall(withdrawn_amount.0, pre_withdraw_staker_balance);
+        self.internal_update_unstake_shortfall(&pool_id, 0, 0, true);
+        self.internal_record_rounding_subsidy(&pool_id, 0, 0, withdraw_subsidy);
+
         // we reset the pools requested unstake amount to 0
         self.delegation_pools.entry(pool_id).and_modify(|pool| {
             pool.total_unstaked = U128(0);
@@ -1062,6 +1550,7 @@ impl NearStaker {
             return;
         }
         let account_total_balance: U128 = stake_result.unwrap();
+        let share_price = self.current_share_price();
         let pool = self.delegation_pools.get_mut(&pool_id).unwrap();
         // The new total staked is given by the total pool account balance minus the total requested unstake amount.
         // We require that the new total staked is greater than the previous total staked amount.
@@ -1070,14 +1559,7 @@ impl NearStaker {
             return;
         };
 
-        let (share_price_num, share_price_denom) = Self::internal_share_price(
-            self.total_staked,
-            self.token.ft_total_supply().0,
-            self.tax_exempt_stake,
-            self.fee,
-        );
-        let shares_amount =
-            Self::convert_to_shares(amount.0, share_price_num, share_price_denom, false);
+        let shares_amount = share_price.convert_to_shares(amount.0, Rounding::Down);
 
         // The new total staked on the pool is given by the account_total_balance minus the pool's
         // total requested unstake. To get the increased stake we subtract the new total staked amount from
@@ -1090,6 +1572,12 @@ impl NearStaker {
         self.total_staked += amount.0;
         self.tax_exempt_stake += amount.0;
         log!("Updated total_staked: {}", self.total_staked);
+        self.internal_record_rounding_subsidy(
+            &pool_id,
+            amount.0.saturating_sub(increased_stake),
+            0,
+            0,
+        );
 
         // finally mint the equivalent TruNEAR to the user
         self.internal_mint(shares_amount, caller.clone());
@@ -1103,8 +1591,8 @@ impl NearStaker {
             shares_amount: &U128(shares_amount),
             total_staked: &U128(self.total_staked),
             total_supply: &U128(self.token.total_supply),
-            share_price_num: &share_price_num.to_string(),
-            share_price_denom: &share_price_denom.to_string(),
+            share_price_num: &share_price.num.to_string(),
+            share_price_denom: &share_price.denom.to_string(),
             epoch: &env::epoch_height().into(),
             pool_id: &pool_id,
         }
@@ -1112,36 +1600,102 @@ impl NearStaker {
     }
 
     #[private]
-    /// Handles the unstake promise, performing associated accounting if successful.
-    pub fn finalize_unstake(
+    /// Handles the donation stake promise, adding the donation to the total staked without minting TruNEAR.
+    pub fn finalize_donation(
         &mut self,
         pool_id: AccountId,
         amount: U128,
-        caller: AccountId,
-        pre_unstake_staker_balance: NearToken,
-        share_price_num: String,
-        share_price_denom: String,
-        shares_amount: U128,
-        withdraw_occurred: bool,
-        attached_near: NearToken,
-        unstake_epoch: u64,
+        donor: AccountId,
+        #[callback_result] stake_result: Result<U128, PromiseError>,
+    ) {
+        self.is_locked = false;
+
+        let account_total_balance = match stake_result {
+            Ok(balance) => balance.0,
+            Err(_) => {
+                log!("Donation failed. Refunding {} to donor", amount.0);
+                Promise::new(donor).transfer(NearToken::from_yoctonear(amount.0));
+                return;
+            }
+        };
+
+        let pool = self.delegation_pools.get_mut(&pool_id).unwrap();
+        // as for deposits, we require that the new total staked on the pool is greater than the previous one
+        if pool.total_staked >= (account_total_balance - pool.total_unstaked.0).into() {
+            log!("Donation failed");
+            return;
+        };
+        let increased_stake = account_total_balance - pool.total_unstaked.0 - pool.total_staked.0;
+
+        // the donation increases the total staked without minting any shares, which raises the share price.
+        // It is also added to the tax exempt stake so that the treasury does not charge fees on it.
+        pool.total_staked = (pool.total_staked.0 + amount.0).into();
+        self.total_staked += amount.0;
+        self.tax_exempt_stake += amount.0;
+        log!("Updated total_staked: {}", self.total_staked);
+        self.internal_record_rounding_subsidy(
+            &pool_id,
+            amount.0.saturating_sub(increased_stake),
+            0,
+            0,
+        );
+
+        let share_price = self.current_share_price();
+        Event::Donation {
+            donor: &donor,
+            amount: &amount,
+            amount_staked: &U128(increased_stake),
+            total_staked: &U128(self.total_staked),
+            total_supply: &U128(self.token.total_supply),
+            share_price_num: &share_price.num.to_string(),
+            share_price_denom: &share_price.denom.to_string(),
+            epoch: &env::epoch_height().into(),
+            pool_id: &pool_id,
+        }
+        .emit();
+    }
+
+    #[private]
+    /// Handles the unstake promise, performing associated accounting if successful.
+    pub fn finalize_unstake(
+        &mut self,
+        unstake: UnstakeDetails,
         #[callback_result] new_unstaked_amount: Result<U128, PromiseError>,
     ) {
         self.is_locked = false;
+        let UnstakeDetails {
+            pool_id,
+            amount,
+            caller,
+            pre_unstake_staker_balance,
+            share_price_num,
+            share_price_denom,
+            shares_amount,
+            withdraw_occurred,
+            attached_near,
+            unstake_epoch,
+            storage_payer,
+        } = unstake;
 
         let new_unstaked_amount = match new_unstaked_amount {
             Ok(amount) => amount.0,
             Err(_) => {
                 log!("Failed to unstake: {}", ERR_CALLBACK_FAILED);
-                self.internal_mint(shares_amount.0, caller.clone());
                 self.total_staked += amount.0;
                 self.tax_exempt_stake += amount.0;
-                Promise::new(caller).transfer(attached_near);
+                self.internal_mint(shares_amount.0, caller.clone());
+                Promise::new(storage_payer).transfer(attached_near);
                 return;
             }
         };
         let pool = self.delegation_pools.get_mut(&pool_id).unwrap();
 
+        // the requested withdrawal amount that didn't reach the staker is covered by the staker
+        let withdraw_subsidy = if withdraw_occurred {
+            Self::withdraw_shortfall(pool.total_unstaked.0, pre_unstake_staker_balance)
+        } else {
+            0
+        };
         if withdraw_occurred {
             self.withdrawn_amount += pool.total_unstaked.0;
             // if a withdraw occurred, the new total unstake amount on the pool should be the amount
@@ -1151,6 +1705,7 @@ impl NearStaker {
             // if no withdraw occurred we add the requested unstake amount to the pool total unstaked amount
             pool.total_unstaked = (pool.total_unstaked.0 + amount.0).into();
         }
+        let tracked_unstaked = pool.total_unstaked.0;
 
         // update delegation pool and total_staked
         pool.last_unstake = Some(unstake_epoch);
@@ -1165,6 +1720,14 @@ impl NearStaker {
             pre_unstake_staker_balance,
             env::account_balance()
         );
+        // any new shortfall between the unstaked amount we track and the one reported by the pool is covered by the staker
+        let unstake_subsidy = self.internal_update_unstake_shortfall(
+            &pool_id,
+            tracked_unstaked,
+            new_unstaked_amount,
+            withdraw_occurred,
+        );
+        self.internal_record_rounding_subsidy(&pool_id, 0, unstake_subsidy, withdraw_subsidy);
 
         // create the unstake request
         self.unstake_nonce += 1;
@@ -1181,6 +1744,8 @@ impl NearStaker {
 
         // refund any excess NEAR to allocator
         let storage_cost = NearToken::from_yoctonear(Self::get_storage_cost().0);
+        self.unstake_storage_deposits
+            .insert(self.unstake_nonce, storage_cost.as_yoctonear());
         if attached_near > storage_cost {
             Promise::new(caller.clone()).transfer(attached_near.checked_sub(storage_cost).unwrap());
         }
@@ -1204,10 +1769,35 @@ impl NearStaker {
 
     #[private]
     /// Handles the get_account_total_balance promises, updating the total_staked and total_staked_last_updated_at.
-    pub fn total_staked_callback(&mut self) {
+    /// Also accounts for the campaign rewards released at the given epoch if they were staked.
+    pub fn total_staked_callback(&mut self, released_rewards: U128, release_epoch: u64) {
         self.is_locked = false;
         let mut total_staked_sum = 0;
         let mut account_total_balances: Vec<U128> = vec![];
+        let mut failed_pools: Vec<AccountId> = vec![];
+
+        // the released rewards were staked if the default pool promise succeeded
+        let default_pool_index = self
+            .delegation_pools_list
+            .iter()
+            .position(|pool_id| *pool_id == self.default_delegation_pool)
+            .unwrap();
+        let rewards_released = released_rewards.0 > 0
+            && matches!(
+                env::promise_result(default_pool_index as u64),
+                PromiseResult::Successful(_)
+            );
+        if rewards_released {
+            // released rewards are tax exempt, like donations
+            self.internal_release_campaign_rewards(release_epoch);
+            self.tax_exempt_stake += released_rewards.0;
+            Event::RewardCampaignsReleased {
+                amount: &released_rewards,
+                pool_id: &self.default_delegation_pool,
+                epoch: &release_epoch.into(),
+            }
+            .emit();
+        }
 
         // ensure all ping and get_account_total_balance promises succeeded
         for i in 0..self.delegation_pools_list.len() {
@@ -1228,16 +1818,34 @@ impl NearStaker {
                             "Error deserializing the account total balance for pool {}",
                             pool_id
                         );
-                        return;
+                        failed_pools.push(pool_id);
                     }
                 }
                 PromiseResult::Failed => {
                     log!("Error fetching the staked amount from pool {}", pool_id);
-                    return;
+                    failed_pools.push(pool_id);
                 }
             }
         }
+        if !failed_pools.is_empty() {
+            // the released rewards were staked, so they must be accounted for until the next successful update
+            if rewards_released {
+                let default_pool = self
+                    .delegation_pools
+                    .get_mut(&self.default_delegation_pool)
+                    .unwrap();
+                default_pool.total_staked = U128(default_pool.total_staked.0 + released_rewards.0);
+                self.total_staked += released_rewards.0;
+            }
+            Event::TotalStakedUpdateFailed {
+                failed_pools: &failed_pools,
+                epoch: &env::epoch_height().into(),
+            }
+            .emit();
+            return;
+        }
         // if all promises succeed, we can now update the pool total_staked amounts and the staker total_staked amount
+        let mut pool_updates: Vec<PoolTotalStakedUpdate> = vec![];
         for i in 0..account_total_balances.len() {
             let pool_id: AccountId = self.delegation_pools_list[i].clone();
             let account_total_balance = account_total_balances[i].clone();
@@ -1245,15 +1853,41 @@ impl NearStaker {
             // To calculate the actual amount staked, we need to subtract the unstaked balance.
             // Due to rounding errors on the staking pool we need to keep track of the total_unstaked amounts ourselves in pool.total_unstaked.
             let pool_mut = self.delegation_pools.get_mut(&pool_id).unwrap();
+            let old_total_staked = pool_mut.total_staked;
             // the new pool total_staked amount is given by the pool total balance minus the total requested unstake amount
             pool_mut.total_staked = U128::from(account_total_balance.0 - pool_mut.total_unstaked.0);
             // we then add the total amount staked on the pool to the total staked by our staker
             total_staked_sum += pool_mut.total_staked.0;
+            pool_updates.push(PoolTotalStakedUpdate {
+                pool_id,
+                old_total_staked,
+                new_total_staked: pool_mut.total_staked,
+            });
         }
 
+        // the share prices at the reached allocation expiries exclude the rewards synced in this update
+        self.internal_record_expiry_share_prices();
+        let old_total_staked = self.total_staked;
         self.total_staked = total_staked_sum;
         self.total_staked_last_updated_at = env::epoch_height();
         log!("Updated total_staked: {}", self.total_staked);
+        self.internal_accrue_management_fee();
+
+        let share_price = self.current_share_price();
+        Event::TotalStakedUpdated {
+            pools: &pool_updates,
+            old_total_staked: &U128(old_total_staked),
+            total_staked: &U128(self.total_staked),
+            total_supply: &U128(self.token.total_supply),
+            share_price_num: &share_price.num.to_string(),
+            share_price_denom: &share_price.denom.to_string(),
+            epoch: &self.total_staked_last_updated_at.into(),
+        }
+        .emit();
+
+        if self.auto_collect_fees {
+            self.internal_collect_fees();
+        }
     }
 }
 
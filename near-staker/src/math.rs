@@ -1,3 +1,7 @@
+use near_sdk::near;
+
+use crate::constants::SHARE_PRICE_SCALING_FACTOR;
+use crate::errors::ERR_SHARE_PRICE_OVERFLOW;
 use crate::types::U256;
 
 pub fn mul256(a: u128, b: u128) -> U256 {
@@ -12,3 +16,88 @@ pub fn mul_div_with_rounding(x: U256, y: U256, denominator: U256, rounding_up: b
     }
     result
 }
+
+/// Rounding direction applied to the result of a division.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// Computes x * y / denominator with the given rounding, returning None on overflow or division by zero.
+pub fn checked_mul_div(x: U256, y: U256, denominator: U256, rounding: Rounding) -> Option<U256> {
+    let product = x.checked_mul(y)?;
+    let result = product.checked_div(denominator)?;
+    if rounding == Rounding::Up && !product.checked_rem(denominator)?.is_zero() {
+        return result.checked_add(U256::from(1));
+    }
+    Some(result)
+}
+
+/// The TruNEAR share price in NEAR, expressed as a fraction whose numerator is scaled by SHARE_PRICE_SCALING_FACTOR.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SharePrice {
+    pub num: U256,
+    pub denom: U256,
+}
+
+impl SharePrice {
+    pub fn new(num: U256, denom: U256) -> Self {
+        Self { num, denom }
+    }
+
+    /// The share price used while no TruNEAR is in circulation, i.e. 1 NEAR per TruNEAR.
+    pub fn initial() -> Self {
+        Self::new(U256::from(SHARE_PRICE_SCALING_FACTOR), U256::from(1))
+    }
+
+    /// Builds the share price at which the given amount of NEAR is worth the given amount of shares.
+    pub fn from_assets_and_shares(assets: u128, shares: U256) -> Self {
+        Self::new(mul256(assets, SHARE_PRICE_SCALING_FACTOR), shares)
+    }
+
+    /// Returns the share price scaled by SHARE_PRICE_SCALING_FACTOR, rounded down.
+    pub fn scaled(&self) -> U256 {
+        self.num / self.denom
+    }
+
+    /// Converts an amount of NEAR to shares, returning None if the result does not fit in a U256.
+    pub fn checked_to_shares(&self, assets: U256, rounding: Rounding) -> Option<U256> {
+        checked_mul_div(
+            assets,
+            self.denom,
+            self.num / U256::from(SHARE_PRICE_SCALING_FACTOR),
+            rounding,
+        )
+    }
+
+    /// Converts an amount of shares to NEAR, returning None if the result does not fit in a U256.
+    pub fn checked_to_assets(&self, shares: U256, rounding: Rounding) -> Option<U256> {
+        checked_mul_div(
+            shares,
+            self.num / U256::from(SHARE_PRICE_SCALING_FACTOR),
+            self.denom,
+            rounding,
+        )
+    }
+
+    /// Converts an amount of NEAR to the equivalent TruNEAR amount using the specified rounding.
+    pub fn convert_to_shares(&self, assets: u128, rounding: Rounding) -> u128 {
+        self.checked_to_shares(U256::from(assets), rounding)
+            .and_then(|shares| u128::try_from(shares).ok())
+            .expect(ERR_SHARE_PRICE_OVERFLOW)
+    }
+
+    /// Converts an amount of TruNEAR to the equivalent NEAR amount using the specified rounding.
+    pub fn convert_to_assets(&self, shares: u128, rounding: Rounding) -> u128 {
+        self.checked_to_assets(U256::from(shares), rounding)
+            .and_then(|assets| u128::try_from(assets).ok())
+            .expect(ERR_SHARE_PRICE_OVERFLOW)
+    }
+
+    /// Returns the numerator and denominator as decimal strings, as used in views and events.
+    pub fn to_strings(self) -> (String, String) {
+        (self.num.to_string(), self.denom.to_string())
+    }
+}
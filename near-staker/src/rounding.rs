@@ -0,0 +1,184 @@
+use near_sdk::{env, json_types::U128, log, near, require, AccountId, NearToken, Promise};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::*;
+
+impl NearStaker {
+    /// Returns the total rounding subsidy across all pools.
+    pub(crate) fn total_rounding_subsidy(&self) -> u128 {
+        self.rounding_subsidies
+            .values()
+            .map(|subsidy| {
+                subsidy.stake_subsidy + subsidy.unstake_subsidy + subsidy.withdraw_subsidy
+            })
+            .sum()
+    }
+
+    /// Returns the part of the rounding reserve that has not been used to cover rounding subsidies.
+    pub(crate) fn remaining_rounding_reserve(&self) -> u128 {
+        self.rounding_reserve
+            .saturating_sub(self.total_rounding_subsidy())
+    }
+
+    /// Adds the NEAR covered by the staker for rounding on the given pool to the pool's rounding subsidy.
+    /// Emits a low reserve event if the remaining reserve drops below the threshold.
+    pub(crate) fn internal_record_rounding_subsidy(
+        &mut self,
+        pool_id: &AccountId,
+        stake_subsidy: u128,
+        unstake_subsidy: u128,
+        withdraw_subsidy: u128,
+    ) {
+        if stake_subsidy == 0 && unstake_subsidy == 0 && withdraw_subsidy == 0 {
+            return;
+        }
+
+        let remaining_before = self.remaining_rounding_reserve();
+        let subsidy = self.rounding_subsidies.entry(pool_id.clone()).or_default();
+        subsidy.stake_subsidy += stake_subsidy;
+        subsidy.unstake_subsidy += unstake_subsidy;
+        subsidy.withdraw_subsidy += withdraw_subsidy;
+        log!(
+            "Rounding subsidy on pool {}: stake {}, unstake {}, withdraw {}",
+            pool_id,
+            stake_subsidy,
+            unstake_subsidy,
+            withdraw_subsidy
+        );
+
+        Event::RoundingSubsidyRecorded {
+            pool_id,
+            stake_subsidy: &U128(stake_subsidy),
+            unstake_subsidy: &U128(unstake_subsidy),
+            withdraw_subsidy: &U128(withdraw_subsidy),
+            total_subsidy: &U128(self.total_rounding_subsidy()),
+        }
+        .emit();
+
+        let remaining_after = self.remaining_rounding_reserve();
+        if remaining_before >= self.rounding_reserve_threshold
+            && remaining_after < self.rounding_reserve_threshold
+        {
+            Event::RoundingReserveLow {
+                remaining_reserve: &U128(remaining_after),
+                threshold: &U128(self.rounding_reserve_threshold),
+            }
+            .emit();
+        }
+    }
+
+    /// Records the shortfall between the unstaked amount tracked on the pool and the one reported by the pool,
+    /// returning the part of it that hasn't been covered yet. The previous shortfall was withdrawn along with
+    /// the rest of the unstaked amount if a withdrawal occurred.
+    pub(crate) fn internal_update_unstake_shortfall(
+        &mut self,
+        pool_id: &AccountId,
+        tracked_unstaked: u128,
+        reported_unstaked: u128,
+        withdraw_occurred: bool,
+    ) -> u128 {
+        let shortfall = tracked_unstaked.saturating_sub(reported_unstaked);
+        let subsidy = self.rounding_subsidies.entry(pool_id.clone()).or_default();
+        let previous_shortfall = if withdraw_occurred {
+            0
+        } else {
+            subsidy.unstake_shortfall
+        };
+        subsidy.unstake_shortfall = shortfall;
+        shortfall.saturating_sub(previous_shortfall)
+    }
+
+    /// Returns the part of the requested withdrawal amount that didn't reach the staker, given its balance before
+    /// the withdrawal.
+    pub(crate) fn withdraw_shortfall(
+        requested_amount: u128,
+        pre_withdraw_balance: NearToken,
+    ) -> u128 {
+        let withdrawn_amount = env::account_balance()
+            .saturating_sub(pre_withdraw_balance)
+            .as_yoctonear();
+        requested_amount.saturating_sub(withdrawn_amount)
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns the rounding reserve and the cumulative rounding subsidy per pool.
+    pub fn get_rounding_reserve(&self) -> RoundingReserveInfo {
+        let pools = self
+            .delegation_pools_list
+            .iter()
+            .map(|pool_id| {
+                let subsidy = self
+                    .rounding_subsidies
+                    .get(pool_id)
+                    .cloned()
+                    .unwrap_or_default();
+                PoolRoundingSubsidy {
+                    pool_id: pool_id.clone(),
+                    stake_subsidy: subsidy.stake_subsidy.into(),
+                    unstake_subsidy: subsidy.unstake_subsidy.into(),
+                    withdraw_subsidy: subsidy.withdraw_subsidy.into(),
+                }
+            })
+            .collect();
+
+        RoundingReserveInfo {
+            reserve_balance: self.rounding_reserve.into(),
+            total_subsidy: self.total_rounding_subsidy().into(),
+            remaining_reserve: self.remaining_rounding_reserve().into(),
+            threshold: self.rounding_reserve_threshold.into(),
+            pools,
+        }
+    }
+
+    #[payable]
+    /// Adds the attached NEAR to the reserve designated to cover rounding subsidies.
+    pub fn fund_rounding_reserve(&mut self) {
+        self.check_owner();
+
+        let amount = env::attached_deposit().as_yoctonear();
+        require!(amount > 0, ERR_NO_RESERVE_DEPOSIT);
+        self.rounding_reserve += amount;
+
+        Event::RoundingReserveFunded {
+            funder: &env::predecessor_account_id(),
+            amount: &U128(amount),
+            reserve_balance: &U128(self.rounding_reserve),
+        }
+        .emit();
+    }
+
+    /// Withdraws NEAR from the part of the rounding reserve that has not been used to cover rounding subsidies,
+    /// sending it to the owner.
+    pub fn withdraw_rounding_reserve(&mut self, amount: U128) -> Promise {
+        self.check_owner();
+        require!(
+            amount.0 > 0 && amount.0 <= self.remaining_rounding_reserve(),
+            ERR_EXCESSIVE_RESERVE_WITHDRAWAL
+        );
+        self.rounding_reserve -= amount.0;
+
+        let owner = env::predecessor_account_id();
+        Event::RoundingReserveWithdrawn {
+            recipient: &owner,
+            amount: &amount,
+            reserve_balance: &U128(self.rounding_reserve),
+        }
+        .emit();
+
+        Promise::new(owner).transfer(NearToken::from_yoctonear(amount.0))
+    }
+
+    /// Sets the remaining rounding reserve below which a low reserve event is emitted.
+    pub fn set_rounding_reserve_threshold(&mut self, threshold: U128) {
+        self.check_owner();
+        Event::SetRoundingReserveThreshold {
+            old_threshold: &U128(self.rounding_reserve_threshold),
+            new_threshold: &threshold,
+        }
+        .emit();
+        self.rounding_reserve_threshold = threshold.0;
+    }
+}
@@ -0,0 +1,85 @@
+use near_sdk::{env, near, require, AccountId};
+
+use crate::errors::*;
+use crate::events::Event;
+use crate::math::Rounding;
+use crate::*;
+
+impl NearStaker {
+    /// Returns the NEAR allocated by the allocator that must be covered by their withdrawable balance in
+    /// strict allocation mode. Escrowed allocations are excluded, as they are backed by locked TruNEAR.
+    pub(crate) fn internal_strict_allocated_amount(&self, allocator: &AccountId) -> u128 {
+        let (total_allocated, _, _) = self.get_total_allocated(allocator.clone());
+        let escrowed_amount: u128 = self
+            .escrowed_allocations
+            .get(allocator)
+            .into_iter()
+            .flatten()
+            .filter_map(|recipient| self.internal_allocation(allocator, recipient))
+            .map(|allocation| allocation.near_amount)
+            .sum();
+        total_allocated.0.saturating_sub(escrowed_amount)
+    }
+
+    /// Checks that an allocator in strict allocation mode still covers their allocated amount after
+    /// withdrawing the given amount of NEAR from their position.
+    pub(crate) fn check_allocation_coverage(&self, account_id: &AccountId, withdrawn_amount: u128) {
+        if !self.strict_allocators.contains(account_id) {
+            return;
+        }
+        require!(
+            self.max_withdraw(account_id.clone())
+                .0
+                .saturating_sub(withdrawn_amount)
+                >= self.internal_strict_allocated_amount(account_id),
+            ERR_ALLOCATION_NOT_COVERED
+        );
+    }
+
+    /// Checks that an allocator in strict allocation mode still covers their allocated amount after
+    /// transferring the given amount of TruNEAR.
+    pub(crate) fn check_allocation_coverage_after_transfer(
+        &self,
+        account_id: &AccountId,
+        shares: u128,
+    ) {
+        if !self.strict_allocators.contains(account_id) {
+            return;
+        }
+        let withdrawn_amount = self
+            .current_share_price()
+            .convert_to_assets(shares, Rounding::Up);
+        self.check_allocation_coverage(account_id, withdrawn_amount);
+    }
+}
+
+#[near]
+impl NearStaker {
+    /// Returns whether the account is in strict allocation mode.
+    pub fn is_strict_allocation_mode(&self, account_id: AccountId) -> bool {
+        self.strict_allocators.contains(&account_id)
+    }
+
+    /// Opts the caller in or out of strict allocation mode. In strict mode, allocations cannot exceed the
+    /// caller's withdrawable balance, and TruNEAR transfers and unstakes cannot take the balance below the
+    /// allocated amount. Enabling strict mode requires the allocated amount to be covered already.
+    pub fn set_strict_allocation_mode(&mut self, enabled: bool) {
+        self.check_not_paused();
+        self.check_whitelisted();
+
+        let allocator = env::predecessor_account_id();
+        if enabled {
+            self.strict_allocators.insert(allocator.clone());
+            self.internal_migrate_allocations(&allocator);
+            self.check_allocation_coverage(&allocator, 0);
+        } else {
+            self.strict_allocators.remove(&allocator);
+        }
+
+        Event::StrictAllocationModeSet {
+            user: &allocator,
+            enabled: &enabled,
+        }
+        .emit();
+    }
+}
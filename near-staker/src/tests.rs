@@ -1,5 +1,6 @@
 use super::*;
 use crate::math::*;
+use crate::whitelist::WhitelistTrait;
 use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
 use near_sdk::{testing_env, AccountId};
 use std::any::Any;
@@ -109,9 +110,9 @@ fn test_set_treasury_called_by_non_owner_fails() {
 
     // non-owner tries to call only-owner method
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_treasury(accounts(2));
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -143,9 +144,9 @@ fn test_set_fee_called_by_non_owner_fails() {
 
     // non-owner tries to call only-owner method
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_fee(40);
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -158,9 +159,9 @@ fn test_set_fee_above_fee_precision_fails() {
 
     // try to set fee above fee precision
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_fee(FEE_PRECISION + 1);
-        }),
+        })),
         "Fee cannot be larger than fee precision",
     );
 }
@@ -195,9 +196,9 @@ fn test_set_distribution_fee_called_by_non_owner_fails() {
     // non-owner tries to call only-owner method
     // non-owner tries to call only-owner method
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_distribution_fee(40);
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -209,9 +210,9 @@ fn test_set_distribution_fee_above_fee_precision_fails() {
 
     // try to set fee above fee precision
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_distribution_fee(FEE_PRECISION + 1);
-        }),
+        })),
         "Fee cannot be larger than fee precision",
     );
 }
@@ -250,9 +251,9 @@ fn test_set_min_deposit_called_by_non_owner_fails() {
 
     // non-owner tries to call only-owner method
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_min_deposit(min_deposit);
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -265,9 +266,9 @@ fn test_set_min_deposit_below_one_near_fails() {
     let min_deposit: U128 = U128::from(100);
     // set min_deposit below one near
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_min_deposit(min_deposit);
-        }),
+        })),
         "Minimum deposit amount is too small",
     );
 }
@@ -288,9 +289,9 @@ fn test_check_owner_fails() {
     let staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.check_owner();
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -318,9 +319,9 @@ fn test_check_not_paused_called_by_owner() {
     specify_signer(0);
     let staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
 
-    let result = std::panic::catch_unwind(move || {
+    let result = std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
         let _ = &staker.check_not_paused();
-    });
+    }));
     assert!(result.is_ok());
 }
 
@@ -341,9 +342,9 @@ fn test_check_not_paused_fails() {
     staker.pause();
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.check_not_paused();
-        }),
+        })),
         "Contract is paused",
     );
 }
@@ -381,9 +382,9 @@ fn test_pause_called_by_non_owner_fails() {
     let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.pause();
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -416,9 +417,9 @@ fn test_unpause_called_by_non_owner_fails() {
     specify_signer(4);
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.unpause();
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -431,9 +432,9 @@ fn test_pause_already_paused_fails() {
     staker.pause();
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.pause();
-        }),
+        })),
         "Contract is paused",
     );
 }
@@ -444,9 +445,9 @@ fn test_unpaused_already_unpaused_fails() {
     specify_signer(0);
     let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.unpause();
-        }),
+        })),
         "Contract is not paused",
     );
 }
@@ -483,9 +484,9 @@ fn test_set_default_delegation_pool_not_called_by_owner_fails() {
 
     // non-owner tries to call only-owner method
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_default_delegation_pool(accounts(2));
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -498,9 +499,9 @@ fn test_set_non_existent_default_delegation_pool_fails() {
 
     // tries to set default pool to a non-registered pool
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_default_delegation_pool(accounts(3));
-        }),
+        })),
         "Delegation pool does not exist",
     );
 }
@@ -515,9 +516,9 @@ fn test_set_disabled_default_delegation_pool_fails() {
     staker.disable_pool(accounts(4));
     // tries to set default pool to a non-registered pool
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_default_delegation_pool(accounts(4));
-        }),
+        })),
         "Delegation pool not enabled",
     );
 }
@@ -530,7 +531,7 @@ fn test_share_price_with_zero_shares_supply() {
     let tax_exempt_stake: u128 = 0;
     let fee: u16 = 0;
 
-    let (numm, denom) =
+    let SharePrice { num: numm, denom } =
         NearStaker::internal_share_price(total_staked, shares_supply, tax_exempt_stake, fee);
 
     assert_eq!(numm, U256::from(SHARE_PRICE_SCALING_FACTOR));
@@ -545,7 +546,7 @@ fn test_share_price_with_total_staked_matching_share_supply() {
     let tax_exempt_stake: u128 = 0;
     let fee: u16 = 0;
 
-    let (numm, denom) =
+    let SharePrice { num: numm, denom } =
         NearStaker::internal_share_price(total_staked, shares_supply, tax_exempt_stake, fee);
 
     // verify the share price numerator and denominator
@@ -570,7 +571,7 @@ fn test_share_price_with_total_staked_greater_than_share_supply() {
     let tax_exempt_stake: u128 = 0;
     let fee: u16 = 0;
 
-    let (numm, denom) =
+    let SharePrice { num: numm, denom } =
         NearStaker::internal_share_price(total_staked, shares_supply, tax_exempt_stake, fee);
 
     // verify the share price numerator and denominator
@@ -595,7 +596,7 @@ fn test_share_price_with_fees() {
     let tax_exempt_stake = 246 * ONE_NEAR;
     let fee: u16 = 500; // 5%
 
-    let (numm, denom) =
+    let SharePrice { num: numm, denom } =
         NearStaker::internal_share_price(total_staked, shares_supply, tax_exempt_stake, fee);
 
     // verify the share price numerator and denominator
@@ -627,7 +628,7 @@ fn test_convert_to_assets() {
 
     // convert 1000 shares to assets
     let shares: u128 = 1000 * ONE_NEAR;
-    let assets = NearStaker::convert_to_assets(shares, price_num, price_denom, true);
+    let assets = SharePrice::new(price_num, price_denom).convert_to_assets(shares, Rounding::Up);
 
     // verify the expected amount of assets
     assert_eq!(assets, 3000 * ONE_NEAR);
@@ -649,8 +650,9 @@ fn test_convert_to_assets_with_rounding() {
 
     // calculate the assets for 1 share rounding up and down
     let shares: u128 = ONE_NEAR;
-    let assets_rounded_up = NearStaker::convert_to_assets(shares, price_num, price_denom, true);
-    let assets_rounded_down = NearStaker::convert_to_assets(shares, price_num, price_denom, false);
+    let share_price = SharePrice::new(price_num, price_denom);
+    let assets_rounded_up = share_price.convert_to_assets(shares, Rounding::Up);
+    let assets_rounded_down = share_price.convert_to_assets(shares, Rounding::Down);
 
     // verify that assets round up and down correctly
     assert_eq!(assets_rounded_up, ONE_NEAR + 1);
@@ -693,9 +695,9 @@ fn test_set_pending_owner_called_by_non_owner_fails() {
     let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.set_pending_owner(accounts(3));
-        }),
+        })),
         "Only the owner can call this method",
     );
 }
@@ -728,9 +730,9 @@ fn test_claim_ownership_when_no_pending_owner_is_set_fails() {
     let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
 
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.claim_ownership();
-        }),
+        })),
         "No pending owner set",
     );
 }
@@ -743,9 +745,9 @@ fn test_claim_ownership_with_non_pending_owner_fails() {
 
     staker.set_pending_owner(new_owner);
     check_error_message(
-        std::panic::catch_unwind(move || {
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
             staker.claim_ownership();
-        }),
+        })),
         "Only the pending owner can claim ownership",
     );
 }
@@ -887,18 +889,13 @@ fn test_mul_div_with_rounding_division_by_zero_fails() {
 
 #[test]
 fn test_internal_calculate_distribution_amount_with_large_allocation() {
-    let global_share_price_num = U256::from(SHARE_PRICE_SCALING_FACTOR);
-    let global_share_price_denom = U256::from(1);
+    let global_share_price = SharePrice::initial();
     let allocation = Allocation {
         near_amount: u128::MAX,
-        share_price_num: U256::from(SHARE_PRICE_SCALING_FACTOR),
-        share_price_denom: U256::from(2),
+        share_price: SharePrice::new(U256::from(SHARE_PRICE_SCALING_FACTOR), U256::from(2)),
     };
-    let dist_amount = NearStaker::internal_calculate_distribution_amount(
-        &allocation,
-        global_share_price_num,
-        global_share_price_denom,
-    );
+    let dist_amount =
+        NearStaker::internal_calculate_distribution_amount(&allocation, global_share_price);
     assert_eq!(dist_amount, u128::MAX);
 }
 
@@ -912,14 +909,12 @@ fn test_internal_calculate_distribution_amount_with_large_share_price() {
         * U256::from(FEE_PRECISION as u128);
     let allocation = Allocation {
         near_amount: u128::MAX,
-        share_price_num: U256::from(SHARE_PRICE_SCALING_FACTOR),
-        share_price_denom: U256::from(1),
+        share_price: SharePrice::initial(),
     };
     let expected_result: u128 = 306254130228844617117037146688591390310;
     let dist_amount = NearStaker::internal_calculate_distribution_amount(
         &allocation,
-        global_share_price_num,
-        global_share_price_denom,
+        SharePrice::new(global_share_price_num, global_share_price_denom),
     );
     assert_eq!(dist_amount, expected_result);
 }
@@ -935,3 +930,3528 @@ fn test_saturating_sub_with_overflow() {
     let result = 19999999999999999999999999u128.saturating_sub(20000000000000000000000000u128);
     assert_eq!(result, 0);
 }
+
+#[test]
+fn test_share_price_checked_conversions_overflow() {
+    let share_price = SharePrice::new(U256::from(SHARE_PRICE_SCALING_FACTOR), U256::MAX);
+
+    assert_eq!(
+        share_price.checked_to_shares(U256::from(2), Rounding::Down),
+        None
+    );
+    assert_eq!(
+        share_price.checked_to_assets(U256::MAX, Rounding::Down),
+        Some(U256::from(1))
+    );
+}
+
+#[test]
+fn test_share_price_checked_conversions_with_zero_price() {
+    let share_price = SharePrice::default();
+
+    assert_eq!(
+        share_price.checked_to_shares(U256::from(1), Rounding::Up),
+        None
+    );
+    assert_eq!(
+        share_price.checked_to_assets(U256::from(1), Rounding::Up),
+        None
+    );
+}
+
+#[test]
+fn test_share_price_convert_to_shares_above_u128_fails() {
+    // a share price of 0.5 NEAR doubles the amount of shares
+    let share_price = SharePrice::new(U256::from(SHARE_PRICE_SCALING_FACTOR), U256::from(2));
+
+    assert_eq!(
+        share_price.convert_to_shares(u128::MAX / 2, Rounding::Down),
+        u128::MAX - 1
+    );
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            share_price.convert_to_shares(u128::MAX, Rounding::Down);
+        })),
+        "Share price arithmetic overflow",
+    );
+}
+
+#[test]
+fn test_share_price_from_assets_and_shares() {
+    let share_price = SharePrice::from_assets_and_shares(3 * ONE_NEAR, U256::from(2 * ONE_NEAR));
+
+    // verify the share price is 1.5
+    assert_eq!(
+        share_price.scaled(),
+        U256::from(3 * SHARE_PRICE_SCALING_FACTOR / 2)
+    );
+    assert_eq!(
+        share_price.to_strings(),
+        (
+            (U256::from(3 * ONE_NEAR) * U256::from(SHARE_PRICE_SCALING_FACTOR)).to_string(),
+            (2 * ONE_NEAR).to_string()
+        )
+    );
+}
+
+#[test]
+fn test_calculate_updated_allocation_at_same_share_price() {
+    let share_price = NearStaker::internal_share_price(246 * ONE_NEAR, 200 * ONE_NEAR, 0, 0);
+    let allocation = Allocation {
+        near_amount: 10 * ONE_NEAR,
+        share_price,
+    };
+
+    let updated = NearStaker::calculate_updated_allocation(&allocation, 5 * ONE_NEAR, share_price);
+
+    assert_eq!(updated.near_amount, 15 * ONE_NEAR);
+    assert_eq!(updated.share_price.scaled(), share_price.scaled());
+}
+
+// Upper bound for generated NEAR amounts: one billion NEAR.
+const MAX_PROPTEST_NEAR: u128 = 1_000_000_000 * ONE_NEAR;
+
+/// Generates share prices through internal_share_price, covering rewards, fees and share prices below 1.0.
+fn reward_campaign(total_amount: u128, start_epoch: u64, num_epochs: u64) -> RewardCampaign {
+    RewardCampaign {
+        funder: accounts(0),
+        total_amount,
+        released_amount: 0,
+        start_epoch,
+        num_epochs,
+    }
+}
+
+#[test]
+fn test_reward_campaign_vests_linearly() {
+    let campaign = reward_campaign(10 * ONE_NEAR, 100, 4);
+
+    assert_eq!(campaign.vested_amount(99), 0);
+    assert_eq!(campaign.vested_amount(100), 0);
+    assert_eq!(campaign.vested_amount(101), 10 * ONE_NEAR / 4);
+    assert_eq!(campaign.vested_amount(103), 30 * ONE_NEAR / 4);
+    assert_eq!(campaign.vested_amount(104), 10 * ONE_NEAR);
+    assert_eq!(campaign.vested_amount(200), 10 * ONE_NEAR);
+    assert_eq!(campaign.end_epoch(), 104);
+}
+
+#[test]
+fn test_reward_campaign_vested_amount_rounds_down() {
+    let campaign = reward_campaign(10, 0, 3);
+
+    assert_eq!(campaign.vested_amount(1), 3);
+    assert_eq!(campaign.vested_amount(2), 6);
+    assert_eq!(campaign.vested_amount(3), 10);
+}
+
+#[test]
+fn test_reward_campaign_releasable_amount_excludes_released() {
+    let mut campaign = reward_campaign(10 * ONE_NEAR, 0, 10);
+    campaign.released_amount = 3 * ONE_NEAR;
+
+    assert_eq!(campaign.releasable_amount(5), 2 * ONE_NEAR);
+    assert_eq!(campaign.releasable_amount(10), 7 * ONE_NEAR);
+}
+
+#[test]
+fn test_internal_release_campaign_rewards_removes_completed_campaigns() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    staker
+        .reward_campaigns
+        .insert(1, reward_campaign(10 * ONE_NEAR, 0, 10));
+    staker
+        .reward_campaigns
+        .insert(2, reward_campaign(5 * ONE_NEAR, 0, 5));
+
+    assert_eq!(
+        staker.internal_releasable_campaign_rewards(5),
+        10 * ONE_NEAR
+    );
+
+    staker.internal_release_campaign_rewards(5);
+
+    assert_eq!(staker.reward_campaigns.len(), 1);
+    assert_eq!(staker.reward_campaigns[&1].released_amount, 5 * ONE_NEAR);
+    assert_eq!(staker.internal_releasable_campaign_rewards(5), 0);
+}
+
+#[test]
+fn test_create_reward_campaign() {
+    let mut context = get_context(accounts(0));
+    context
+        .attached_deposit(NearToken::from_near(10))
+        .epoch_height(7);
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    let campaign_id = staker.create_reward_campaign(5);
+
+    assert_eq!(campaign_id, U64(1));
+    let campaign = staker.get_reward_campaign(campaign_id);
+    assert_eq!(campaign.funder, accounts(0));
+    assert_eq!(campaign.total_amount, U128(10 * ONE_NEAR));
+    assert_eq!(campaign.start_epoch, U64(7));
+    assert_eq!(campaign.end_epoch, U64(12));
+
+    let (data, event) = fetch_event(&get_logs()[1]);
+    assert_eq!(event, "reward_campaign_created_event");
+    assert_eq!(data[0]["campaign_id"].as_str().unwrap(), "1");
+    assert_eq!(
+        data[0]["amount"].as_str().unwrap(),
+        "10000000000000000000000000"
+    );
+}
+
+#[test]
+fn test_create_reward_campaign_by_non_owner_fails() {
+    let mut context = get_context(accounts(4));
+    context.attached_deposit(NearToken::from_near(10));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.create_reward_campaign(5);
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_create_reward_campaign_under_one_near_fails() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_millinear(999));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.create_reward_campaign(5);
+        })),
+        ERR_CAMPAIGN_UNDER_ONE_NEAR,
+    );
+}
+
+#[test]
+fn test_create_reward_campaign_without_epochs_fails() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_near(10));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.create_reward_campaign(0);
+        })),
+        ERR_CAMPAIGN_NO_EPOCHS,
+    );
+}
+
+#[test]
+fn test_cancel_reward_campaign_truncates_campaign() {
+    let mut context = get_context(accounts(0));
+    context
+        .attached_deposit(NearToken::from_near(10))
+        .epoch_height(10);
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    let campaign_id = staker.create_reward_campaign(10);
+
+    // cancel after 4 epochs with nothing released yet
+    context
+        .attached_deposit(NearToken::from_yoctonear(0))
+        .epoch_height(14);
+    testing_env!(context.build());
+    staker.cancel_reward_campaign(campaign_id);
+
+    let campaign = staker.get_reward_campaign(campaign_id);
+    assert_eq!(campaign.total_amount, U128(4 * ONE_NEAR));
+    assert_eq!(campaign.releasable_amount, U128(4 * ONE_NEAR));
+    assert_eq!(campaign.end_epoch, U64(14));
+
+    let (data, event) = fetch_event(&get_logs()[0]);
+    assert_eq!(event, "reward_campaign_cancelled_event");
+    assert_eq!(
+        data[0]["refunded_amount"].as_str().unwrap(),
+        "6000000000000000000000000"
+    );
+}
+
+#[test]
+fn test_cancel_reward_campaign_refunds_funder() {
+    let mut context = get_context(accounts(0));
+    context
+        .attached_deposit(NearToken::from_near(10))
+        .epoch_height(10);
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    let campaign_id = staker.create_reward_campaign(10);
+    staker.set_pending_owner(accounts(5));
+    specify_signer(5);
+    staker.claim_ownership();
+
+    // the new owner cancels the campaign funded by the previous owner
+    let mut context = get_context(accounts(5));
+    context.epoch_height(14);
+    testing_env!(context.build());
+    staker.cancel_reward_campaign(campaign_id);
+
+    let receipts = near_sdk::test_utils::get_created_receipts();
+    assert_eq!(receipts.len(), 1);
+    assert_eq!(receipts[0].receiver_id, accounts(0));
+}
+
+#[test]
+fn test_cancel_fully_released_reward_campaign_removes_campaign() {
+    let mut context = get_context(accounts(0));
+    context
+        .attached_deposit(NearToken::from_near(10))
+        .epoch_height(10);
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    let campaign_id = staker.create_reward_campaign(10);
+
+    staker.cancel_reward_campaign(campaign_id);
+
+    assert!(staker.get_reward_campaigns().is_empty());
+}
+
+#[test]
+fn test_cancel_reward_campaign_by_non_owner_fails() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_near(10));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    let campaign_id = staker.create_reward_campaign(10);
+
+    testing_env!(get_context(accounts(4)).build());
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.cancel_reward_campaign(campaign_id);
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_record_rounding_subsidy() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    staker.internal_record_rounding_subsidy(&accounts(2), 3, 0, 0);
+    staker.internal_record_rounding_subsidy(&accounts(2), 1, 0, 2);
+
+    let reserve = staker.get_rounding_reserve();
+    assert_eq!(reserve.total_subsidy, U128(6));
+    assert_eq!(reserve.pools.len(), 1);
+    assert_eq!(reserve.pools[0].pool_id, accounts(2));
+    assert_eq!(reserve.pools[0].stake_subsidy, U128(4));
+    assert_eq!(reserve.pools[0].withdraw_subsidy, U128(2));
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "rounding_subsidy_recorded_event");
+    assert_eq!(data[0]["total_subsidy"].as_str().unwrap(), "6");
+}
+
+#[test]
+fn test_record_zero_rounding_subsidy_is_ignored() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    staker.internal_record_rounding_subsidy(&accounts(2), 0, 0, 0);
+
+    assert_eq!(staker.get_rounding_reserve().total_subsidy, U128(0));
+    assert_eq!(get_logs().len(), 1);
+}
+
+#[test]
+fn test_rounding_reserve_low_event_emitted_when_dropping_below_threshold() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_yoctonear(100));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    staker.fund_rounding_reserve();
+    staker.set_rounding_reserve_threshold(U128(50));
+
+    // the reserve stays above the threshold
+    staker.internal_record_rounding_subsidy(&accounts(2), 40, 0, 0);
+    assert!(!get_logs()
+        .iter()
+        .any(|log| log.contains("rounding_reserve_low_event")));
+
+    // the reserve drops below the threshold
+    staker.internal_record_rounding_subsidy(&accounts(2), 0, 0, 20);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "rounding_reserve_low_event");
+    assert_eq!(data[0]["remaining_reserve"].as_str().unwrap(), "40");
+    assert_eq!(data[0]["threshold"].as_str().unwrap(), "50");
+
+    // the event is not emitted again while the reserve stays below the threshold
+    staker.internal_record_rounding_subsidy(&accounts(2), 1, 0, 0);
+    assert_eq!(
+        get_logs()
+            .iter()
+            .filter(|log| log.contains("rounding_reserve_low_event"))
+            .count(),
+        1
+    );
+    assert_eq!(staker.get_rounding_reserve().remaining_reserve, U128(39));
+}
+
+#[test]
+fn test_fund_rounding_reserve() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_near(2));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    staker.fund_rounding_reserve();
+    staker.fund_rounding_reserve();
+
+    let reserve = staker.get_rounding_reserve();
+    assert_eq!(reserve.reserve_balance, U128(4 * ONE_NEAR));
+    assert_eq!(reserve.remaining_reserve, U128(4 * ONE_NEAR));
+
+    let (data, event) = fetch_event(&get_logs()[2]);
+    assert_eq!(event, "rounding_reserve_funded_event");
+    assert_eq!(
+        data[0]["reserve_balance"].as_str().unwrap(),
+        "4000000000000000000000000"
+    );
+}
+
+#[test]
+fn test_fund_rounding_reserve_by_non_owner_fails() {
+    let mut context = get_context(accounts(4));
+    context.attached_deposit(NearToken::from_near(2));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.fund_rounding_reserve();
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_fund_rounding_reserve_without_deposit_fails() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.fund_rounding_reserve();
+        })),
+        ERR_NO_RESERVE_DEPOSIT,
+    );
+}
+
+#[test]
+fn test_unstake_shortfall_reported_by_pool_is_recorded_as_subsidy() {
+    let mut staker = setup_staker_with_holders();
+    staker
+        .delegation_pools
+        .get_mut(&accounts(2))
+        .unwrap()
+        .total_staked = U128(200 * ONE_NEAR);
+    testing_env!(get_context(accounts(0))
+        .predecessor_account_id(env::current_account_id())
+        .build());
+
+    staker.finalize_unstake(
+        UnstakeDetails {
+            pool_id: accounts(2),
+            amount: U128(10 * ONE_NEAR),
+            caller: accounts(3),
+            pre_unstake_staker_balance: NearToken::from_yoctonear(0),
+            share_price_num: "1".to_string(),
+            share_price_denom: "1".to_string(),
+            shares_amount: U128(10 * ONE_NEAR),
+            withdraw_occurred: false,
+            attached_near: NearToken::from_yoctonear(0),
+            unstake_epoch: 0,
+            storage_payer: accounts(3),
+        },
+        Ok(U128(10 * ONE_NEAR - 2)),
+    );
+
+    let reserve = staker.get_rounding_reserve();
+    assert_eq!(reserve.pools[0].unstake_subsidy, U128(2));
+    assert_eq!(reserve.pools[0].withdraw_subsidy, U128(0));
+}
+
+/// Finalizes an unstake of 10 NEAR from accounts(3) on the default pool, given the pool's reported unstaked balance.
+fn finalize_unstake_with_reported_balance(
+    staker: &mut NearStaker,
+    withdraw_occurred: bool,
+    pre_unstake_staker_balance: NearToken,
+    reported_unstaked: u128,
+) {
+    staker.finalize_unstake(
+        UnstakeDetails {
+            pool_id: accounts(2),
+            amount: U128(10 * ONE_NEAR),
+            caller: accounts(3),
+            pre_unstake_staker_balance,
+            share_price_num: "1".to_string(),
+            share_price_denom: "1".to_string(),
+            shares_amount: U128(10 * ONE_NEAR),
+            withdraw_occurred,
+            attached_near: NearToken::from_yoctonear(0),
+            unstake_epoch: 0,
+            storage_payer: accounts(3),
+        },
+        Ok(U128(reported_unstaked)),
+    );
+}
+
+#[test]
+fn test_unstake_subsidy_only_records_new_shortfall() {
+    let mut staker = setup_staker_with_holders();
+    staker
+        .delegation_pools
+        .get_mut(&accounts(2))
+        .unwrap()
+        .total_staked = U128(200 * ONE_NEAR);
+    testing_env!(get_context(accounts(0))
+        .predecessor_account_id(env::current_account_id())
+        .build());
+    let balance = env::account_balance();
+
+    finalize_unstake_with_reported_balance(&mut staker, false, balance, 10 * ONE_NEAR - 2);
+    // the shortfall reported by the pool is unchanged, so it is not recorded again
+    finalize_unstake_with_reported_balance(&mut staker, false, balance, 20 * ONE_NEAR - 2);
+    assert_eq!(
+        staker.get_rounding_reserve().pools[0].unstake_subsidy,
+        U128(2)
+    );
+
+    finalize_unstake_with_reported_balance(&mut staker, false, balance, 30 * ONE_NEAR - 5);
+    assert_eq!(
+        staker.get_rounding_reserve().pools[0].unstake_subsidy,
+        U128(5)
+    );
+}
+
+#[test]
+fn test_withdraw_shortfall_is_recorded_as_subsidy() {
+    let mut staker = setup_staker_with_holders();
+    let pool = staker.delegation_pools.get_mut(&accounts(2)).unwrap();
+    pool.total_staked = U128(200 * ONE_NEAR);
+    pool.total_unstaked = U128(20 * ONE_NEAR);
+    testing_env!(get_context(accounts(0))
+        .predecessor_account_id(env::current_account_id())
+        .build());
+
+    // the pool transferred 3 yoctoNEAR less than the 20 NEAR requested
+    let pre_unstake_staker_balance = env::account_balance()
+        .checked_sub(NearToken::from_yoctonear(20 * ONE_NEAR - 3))
+        .unwrap();
+    finalize_unstake_with_reported_balance(
+        &mut staker,
+        true,
+        pre_unstake_staker_balance,
+        10 * ONE_NEAR,
+    );
+
+    let reserve = staker.get_rounding_reserve();
+    assert_eq!(reserve.pools[0].withdraw_subsidy, U128(3));
+    assert_eq!(reserve.pools[0].unstake_subsidy, U128(0));
+    assert_eq!(reserve.total_subsidy, U128(3));
+}
+
+#[test]
+fn test_withdraw_rounding_reserve() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_near(2));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    staker.fund_rounding_reserve();
+    staker.internal_record_rounding_subsidy(&accounts(2), ONE_NEAR / 2, 0, 0);
+
+    staker.withdraw_rounding_reserve(U128(ONE_NEAR));
+
+    let reserve = staker.get_rounding_reserve();
+    assert_eq!(reserve.reserve_balance, U128(ONE_NEAR));
+    assert_eq!(reserve.remaining_reserve, U128(ONE_NEAR / 2));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "rounding_reserve_withdrawn_event");
+    assert_eq!(data[0]["recipient"], accounts(0).to_string());
+    assert_eq!(data[0]["amount"], ONE_NEAR.to_string());
+    let receipts = near_sdk::test_utils::get_created_receipts();
+    assert_eq!(receipts.last().unwrap().receiver_id, accounts(0));
+}
+
+#[test]
+fn test_withdraw_rounding_reserve_used_by_subsidies_fails() {
+    let mut context = get_context(accounts(0));
+    context.attached_deposit(NearToken::from_near(2));
+    testing_env!(context.build());
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    staker.fund_rounding_reserve();
+    staker.internal_record_rounding_subsidy(&accounts(2), ONE_NEAR, 0, 0);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.withdraw_rounding_reserve(U128(ONE_NEAR + 1));
+        })),
+        ERR_EXCESSIVE_RESERVE_WITHDRAWAL,
+    );
+}
+
+#[test]
+fn test_withdraw_rounding_reserve_by_non_owner_fails() {
+    specify_signer(4);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.withdraw_rounding_reserve(U128(ONE_NEAR));
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_set_rounding_reserve_threshold_by_non_owner_fails() {
+    specify_signer(4);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_rounding_reserve_threshold(U128(ONE_NEAR));
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+/// Sets up a staker charging a 10% fee with 100 TruNEAR held by accounts(3) and accounts(4) each.
+fn setup_staker_with_holders() -> NearStaker {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    staker.set_fee(1000);
+    staker.internal_mint(100 * ONE_NEAR, accounts(3));
+    staker.internal_mint(100 * ONE_NEAR, accounts(4));
+    staker.total_staked = 200 * ONE_NEAR;
+    staker.tax_exempt_stake = 200 * ONE_NEAR;
+    staker
+}
+
+#[test]
+fn test_set_fee_override() {
+    let mut staker = setup_staker_with_holders();
+
+    staker.set_fee_override(accounts(3), 0, 50);
+
+    let fee_override = staker.get_fee_override(accounts(3)).unwrap();
+    assert_eq!(fee_override.fee, 0);
+    assert_eq!(fee_override.distribution_fee, 50);
+    assert_eq!(staker.get_fee_overrides().len(), 1);
+    assert!(staker.get_fee_override(accounts(4)).is_none());
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "fee_override_set_event");
+    assert_eq!(data[0]["account_id"].as_str().unwrap(), accounts(3));
+    assert_eq!(data[0]["distribution_fee"].as_u64().unwrap(), 50);
+}
+
+#[test]
+fn test_set_fee_override_by_non_owner_fails() {
+    let mut staker = setup_staker_with_holders();
+    specify_signer(4);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee_override(accounts(4), 0, 0);
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_set_fee_override_above_fee_precision_fails() {
+    let mut staker = setup_staker_with_holders();
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee_override(accounts(3), FEE_PRECISION, 0);
+        })),
+        ERR_FEE_TOO_LARGE,
+    );
+}
+
+#[test]
+fn test_remove_fee_override() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_override(accounts(3), 0, 0);
+
+    staker.remove_fee_override(accounts(3));
+
+    assert!(staker.get_fee_overrides().is_empty());
+    let (_, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "fee_override_removed_event");
+}
+
+#[test]
+fn test_remove_missing_fee_override_fails() {
+    let mut staker = setup_staker_with_holders();
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.remove_fee_override(accounts(3));
+        })),
+        ERR_NO_FEE_OVERRIDE,
+    );
+}
+
+#[test]
+fn test_distribution_fee_override() {
+    let mut staker = setup_staker_with_holders();
+    staker.distribution_fee = 500;
+    staker.set_fee_override(accounts(3), 1000, 0);
+
+    assert_eq!(staker.internal_distribution_fee(&accounts(3)), 0);
+    assert_eq!(staker.internal_distribution_fee(&accounts(4)), 500);
+}
+
+#[test]
+fn test_distribution_fee_override_above_global_fee_is_not_charged() {
+    let mut staker = setup_staker_with_holders();
+    staker.distribution_fee = 500;
+    staker.set_fee_override(accounts(3), 1000, 2000);
+
+    assert_eq!(staker.internal_distribution_fee(&accounts(3)), 500);
+
+    // lowering the global distribution fee applies to the account too
+    staker.distribution_fee = 100;
+    assert_eq!(staker.internal_distribution_fee(&accounts(3)), 100);
+}
+
+#[test]
+fn test_fee_rebate_starts_from_next_fee_collection() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_override(accounts(3), 0, 0);
+
+    // rewards accrued before the override are charged the global fee
+    staker.total_staked = 210 * ONE_NEAR;
+    staker.internal_collect_fees();
+
+    assert_eq!(staker.ft_balance_of(accounts(3)), U128(100 * ONE_NEAR));
+    assert!(staker.ft_balance_of(accounts(1)).0 > 0);
+}
+
+#[test]
+fn test_fee_rebate_on_zero_fee_override() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_override(accounts(3), 0, 0);
+    staker.total_staked = 210 * ONE_NEAR;
+    staker.internal_collect_fees();
+    let treasury_balance = staker.ft_balance_of(accounts(1)).0;
+    let supply = staker.token.total_supply;
+
+    staker.total_staked = 230 * ONE_NEAR;
+    let share_price = staker.current_share_price();
+    staker.internal_collect_fees();
+
+    // the collection does not change the share price
+    assert_eq!(staker.current_share_price().scaled(), share_price.scaled());
+
+    // the fee shares are split between the treasury and the account with the override
+    let fee_shares = staker.token.total_supply - supply;
+    let rebate = staker.ft_balance_of(accounts(3)).0 - 100 * ONE_NEAR;
+    let treasury_shares = staker.ft_balance_of(accounts(1)).0 - treasury_balance;
+    assert_eq!(rebate + treasury_shares, fee_shares);
+    assert_eq!(
+        rebate,
+        (mul256(fee_shares, 100 * ONE_NEAR) / U256::from(supply)).as_u128()
+    );
+    assert_eq!(staker.ft_balance_of(accounts(4)), U128(100 * ONE_NEAR));
+
+    let log = get_logs()
+        .into_iter()
+        .find(|log| log.contains("fee_rebated_event"))
+        .unwrap();
+    let (data, _) = fetch_event(&log);
+    assert_eq!(
+        data[0]["shares_rebated"].as_str().unwrap(),
+        rebate.to_string()
+    );
+}
+
+#[test]
+fn test_fee_rebate_on_partial_fee_override() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_override(accounts(3), 500, 0);
+    staker.total_staked = 210 * ONE_NEAR;
+    staker.internal_collect_fees();
+    let supply = staker.token.total_supply;
+
+    staker.total_staked = 230 * ONE_NEAR;
+    staker.internal_collect_fees();
+
+    // half of the account's part of the fees is rebated
+    let fee_shares = staker.token.total_supply - supply;
+    let rebate = staker.ft_balance_of(accounts(3)).0 - 100 * ONE_NEAR;
+    assert_eq!(
+        rebate,
+        (mul256(fee_shares, 100 * ONE_NEAR) / U256::from(supply * 2)).as_u128()
+    );
+}
+
+#[test]
+fn test_fee_rebate_excludes_shares_acquired_during_fee_period() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_override(accounts(3), 0, 0);
+    staker.total_staked = 210 * ONE_NEAR;
+    staker.internal_collect_fees();
+
+    // the account acquires more shares just before the next collection
+    staker.internal_mint(1000 * ONE_NEAR, accounts(3));
+    staker.total_staked += 1000 * ONE_NEAR;
+    staker.tax_exempt_stake += 1000 * ONE_NEAR;
+    let supply = staker.token.total_supply;
+
+    staker.total_staked += 20 * ONE_NEAR;
+    staker.internal_collect_fees();
+
+    // only the 100 TruNEAR held throughout the fee period earn a rebate
+    let fee_shares = staker.token.total_supply - supply;
+    let rebate = staker.ft_balance_of(accounts(3)).0 - 1100 * ONE_NEAR;
+    assert_eq!(
+        rebate,
+        (mul256(fee_shares, 100 * ONE_NEAR) / U256::from(supply)).as_u128()
+    );
+}
+
+fn fee_beneficiary(account_id: AccountId, basis_points: u16) -> FeeBeneficiary {
+    FeeBeneficiary {
+        account_id,
+        basis_points,
+    }
+}
+
+#[test]
+fn test_split_fee_shares_without_fee_split_goes_to_treasury() {
+    let shares = NearStaker::split_fee_shares(&[], &accounts(1), 100);
+
+    assert_eq!(shares, vec![(accounts(1), 100)]);
+}
+
+#[test]
+fn test_split_fee_shares_assigns_dust_to_first_beneficiary() {
+    let fee_split = vec![
+        fee_beneficiary(accounts(3), 3333),
+        fee_beneficiary(accounts(4), 3333),
+        fee_beneficiary(accounts(5), 3334),
+    ];
+
+    let shares = NearStaker::split_fee_shares(&fee_split, &accounts(1), 100);
+
+    assert_eq!(
+        shares,
+        vec![(accounts(3), 34), (accounts(4), 33), (accounts(5), 33)]
+    );
+}
+
+#[test]
+fn test_get_fee_split_defaults_to_treasury() {
+    specify_signer(0);
+    let staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    assert_eq!(
+        staker.get_fee_split(),
+        vec![fee_beneficiary(accounts(1), FEE_PRECISION)]
+    );
+}
+
+#[test]
+fn test_set_fee_split() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    let fee_split = vec![
+        fee_beneficiary(accounts(1), 7000),
+        fee_beneficiary(accounts(3), 3000),
+    ];
+
+    staker.set_fee_split(fee_split.clone());
+
+    assert_eq!(staker.get_fee_split(), fee_split);
+    assert!(staker.token.accounts.contains_key(&accounts(3)));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "set_fee_split_event");
+    assert_eq!(data[0]["new_fee_split"][1]["basis_points"], 3000);
+}
+
+#[test]
+fn test_set_fee_split_not_summing_to_fee_precision_fails() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee_split(vec![
+                fee_beneficiary(accounts(1), 7000),
+                fee_beneficiary(accounts(3), 2000),
+            ]);
+        })),
+        ERR_INVALID_FEE_SPLIT,
+    );
+}
+
+#[test]
+fn test_set_fee_split_with_zero_share_fails() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee_split(vec![
+                fee_beneficiary(accounts(1), FEE_PRECISION),
+                fee_beneficiary(accounts(3), 0),
+            ]);
+        })),
+        ERR_FEE_SPLIT_ZERO_SHARE,
+    );
+}
+
+#[test]
+fn test_set_fee_split_with_duplicate_beneficiary_fails() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee_split(vec![
+                fee_beneficiary(accounts(3), 5000),
+                fee_beneficiary(accounts(3), 5000),
+            ]);
+        })),
+        ERR_FEE_SPLIT_DUPLICATE,
+    );
+}
+
+#[test]
+fn test_set_fee_split_by_non_owner_fails() {
+    specify_signer(4);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee_split(vec![fee_beneficiary(accounts(4), FEE_PRECISION)]);
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_collect_fees_with_fee_split() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_split(vec![
+        fee_beneficiary(accounts(1), 8000),
+        fee_beneficiary(accounts(5), 2000),
+    ]);
+    let supply = staker.token.total_supply;
+    let share_price = staker.current_share_price();
+
+    staker.total_staked = 210 * ONE_NEAR;
+    let share_price_with_rewards = staker.current_share_price();
+    staker.internal_collect_fees();
+
+    let fee_shares = staker.token.total_supply - supply;
+    let treasury_shares = staker.ft_balance_of(accounts(1)).0;
+    let partner_shares = staker.ft_balance_of(accounts(5)).0;
+    assert!(share_price_with_rewards.scaled() > share_price.scaled());
+    assert_eq!(treasury_shares + partner_shares, fee_shares);
+    assert_eq!(partner_shares, fee_shares * 2000 / 10000);
+
+    // one fees collected event is emitted per beneficiary
+    let events: Vec<serde_json::Value> = get_logs()
+        .iter()
+        .filter(|log| log.contains("fees_collected_event"))
+        .map(|log| fetch_event(log).0[0].clone())
+        .collect();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["beneficiary"], accounts(1).to_string());
+    assert_eq!(events[0]["shares_minted"], treasury_shares.to_string());
+    assert_eq!(events[1]["beneficiary"], accounts(5).to_string());
+    assert_eq!(events[1]["shares_minted"], partner_shares.to_string());
+}
+
+#[test]
+fn test_fee_increases_are_timelocked_and_limited_by_default() {
+    let staker = setup_staker_with_holders();
+
+    assert_eq!(staker.config_change_delay, DEFAULT_CONFIG_CHANGE_DELAY);
+    assert_eq!(staker.max_fee_increase, DEFAULT_MAX_FEE_INCREASE);
+}
+
+#[test]
+fn test_fee_increase_is_immediate_before_trunear_is_in_circulation() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    staker.set_fee(3000);
+
+    assert_eq!(staker.fee, 3000);
+    assert!(staker.get_pending_config_changes().is_empty());
+    let (_, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "set_fee_event");
+}
+
+#[test]
+fn test_fee_increase_is_proposed_once_trunear_is_in_circulation() {
+    let mut staker = setup_staker_with_holders();
+
+    staker.set_fee(1500);
+
+    // the fee does not change until the proposal is executed
+    assert_eq!(staker.fee, 1000);
+    let pending_changes = staker.get_pending_config_changes();
+    assert_eq!(pending_changes.len(), 1);
+    assert_eq!(pending_changes[0].key, ConfigKey::FEE);
+    assert_eq!(pending_changes[0].old_value, U64(1000));
+    assert_eq!(pending_changes[0].new_value, U64(1500));
+    assert_eq!(pending_changes[0].proposed_at, U64(0));
+    assert_eq!(
+        pending_changes[0].executable_at,
+        U64(DEFAULT_CONFIG_CHANGE_DELAY)
+    );
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "config_change_proposed_event");
+    assert_eq!(data[0]["key"].as_str().unwrap(), "FEE");
+}
+
+#[test]
+fn test_fee_decrease_is_immediate_and_cancels_pending_increase() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee(1500);
+
+    staker.set_fee(800);
+
+    assert_eq!(staker.fee, 800);
+    assert!(staker.get_pending_config_changes().is_empty());
+    let events: Vec<String> = get_logs()
+        .iter()
+        .filter(|log| log.starts_with("EVENT_JSON"))
+        .map(|log| fetch_event(log).1)
+        .collect();
+    assert_eq!(events[events.len() - 2], "config_change_cancelled_event");
+    assert_eq!(events[events.len() - 1], "set_fee_event");
+}
+
+#[test]
+fn test_fee_decrease_collects_fees_at_previous_fee() {
+    let mut staker = setup_staker_with_holders();
+
+    // rewards accrue at the current fee of 10%
+    staker.total_staked = 210 * ONE_NEAR;
+    let share_price = staker.current_share_price();
+
+    staker.set_fee(800);
+
+    // the accrued rewards were charged the previous fee
+    assert_eq!(staker.fee, 800);
+    assert_eq!(staker.tax_exempt_stake, staker.total_staked);
+    assert_eq!(staker.current_share_price().scaled(), share_price.scaled());
+    assert!(staker.ft_balance_of(accounts(1)).0 > 0);
+}
+
+#[test]
+fn test_fee_increase_above_max_fee_increase_fails() {
+    let mut staker = setup_staker_with_holders();
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_fee(1000 + DEFAULT_MAX_FEE_INCREASE + 1);
+        })),
+        ERR_FEE_INCREASE_TOO_LARGE,
+    );
+}
+
+#[test]
+fn test_execute_config_change() {
+    let mut staker = setup_staker_with_holders();
+    staker.total_staked_last_updated_at = DEFAULT_CONFIG_CHANGE_DELAY;
+    staker.set_distribution_fee(200);
+
+    let mut context = get_context(accounts(0));
+    context.epoch_height(DEFAULT_CONFIG_CHANGE_DELAY);
+    testing_env!(context.build());
+    staker.execute_config_change(ConfigKey::DISTRIBUTION_FEE);
+
+    assert_eq!(staker.distribution_fee, 200);
+    assert!(staker.get_pending_config_changes().is_empty());
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "config_change_executed_event");
+    assert_eq!(data[0]["new_value"].as_str().unwrap(), "200");
+}
+
+#[test]
+fn test_execute_fee_change_collects_fees_at_previous_fee() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee(1500);
+
+    // rewards accrue while the fee increase is pending
+    staker.total_staked = 210 * ONE_NEAR;
+    staker.total_staked_last_updated_at = DEFAULT_CONFIG_CHANGE_DELAY;
+    let share_price = staker.current_share_price();
+
+    let mut context = get_context(accounts(0));
+    context.epoch_height(DEFAULT_CONFIG_CHANGE_DELAY);
+    testing_env!(context.build());
+    staker.execute_config_change(ConfigKey::FEE);
+
+    // the accrued rewards were charged the previous fee
+    assert_eq!(staker.fee, 1500);
+    assert_eq!(staker.tax_exempt_stake, staker.total_staked);
+    assert_eq!(staker.current_share_price().scaled(), share_price.scaled());
+}
+
+#[test]
+fn test_execute_config_change_before_delay_fails() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_distribution_fee(200);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.execute_config_change(ConfigKey::DISTRIBUTION_FEE);
+        })),
+        ERR_CONFIG_CHANGE_NOT_READY,
+    );
+}
+
+#[test]
+fn test_cancel_config_change() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_distribution_fee(200);
+
+    staker.cancel_config_change(ConfigKey::DISTRIBUTION_FEE);
+
+    assert!(staker.get_pending_config_changes().is_empty());
+    assert_eq!(staker.distribution_fee, 0);
+}
+
+#[test]
+fn test_cancel_missing_config_change_fails() {
+    let mut staker = setup_staker_with_holders();
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.cancel_config_change(ConfigKey::FEE);
+        })),
+        ERR_NO_PENDING_CONFIG_CHANGE,
+    );
+}
+
+#[test]
+fn test_config_change_delay_reduction_is_timelocked() {
+    let mut staker = setup_staker_with_holders();
+
+    // increasing the delay is immediate
+    staker.set_config_change_delay(U64(20));
+    assert_eq!(staker.config_change_delay, 20);
+
+    // reducing the delay is proposed
+    staker.set_config_change_delay(U64(0));
+    assert_eq!(staker.config_change_delay, 20);
+    assert_eq!(
+        staker.get_pending_config_changes()[0].key,
+        ConfigKey::CONFIG_CHANGE_DELAY
+    );
+}
+
+#[test]
+fn test_max_fee_increase_raise_is_timelocked() {
+    let mut staker = setup_staker_with_holders();
+
+    staker.set_max_fee_increase(2000);
+
+    assert_eq!(staker.max_fee_increase, DEFAULT_MAX_FEE_INCREASE);
+    assert_eq!(
+        staker.get_pending_config_changes()[0].key,
+        ConfigKey::MAX_FEE_INCREASE
+    );
+}
+
+#[test]
+fn test_execute_config_change_by_non_owner_fails() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_distribution_fee(200);
+    specify_signer(4);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.execute_config_change(ConfigKey::DISTRIBUTION_FEE);
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+/// Mocks the promise results of a total staked update with the given account total balance on each pool.
+fn mock_total_staked_update(account_total_balances: Vec<u128>) {
+    mock_total_staked_update_at(account_total_balances, 0, 0);
+}
+
+fn mock_total_staked_update_at(
+    account_total_balances: Vec<u128>,
+    epoch: u64,
+    block_timestamp: u64,
+) {
+    let promise_results = account_total_balances
+        .into_iter()
+        .map(|balance| PromiseResult::Successful(serde_json::to_vec(&U128(balance)).unwrap()))
+        .collect();
+    testing_env!(
+        get_context(accounts(0))
+            .epoch_height(epoch)
+            .block_timestamp(block_timestamp)
+            .build(),
+        near_sdk::test_vm_config(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        promise_results,
+    );
+}
+
+#[test]
+fn test_get_pending_fees() {
+    let mut staker = setup_staker_with_holders();
+    staker.total_staked = 210 * ONE_NEAR;
+
+    let pending_fees = staker.get_pending_fees();
+    staker.internal_collect_fees();
+
+    // 10% of the 10 NEAR rewards
+    assert_eq!(pending_fees.near_amount, U128(ONE_NEAR));
+    assert_eq!(
+        pending_fees.shares_amount,
+        staker.ft_balance_of(accounts(1))
+    );
+    assert_eq!(pending_fees.rebated_shares, U128(0));
+    assert_eq!(staker.get_pending_fees().near_amount, U128(0));
+}
+
+#[test]
+fn test_get_pending_fees_with_fee_override() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_fee_override(accounts(3), 0, 0);
+    staker.total_staked = 210 * ONE_NEAR;
+    staker.internal_collect_fees();
+    let treasury_balance = staker.ft_balance_of(accounts(1)).0;
+    staker.total_staked = 230 * ONE_NEAR;
+
+    let pending_fees = staker.get_pending_fees();
+    staker.internal_collect_fees();
+
+    assert_eq!(
+        pending_fees.shares_amount.0,
+        staker.ft_balance_of(accounts(1)).0 - treasury_balance
+    );
+    assert_eq!(
+        pending_fees.rebated_shares.0,
+        staker.ft_balance_of(accounts(3)).0 - 100 * ONE_NEAR
+    );
+    assert!(pending_fees.rebated_shares.0 > 0);
+}
+
+#[test]
+fn test_set_auto_collect_fees() {
+    let mut staker = setup_staker_with_holders();
+
+    staker.set_auto_collect_fees(true);
+
+    assert!(staker.auto_collect_fees);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "set_auto_collect_fees_event");
+    assert!(data[0]["new_auto_collect_fees"].as_bool().unwrap());
+}
+
+#[test]
+fn test_set_auto_collect_fees_by_non_owner_fails() {
+    let mut staker = setup_staker_with_holders();
+    specify_signer(4);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_auto_collect_fees(true);
+        })),
+        "Only the owner can call this method",
+    );
+}
+
+#[test]
+fn test_total_staked_callback_collects_fees_when_enabled() {
+    let mut staker = setup_staker_with_holders();
+    staker.set_auto_collect_fees(true);
+
+    mock_total_staked_update(vec![210 * ONE_NEAR]);
+    staker.total_staked_callback(U128(0), 0);
+
+    assert_eq!(staker.total_staked, 210 * ONE_NEAR);
+    assert_eq!(staker.tax_exempt_stake, 210 * ONE_NEAR);
+    assert!(staker.ft_balance_of(accounts(1)).0 > 0);
+    assert!(get_logs()
+        .iter()
+        .any(|log| log.contains("fees_collected_event")));
+}
+
+#[test]
+fn test_total_staked_callback_does_not_collect_fees_when_disabled() {
+    let mut staker = setup_staker_with_holders();
+
+    mock_total_staked_update(vec![210 * ONE_NEAR]);
+    staker.total_staked_callback(U128(0), 0);
+
+    assert_eq!(staker.total_staked, 210 * ONE_NEAR);
+    assert_eq!(staker.tax_exempt_stake, 200 * ONE_NEAR);
+    assert_eq!(staker.ft_balance_of(accounts(1)), U128(0));
+}
+
+const HALF_YEAR: u64 = NANOSECONDS_PER_YEAR / 2;
+
+#[test]
+fn test_set_management_fee() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    staker.set_management_fee(200);
+
+    assert_eq!(staker.management_fee, 200);
+    assert_eq!(staker.get_staker_info().management_fee, 200);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "set_management_fee_event");
+    assert_eq!(data[0]["old_management_fee"].as_u64().unwrap(), 0);
+    assert_eq!(data[0]["new_management_fee"].as_u64().unwrap(), 200);
+}
+
+#[test]
+fn test_set_management_fee_too_large_fails() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_management_fee(FEE_PRECISION);
+        })),
+        ERR_FEE_TOO_LARGE,
+    );
+}
+
+#[test]
+fn test_management_fee_accrues_over_multiple_syncs() {
+    let mut staker = setup_staker_with_holders();
+    staker.management_fee = 200;
+
+    // 2% per year on 200 NEAR over half a year
+    mock_total_staked_update_at(vec![200 * ONE_NEAR], 1, HALF_YEAR);
+    staker.total_staked_callback(U128(0), 1);
+    assert_eq!(staker.accrued_management_fees, 2 * ONE_NEAR);
+    assert!(get_logs()
+        .iter()
+        .any(|log| log.contains("management_fee_accrued_event")));
+
+    // the accrued fees are not charged the management fee again
+    mock_total_staked_update_at(vec![200 * ONE_NEAR], 2, NANOSECONDS_PER_YEAR);
+    staker.total_staked_callback(U128(0), 2);
+    assert_eq!(
+        staker.accrued_management_fees,
+        2 * ONE_NEAR + 198 * ONE_NEAR / 100
+    );
+    assert_eq!(staker.management_fee_accrued_at, NANOSECONDS_PER_YEAR);
+
+    // no time has elapsed, so nothing accrues
+    mock_total_staked_update_at(vec![200 * ONE_NEAR], 3, NANOSECONDS_PER_YEAR);
+    staker.total_staked_callback(U128(0), 3);
+    assert_eq!(
+        staker.accrued_management_fees,
+        2 * ONE_NEAR + 198 * ONE_NEAR / 100
+    );
+}
+
+#[test]
+fn test_management_fee_set_part_way_accrues_from_execution() {
+    let mut staker = setup_staker_with_holders();
+
+    // no management fee accrues before it is set
+    mock_total_staked_update_at(vec![200 * ONE_NEAR], 1, HALF_YEAR);
+    staker.total_staked_callback(U128(0), 1);
+    assert_eq!(staker.accrued_management_fees, 0);
+
+    // TruNEAR is in circulation, so the fee increase is timelocked
+    staker.set_management_fee(100);
+    assert_eq!(staker.management_fee, 0);
+    testing_env!(get_context(accounts(0))
+        .epoch_height(DEFAULT_CONFIG_CHANGE_DELAY + 1)
+        .block_timestamp(NANOSECONDS_PER_YEAR)
+        .build());
+    staker.execute_config_change(ConfigKey::MANAGEMENT_FEE);
+    assert_eq!(staker.management_fee, 100);
+    assert_eq!(staker.accrued_management_fees, 0);
+    assert_eq!(staker.management_fee_accrued_at, NANOSECONDS_PER_YEAR);
+
+    // 1% per year on 200 NEAR over the year since execution
+    mock_total_staked_update_at(
+        vec![200 * ONE_NEAR],
+        DEFAULT_CONFIG_CHANGE_DELAY + 2,
+        2 * NANOSECONDS_PER_YEAR,
+    );
+    staker.total_staked_callback(U128(0), DEFAULT_CONFIG_CHANGE_DELAY + 2);
+    assert_eq!(staker.accrued_management_fees, 2 * ONE_NEAR);
+}
+
+#[test]
+fn test_management_fee_change_accrues_at_previous_fee() {
+    specify_signer(0);
+    let mut staker = NearStaker::new(accounts(0), accounts(1), accounts(2));
+    staker.set_management_fee(100);
+    staker.total_staked = 100 * ONE_NEAR;
+
+    testing_env!(get_context(accounts(0))
+        .block_timestamp(NANOSECONDS_PER_YEAR)
+        .build());
+    staker.set_management_fee(300);
+
+    assert_eq!(staker.accrued_management_fees, ONE_NEAR);
+    assert_eq!(staker.management_fee, 300);
+}
+
+#[test]
+fn test_share_price_falls_after_management_fee_accrual() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.management_fee = 200;
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(4), U128(20 * ONE_NEAR));
+    staker.set_allocation_expiry(accounts(4), Some(U64(1)));
+    let allocation_price = staker.current_share_price();
+
+    // the management fee accrued without rewards lowers the share price
+    mock_total_staked_update_at(vec![200 * ONE_NEAR], 1, HALF_YEAR);
+    staker.total_staked_callback(U128(0), 1);
+    assert!(staker.current_share_price().scaled() < allocation_price.scaled());
+
+    // distributing while the share price is lower keeps the allocation share price
+    testing_env!(get_context(accounts(3)).epoch_height(1).build());
+    staker.distribute_all(false);
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+    assert_eq!(
+        staker
+            .internal_allocation(&accounts(3), &accounts(5))
+            .unwrap()
+            .share_price
+            .scaled(),
+        allocation_price.scaled()
+    );
+
+    // only the rewards above the allocation share price are distributed, and the expired allocation
+    // stays capped at the share price recorded at its expiry
+    staker.total_staked = 220 * ONE_NEAR;
+    let expected_shares = NearStaker::internal_calculate_distribution_amount(
+        &staker
+            .internal_allocation(&accounts(3), &accounts(5))
+            .unwrap(),
+        staker.current_share_price(),
+    );
+    staker.distribute_all(false);
+    assert!(expected_shares > 0);
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(expected_shares));
+    assert_eq!(staker.ft_balance_of(accounts(4)).0, 100 * ONE_NEAR);
+}
+
+#[test]
+fn test_collect_management_fees_is_share_price_neutral() {
+    let mut staker = setup_staker_with_holders();
+    staker.management_fee = 200;
+
+    // 10 NEAR rewards and 2% per year on 210 NEAR over half a year
+    mock_total_staked_update_at(vec![210 * ONE_NEAR], 1, HALF_YEAR);
+    staker.total_staked_callback(U128(0), 1);
+    assert_eq!(staker.accrued_management_fees, 2 * ONE_NEAR + ONE_NEAR / 10);
+    let share_price = staker.current_share_price();
+
+    let pending_fees = staker.get_pending_fees();
+    staker.internal_collect_fees();
+
+    // the holders keep the same value, and the treasury receives the performance and management fees
+    let shares_value = |staker: &NearStaker, shares: u128| {
+        staker
+            .current_share_price()
+            .convert_to_assets(shares, Rounding::Down)
+    };
+    assert!(
+        share_price
+            .convert_to_assets(100 * ONE_NEAR, Rounding::Down)
+            .abs_diff(shares_value(&staker, 100 * ONE_NEAR))
+            <= 1
+    );
+    assert_eq!(staker.accrued_management_fees, 0);
+    assert_eq!(staker.tax_exempt_stake, 210 * ONE_NEAR);
+    assert_eq!(
+        pending_fees.near_amount,
+        U128(ONE_NEAR + 2 * ONE_NEAR + ONE_NEAR / 10)
+    );
+    assert_eq!(
+        pending_fees.shares_amount,
+        staker.ft_balance_of(accounts(1))
+    );
+    let treasury_value = shares_value(&staker, staker.ft_balance_of(accounts(1)).0);
+    assert!((3 * ONE_NEAR + ONE_NEAR / 10).abs_diff(treasury_value) <= 1);
+    assert_eq!(staker.get_pending_fees().near_amount, U128(0));
+}
+
+fn setup_whitelisted_allocator() -> NearStaker {
+    let mut staker = setup_staker_with_holders();
+    staker.add_user_to_whitelist(accounts(3));
+    // recipients are paid without accepting their allocations, consent is covered by the recipient consent tests
+    staker.set_recipient_consent_required(false);
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_near(1))
+        .build());
+    staker
+}
+
+#[test]
+fn test_allocate_percentage() {
+    let mut staker = setup_whitelisted_allocator();
+
+    staker.allocate_percentage(accounts(5), 3000);
+
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].recipient, accounts(5));
+    assert_eq!(allocations[0].near_amount, U128(30 * ONE_NEAR));
+    assert_eq!(allocations[0].basis_points, Some(3000));
+    assert_eq!(
+        staker.get_total_allocated(accounts(3)).0,
+        U128(30 * ONE_NEAR)
+    );
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocated_percentage_event");
+    assert_eq!(data[0]["basis_points"].as_u64().unwrap(), 3000);
+    assert_eq!(data[0]["total_basis_points"].as_u64().unwrap(), 3000);
+    assert_eq!(
+        data[0]["total_allocated_basis_points"].as_u64().unwrap(),
+        3000
+    );
+}
+
+#[test]
+fn test_allocate_percentage_above_one_hundred_percent_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 6000);
+    staker.allocate_percentage(accounts(2), 3000);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate_percentage(accounts(5), 1001);
+        })),
+        ERR_ALLOCATION_PERCENTAGE_EXCEEDED,
+    );
+}
+
+#[test]
+fn test_allocate_percentage_to_fixed_allocation_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate_percentage(accounts(5), 1000);
+        })),
+        ERR_ALLOCATION_TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn test_allocate_to_percentage_allocation_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 1000);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+        })),
+        ERR_ALLOCATION_TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn test_percentage_allocation_follows_trunear_position() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 3000);
+    let allocation_price = staker.current_share_price();
+    staker.total_staked = 220 * ONE_NEAR;
+    let global_price = staker.current_share_price();
+
+    let fixed_allocation = |near_amount: u128| Allocation {
+        near_amount,
+        share_price: allocation_price,
+    };
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert_eq!(
+        required_shares.0,
+        NearStaker::internal_calculate_distribution_amount(
+            &fixed_allocation(30 * ONE_NEAR),
+            global_price
+        )
+    );
+
+    // the allocated amount doubles with the allocator's TruNEAR balance, but the deposited TruNEAR
+    // doesn't earn the rewards accrued before the deposit
+    let deposit = global_price.convert_to_assets(100 * ONE_NEAR, Rounding::Up);
+    staker.total_staked += deposit;
+    staker.tax_exempt_stake += deposit;
+    staker.internal_mint(100 * ONE_NEAR, accounts(3));
+    let deposit_price = staker.current_share_price();
+    let (rewards_at_deposit, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), None, false);
+    assert!(rewards_at_deposit.0.abs_diff(required_shares.0) <= 1);
+
+    // the rewards accrued after the deposit are earned on the doubled allocation
+    staker.total_staked += 40 * ONE_NEAR;
+    let global_price = staker.current_share_price();
+    let (required_shares, _) = staker.get_rewards_distribution_amounts(&accounts(3), None, false);
+    let added_allocation = Allocation {
+        near_amount: deposit_price.convert_to_assets(30 * ONE_NEAR, Rounding::Down),
+        share_price: deposit_price,
+    };
+    let expected_shares =
+        NearStaker::internal_calculate_distribution_amount(
+            &fixed_allocation(30 * ONE_NEAR),
+            global_price,
+        ) + NearStaker::internal_calculate_distribution_amount(&added_allocation, global_price);
+    assert!(required_shares.0.abs_diff(expected_shares) <= 2);
+}
+
+#[test]
+fn test_percentage_allocation_is_not_paid_retroactively_on_received_trunear() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 3000);
+    staker.total_staked = 220 * ONE_NEAR;
+    let (pending_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    // TruNEAR received after the rewards accrued only increases the allocation from now on
+    testing_env!(get_context(accounts(4))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+    staker.ft_transfer(accounts(3), U128(100 * ONE_NEAR), None);
+
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert!(required_shares.0.abs_diff(pending_shares.0) <= 1);
+    assert_eq!(
+        staker.get_allocations(accounts(3), None, None)[0].basis_points,
+        Some(3000)
+    );
+}
+
+#[test]
+fn test_distribute_rewards_to_percentage_allocation() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 3000);
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert!(required_shares.0 > 0);
+
+    staker.distribute_rewards(accounts(5), false);
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), required_shares);
+    assert_eq!(
+        staker.ft_balance_of(accounts(3)).0,
+        100 * ONE_NEAR - required_shares.0
+    );
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert_eq!(required_shares, U128(0));
+}
+
+#[test]
+fn test_increasing_allocated_percentage_preserves_pending_rewards() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 3000);
+    staker.total_staked = 220 * ONE_NEAR;
+    let (pending_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    staker.allocate_percentage(accounts(5), 1000);
+
+    assert_eq!(
+        staker.get_allocations(accounts(3), None, None)[0].basis_points,
+        Some(4000)
+    );
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert!(required_shares.0.abs_diff(pending_shares.0) <= 1);
+}
+
+#[test]
+fn test_deallocate_percentage() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 3000);
+
+    staker.deallocate_percentage(accounts(5), 1000);
+    assert_eq!(
+        staker.get_allocations(accounts(3), None, None)[0].basis_points,
+        Some(2000)
+    );
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "deallocated_percentage_event");
+    assert_eq!(data[0]["total_basis_points"].as_u64().unwrap(), 2000);
+
+    staker.deallocate_percentage(accounts(5), 2000);
+    assert!(staker.get_allocations(accounts(3), None, None).is_empty());
+    assert_eq!(
+        staker.internal_total_allocated_basis_points(&accounts(3)),
+        0
+    );
+
+    // a fixed allocation can now be made to the recipient
+    staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+}
+
+#[test]
+fn test_deallocate_fixed_amount_from_percentage_allocation_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_percentage(accounts(5), 3000);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.deallocate(accounts(5), U128(ONE_NEAR));
+        })),
+        ERR_ALLOCATION_TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn test_allocate_escrowed_locks_trunear() {
+    let mut staker = setup_whitelisted_allocator();
+
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+
+    assert_eq!(staker.get_locked_balance(accounts(3)), U128(40 * ONE_NEAR));
+    assert_eq!(staker.max_withdraw(accounts(3)), U128(60 * ONE_NEAR));
+    assert!(staker.get_allocations(accounts(3), None, None)[0].escrowed);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "shares_locked_event");
+    assert_eq!(data[0]["shares"], (40 * ONE_NEAR).to_string());
+    assert_eq!(data[0]["locked_balance"], (40 * ONE_NEAR).to_string());
+}
+
+#[test]
+fn test_allocate_escrowed_above_unlocked_balance_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(60 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate_escrowed(accounts(2), U128(41 * ONE_NEAR));
+        })),
+        ERR_INSUFFICIENT_UNLOCKED_TRUNEAR_BALANCE,
+    );
+}
+
+#[test]
+fn test_allocate_to_escrowed_allocation_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(10 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+        })),
+        ERR_ALLOCATION_TYPE_MISMATCH,
+    );
+}
+
+#[test]
+fn test_transfer_of_locked_trunear_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+    staker.ft_transfer(accounts(4), U128(60 * ONE_NEAR), None);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.ft_transfer(accounts(4), U128(1), None);
+        })),
+        ERR_INSUFFICIENT_UNLOCKED_TRUNEAR_BALANCE,
+    );
+}
+
+fn setup_recipient_consent() -> NearStaker {
+    let mut staker = setup_staker_with_holders();
+    staker.add_user_to_whitelist(accounts(3));
+    staker.add_user_to_whitelist(accounts(4));
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_near(1))
+        .build());
+    staker
+}
+
+#[test]
+fn test_set_recipient_consent_required() {
+    let mut staker = setup_staker_with_holders();
+    assert!(staker.is_recipient_consent_required());
+
+    staker.set_recipient_consent_required(false);
+
+    assert!(!staker.is_recipient_consent_required());
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "recipient_consent_required_set_event");
+    assert_eq!(data[0]["required"], false);
+}
+
+#[test]
+fn test_set_recipient_consent_required_by_non_owner_fails() {
+    let mut staker = setup_whitelisted_allocator();
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_recipient_consent_required(true);
+        })),
+        ERR_ONLY_OWNER,
+    );
+}
+
+#[test]
+fn test_allocation_to_unvetted_recipient_is_pending() {
+    let mut staker = setup_recipient_consent();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(4), U128(20 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+
+    // whitelisted recipients don't need to accept their allocations
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert!(allocations[1].pending);
+    assert!(!allocations[0].pending);
+    assert!(staker.get_allocations_to(accounts(5), None, None)[0].pending);
+
+    // pending allocations are skipped by distributions and don't require funds
+    let (required_shares, _) = staker.get_rewards_distribution_amounts(&accounts(3), None, false);
+    assert_eq!(
+        required_shares,
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(4)), false)
+            .0
+    );
+    staker.distribute_all(false);
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+
+    let global_price = staker.current_share_price();
+    let distribution = staker.internal_distribute(
+        accounts(3),
+        accounts(5),
+        global_price,
+        false,
+        NearToken::from_near(0),
+        None,
+    );
+    assert_eq!(
+        distribution.err().unwrap().to_string(),
+        ERR_ALLOCATION_PENDING
+    );
+}
+
+#[test]
+fn test_accept_allocation() {
+    let mut staker = setup_recipient_consent();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+
+    testing_env!(get_context(accounts(5)).build());
+    staker.accept_allocation(accounts(3));
+
+    assert!(!staker.get_allocations(accounts(3), None, None)[0].pending);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocation_accepted_event");
+    assert_eq!(data[0]["user"], accounts(3).to_string());
+
+    testing_env!(get_context(accounts(3)).build());
+    staker.distribute_rewards(accounts(5), false);
+    assert!(staker.ft_balance_of(accounts(5)).0 > 0);
+}
+
+#[test]
+fn test_accept_allocation_that_is_not_pending_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    testing_env!(get_context(accounts(5)).build());
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.accept_allocation(accounts(3));
+        })),
+        ERR_ALLOCATION_NOT_PENDING,
+    );
+}
+
+#[test]
+fn test_reject_allocation() {
+    let mut staker = setup_recipient_consent();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+
+    testing_env!(get_context(accounts(5)).build());
+    staker.reject_allocation(accounts(3));
+
+    assert!(staker.get_allocations(accounts(3), None, None).is_empty());
+    assert!(staker
+        .get_allocations_to(accounts(5), None, None)
+        .is_empty());
+    assert!(!staker.internal_is_pending_allocation(&accounts(3), &accounts(5)));
+    assert_eq!(staker.get_total_allocated(accounts(3)).0, U128(0));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocation_rejected_event");
+    assert_eq!(data[0]["near_amount"], (40 * ONE_NEAR).to_string());
+    assert_eq!(data[0]["pending"], true);
+}
+
+#[test]
+fn test_leave_escrowed_allocation_unlocks_trunear() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+
+    testing_env!(get_context(accounts(5)).build());
+    staker.reject_allocation(accounts(3));
+
+    assert_eq!(staker.get_locked_balance(accounts(3)), U128(0));
+    let logs = get_logs();
+    let (data, event) = fetch_event(&logs[logs.len() - 2]);
+    assert_eq!(event, "allocation_rejected_event");
+    assert_eq!(data[0]["pending"], false);
+    let (_, event) = fetch_event(logs.last().unwrap());
+    assert_eq!(event, "shares_unlocked_event");
+}
+
+#[test]
+fn test_reject_allocation_without_allocation_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    testing_env!(get_context(accounts(4)).build());
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.reject_allocation(accounts(3));
+        })),
+        ERR_NO_ALLOCATIONS_TO_RECIPIENT,
+    );
+}
+
+#[test]
+fn test_set_strict_allocation_mode() {
+    let mut staker = setup_whitelisted_allocator();
+
+    staker.set_strict_allocation_mode(true);
+
+    assert!(staker.is_strict_allocation_mode(accounts(3)));
+    assert!(!staker.is_strict_allocation_mode(accounts(4)));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "strict_allocation_mode_set_event");
+    assert_eq!(data[0]["enabled"], true);
+
+    staker.set_strict_allocation_mode(false);
+    assert!(!staker.is_strict_allocation_mode(accounts(3)));
+}
+
+#[test]
+fn test_enable_strict_allocation_mode_when_over_allocated_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(150 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_strict_allocation_mode(true);
+        })),
+        ERR_ALLOCATION_NOT_COVERED,
+    );
+}
+
+#[test]
+fn test_strict_allocation_mode_allocate_above_balance_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.set_strict_allocation_mode(true);
+    staker.allocate(accounts(5), U128(60 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(40 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate(accounts(2), U128(ONE_NEAR));
+        })),
+        ERR_ALLOCATION_NOT_COVERED,
+    );
+}
+
+#[test]
+fn test_strict_allocation_mode_excludes_escrowed_allocations() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.set_strict_allocation_mode(true);
+
+    // the escrowed allocation is backed by locked TruNEAR, so the rest of the balance covers the other allocation
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(60 * ONE_NEAR));
+
+    assert_eq!(
+        staker.internal_strict_allocated_amount(&accounts(3)),
+        60 * ONE_NEAR
+    );
+}
+
+#[test]
+fn test_strict_allocation_mode_transfer_below_allocated_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.set_strict_allocation_mode(true);
+    staker.allocate(accounts(5), U128(60 * ONE_NEAR));
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+    staker.ft_transfer(accounts(4), U128(40 * ONE_NEAR), None);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.ft_transfer(accounts(4), U128(1), None);
+        })),
+        ERR_ALLOCATION_NOT_COVERED,
+    );
+}
+
+#[test]
+fn test_strict_allocation_mode_unstake_below_allocated_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.set_strict_allocation_mode(true);
+    staker.allocate(accounts(5), U128(60 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.internal_check_unstake_amount(&accounts(2), 41 * ONE_NEAR, &accounts(3));
+        })),
+        ERR_ALLOCATION_NOT_COVERED,
+    );
+}
+
+#[test]
+fn test_transfer_below_allocated_without_strict_allocation_mode() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(60 * ONE_NEAR));
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+
+    staker.ft_transfer(accounts(4), U128(100 * ONE_NEAR), None);
+
+    assert_eq!(staker.ft_balance_of(accounts(3)), U128(0));
+}
+
+#[test]
+fn test_escrowed_rewards_are_paid_from_locked_trunear() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(60 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    // the unlocked TruNEAR is transferred away, but the escrowed rewards can still be distributed
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+    staker.ft_transfer(accounts(4), U128(60 * ONE_NEAR), None);
+    staker.distribute_rewards(accounts(5), false);
+    assert_eq!(staker.ft_balance_of(accounts(5)), required_shares);
+
+    // the locked TruNEAR now backs the allocated amount at the current share price
+    let locked_shares = staker.get_locked_balance(accounts(3)).0;
+    assert_eq!(locked_shares, 40 * ONE_NEAR - required_shares.0);
+    assert_eq!(staker.ft_balance_of(accounts(3)).0, locked_shares);
+
+    // the other allocations cannot be paid from the locked TruNEAR
+    let global_price = staker.current_share_price();
+    let distribution = staker.internal_distribute(
+        accounts(3),
+        accounts(2),
+        global_price,
+        false,
+        NearToken::from_near(0),
+        None,
+    );
+    assert_eq!(
+        distribution.err().unwrap().to_string(),
+        ERR_INSUFFICIENT_TRUNEAR_BALANCE
+    );
+}
+
+#[test]
+fn test_deallocate_escrowed_distributes_rewards_and_releases_principal() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    staker.deallocate(accounts(5), U128(40 * ONE_NEAR));
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), required_shares);
+    assert_eq!(staker.get_locked_balance(accounts(3)), U128(0));
+    assert!(staker.get_allocations(accounts(3), None, None).is_empty());
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "shares_unlocked_event");
+    assert_eq!(
+        data[0]["shares"],
+        (40 * ONE_NEAR - required_shares.0).to_string()
+    );
+    assert_eq!(data[0]["locked_balance"], "0");
+}
+
+#[test]
+fn test_deallocate_escrowed_while_locked_distributes_rewards() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    staker.is_locked = true;
+
+    staker.deallocate(accounts(5), U128(40 * ONE_NEAR));
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), required_shares);
+    assert_eq!(staker.get_locked_balance(accounts(3)), U128(0));
+    assert!(staker.get_allocations(accounts(3), None, None).is_empty());
+}
+
+#[test]
+fn test_approve_distribution() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+
+    staker.approve_distribution(accounts(5), U128(ONE_NEAR));
+
+    assert_eq!(
+        staker.get_distribution_allowance(accounts(3), accounts(5)),
+        U128(ONE_NEAR)
+    );
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distribution_allowance_set_event");
+    assert_eq!(data[0]["old_allowance"], "0");
+    assert_eq!(data[0]["new_allowance"], ONE_NEAR.to_string());
+}
+
+#[test]
+fn test_approve_distribution_without_allocation_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.approve_distribution(accounts(2), U128(ONE_NEAR));
+        })),
+        ERR_NO_ALLOCATIONS_TO_RECIPIENT,
+    );
+}
+
+#[test]
+fn test_claim_allocation_rewards() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.approve_distribution(accounts(5), U128(10 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    specify_signer(5);
+    staker.claim_allocation_rewards(accounts(3));
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), required_shares);
+    assert_eq!(
+        staker.get_distribution_allowance(accounts(3), accounts(5)),
+        U128(10 * ONE_NEAR - required_shares.0)
+    );
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocation_rewards_claimed_event");
+    assert_eq!(data[0]["shares"], required_shares.0.to_string());
+}
+
+#[test]
+fn test_claim_allocation_rewards_is_capped_at_allowance() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    let allowance = required_shares.0 / 3;
+    staker.approve_distribution(accounts(5), U128(allowance));
+
+    specify_signer(5);
+    staker.claim_allocation_rewards(accounts(3));
+
+    // the rewards above the allowance remain to be distributed
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(allowance));
+    assert_eq!(
+        staker.get_distribution_allowance(accounts(3), accounts(5)),
+        U128(0)
+    );
+    let (remaining_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert!(remaining_shares.0.abs_diff(required_shares.0 - allowance) <= 1);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.claim_allocation_rewards(accounts(3));
+        })),
+        ERR_NO_DISTRIBUTION_ALLOWANCE,
+    );
+}
+
+#[test]
+fn test_deallocate_removes_distribution_allowance() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.approve_distribution(accounts(5), U128(ONE_NEAR));
+
+    staker.deallocate(accounts(5), U128(40 * ONE_NEAR));
+
+    assert_eq!(
+        staker.get_distribution_allowance(accounts(3), accounts(5)),
+        U128(0)
+    );
+}
+
+/// Returns the NEAR transferred to the account by the receipts created in the current context.
+fn transferred_to(account_id: AccountId) -> u128 {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .filter(|receipt| receipt.receiver_id == account_id)
+        .flat_map(|receipt| receipt.actions)
+        .map(|action| match action {
+            near_sdk::mock::MockAction::Transfer { deposit, .. } => deposit.as_yoctonear(),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[test]
+fn test_deallocate_refunds_recorded_storage_deposit() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(40 * ONE_NEAR));
+    assert_eq!(
+        staker
+            .allocation_storage_deposits
+            .get(&(accounts(3), accounts(5)))
+            .copied(),
+        Some(NearStaker::get_allocation_storage_cost().0)
+    );
+
+    testing_env!(get_context(accounts(3)).build());
+    staker.deallocate(accounts(5), U128(40 * ONE_NEAR));
+    assert_eq!(
+        transferred_to(accounts(3)),
+        NearStaker::get_allocation_storage_cost().0
+    );
+
+    // allocations made before deposits were recorded paid the unstake storage cost
+    staker
+        .allocation_storage_deposits
+        .remove(&(accounts(3), accounts(2)));
+    testing_env!(get_context(accounts(3)).build());
+    staker.deallocate(accounts(2), U128(40 * ONE_NEAR));
+    assert_eq!(
+        transferred_to(accounts(3)),
+        NearStaker::get_storage_cost().0
+    );
+}
+
+fn setup_keeper_distributions() -> NearStaker {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.set_distribution_keeper(accounts(4), U64(2));
+    staker
+}
+
+fn keeper_call_at_epoch(staker: &mut NearStaker, epoch: u64, total_staked: u128) {
+    testing_env!(get_context(accounts(4)).epoch_height(epoch).build());
+    staker.total_staked = total_staked;
+    staker.total_staked_last_updated_at = epoch;
+}
+
+#[test]
+fn test_set_distribution_keeper() {
+    let staker = setup_keeper_distributions();
+
+    let schedule = staker.get_distribution_keeper(accounts(3)).unwrap();
+    assert_eq!(schedule.keeper, accounts(4));
+    assert_eq!(schedule.interval, U64(2));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distribution_keeper_set_event");
+    assert_eq!(data[0]["keeper"], accounts(4).to_string());
+}
+
+#[test]
+fn test_distribute_all_for_by_non_keeper_fails() {
+    let mut staker = setup_keeper_distributions();
+    specify_signer(5);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.distribute_all_for(accounts(3), None, None);
+        })),
+        ERR_NOT_DISTRIBUTION_KEEPER,
+    );
+}
+
+#[test]
+fn test_distribute_all_for_enforces_interval() {
+    let mut staker = setup_keeper_distributions();
+
+    keeper_call_at_epoch(&mut staker, 1, 220 * ONE_NEAR);
+    staker.distribute_all_for(accounts(3), None, None);
+    assert!(staker.ft_balance_of(accounts(5)).0 > 0);
+    assert!(staker.ft_balance_of(accounts(2)).0 > 0);
+    assert_eq!(
+        staker.get_last_distribution_epoch(accounts(3), accounts(5)),
+        Some(U64(1))
+    );
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distributed_all_for_event");
+    assert_eq!(data[0]["distributions"].as_u64().unwrap(), 2);
+
+    // the interval has not elapsed yet
+    keeper_call_at_epoch(&mut staker, 2, 240 * ONE_NEAR);
+    let balance = staker.ft_balance_of(accounts(5));
+    staker.distribute_all_for(accounts(3), None, None);
+    assert_eq!(staker.ft_balance_of(accounts(5)), balance);
+    let (data, _) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(data[0]["distributions"].as_u64().unwrap(), 0);
+
+    keeper_call_at_epoch(&mut staker, 3, 240 * ONE_NEAR);
+    staker.distribute_all_for(accounts(3), None, None);
+    assert!(staker.ft_balance_of(accounts(5)).0 > balance.0);
+    assert_eq!(
+        staker.get_last_distribution_epoch(accounts(3), accounts(5)),
+        Some(U64(3))
+    );
+}
+
+#[test]
+fn test_distribute_all_for_is_paginated() {
+    let mut staker = setup_keeper_distributions();
+    keeper_call_at_epoch(&mut staker, 1, 220 * ONE_NEAR);
+
+    // recipients are ordered by account ID
+    staker.distribute_all_for(accounts(3), None, Some(1));
+    assert!(staker.ft_balance_of(accounts(2)).0 > 0);
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distributed_all_for_event");
+    assert_eq!(data[0]["next_recipient"], accounts(5).to_string());
+
+    staker.distribute_all_for(accounts(3), Some(accounts(5)), Some(1));
+    assert!(staker.ft_balance_of(accounts(5)).0 > 0);
+    let (data, _) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(data[0]["from_recipient"], accounts(5).to_string());
+    assert!(data[0]["next_recipient"].is_null());
+}
+
+#[test]
+fn test_distribute_batch_continues_from_cursor() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.allocate(accounts(4), U128(20 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+
+    // recipients are ordered by account ID
+    staker.distribute_batch(false, None, Some(2));
+    assert!(staker.ft_balance_of(accounts(2)).0 > 0);
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+    assert_eq!(
+        staker.get_distribution_cursor(accounts(3)),
+        Some(accounts(5))
+    );
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distributed_batch_event");
+    assert!(data[0]["from_recipient"].is_null());
+    assert_eq!(data[0]["next_recipient"], accounts(5).to_string());
+    assert_eq!(data[0]["distributions"], 2);
+
+    // the last batch wraps the cursor back to the first recipient
+    staker.distribute_batch(false, None, Some(2));
+    assert!(staker.ft_balance_of(accounts(5)).0 > 0);
+    assert_eq!(staker.get_distribution_cursor(accounts(3)), None);
+    let (data, _) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(data[0]["from_recipient"], accounts(5).to_string());
+    assert!(data[0]["next_recipient"].is_null());
+    assert_eq!(data[0]["distributions"], 1);
+}
+
+#[test]
+fn test_distribute_batch_cursor_follows_removed_and_added_recipients() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.allocate(accounts(4), U128(20 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    staker.distribute_batch(false, None, Some(2));
+    assert_eq!(
+        staker.get_distribution_cursor(accounts(3)),
+        Some(accounts(5))
+    );
+
+    // recipients added or removed before the cursor don't move it
+    staker.deallocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.allocate(accounts(1), U128(20 * ONE_NEAR));
+    assert_eq!(
+        staker.get_distribution_cursor(accounts(3)),
+        Some(accounts(5))
+    );
+
+    // the next batch continues from the recipient the previous batch stopped at
+    staker.distribute_batch(false, None, Some(2));
+    assert!(staker.ft_balance_of(accounts(5)).0 > 0);
+    assert_eq!(staker.ft_balance_of(accounts(1)), U128(0));
+    assert_eq!(staker.get_distribution_cursor(accounts(3)), None);
+}
+
+#[test]
+fn test_distribute_batch_continues_after_removed_cursor_recipient() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.allocate(accounts(4), U128(20 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    staker.distribute_batch(false, None, Some(1));
+    assert_eq!(
+        staker.get_distribution_cursor(accounts(3)),
+        Some(accounts(4))
+    );
+
+    // the batch starts at the first recipient after the removed cursor recipient
+    staker.deallocate(accounts(4), U128(20 * ONE_NEAR));
+    staker.distribute_batch(false, None, Some(1));
+    assert!(staker.ft_balance_of(accounts(5)).0 > 0);
+    assert_eq!(staker.get_distribution_cursor(accounts(3)), None);
+}
+
+#[test]
+fn test_distribute_batch_only_requires_batch_amounts() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (_, batch_near) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(2)), true);
+    let (_, total_near) = staker.get_rewards_distribution_amounts(&accounts(3), None, true);
+    assert!(batch_near.0 < total_near.0);
+
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(batch_near.0))
+        .build());
+    staker.distribute_batch(true, None, Some(1));
+
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(2)), true)
+            .1,
+        U128(0)
+    );
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), None, true)
+            .1
+             .0,
+        total_near.0 - batch_near.0
+    );
+}
+
+#[test]
+fn test_remove_distribution_keeper() {
+    let mut staker = setup_keeper_distributions();
+
+    staker.remove_distribution_keeper();
+
+    assert!(staker.get_distribution_keeper(accounts(3)).is_none());
+    keeper_call_at_epoch(&mut staker, 1, 220 * ONE_NEAR);
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.distribute_all_for(accounts(3), None, None);
+        })),
+        ERR_NO_DISTRIBUTION_KEEPER,
+    );
+}
+
+#[test]
+fn test_get_allocations_for_user_with_no_allocations() {
+    let staker = setup_whitelisted_allocator();
+
+    assert!(staker.get_allocations(accounts(3), None, None).is_empty());
+    assert!(staker
+        .get_allocations_to(accounts(5), None, None)
+        .is_empty());
+}
+
+#[test]
+fn test_get_allocations_is_paginated() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(ONE_NEAR));
+    staker.allocate(accounts(2), U128(2 * ONE_NEAR));
+    staker.allocate(accounts(4), U128(3 * ONE_NEAR));
+
+    // allocations are ordered by recipient
+    let recipients = |allocations: Vec<AllocationInfo>| {
+        allocations
+            .into_iter()
+            .map(|allocation| allocation.recipient)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        recipients(staker.get_allocations(accounts(3), None, None)),
+        vec![accounts(2), accounts(4), accounts(5)]
+    );
+    assert_eq!(
+        recipients(staker.get_allocations(accounts(3), Some(1), Some(1))),
+        vec![accounts(4)]
+    );
+    assert_eq!(
+        recipients(staker.get_allocations(accounts(3), Some(2), Some(5))),
+        vec![accounts(5)]
+    );
+    assert!(staker
+        .get_allocations(accounts(3), Some(3), None)
+        .is_empty());
+}
+
+#[test]
+fn test_get_allocations_to() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    specify_signer(0);
+    staker.add_user_to_whitelist(accounts(4));
+    testing_env!(get_context(accounts(4))
+        .attached_deposit(NearToken::from_near(1))
+        .build());
+    staker.allocate_percentage(accounts(5), 5000);
+    staker.total_staked = 220 * ONE_NEAR;
+
+    let allocations = staker.get_allocations_to(accounts(5), None, None);
+    assert_eq!(allocations.len(), 2);
+    assert_eq!(allocations[0].distributor, accounts(3));
+    assert_eq!(allocations[0].near_amount, U128(40 * ONE_NEAR));
+    assert_eq!(
+        allocations[0].pending_rewards,
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false)
+            .0
+    );
+    assert_eq!(allocations[1].distributor, accounts(4));
+    assert_eq!(allocations[1].near_amount, U128(50 * ONE_NEAR));
+    assert_eq!(allocations[1].basis_points, Some(5000));
+    assert!(allocations[1].pending_rewards.0 > 0);
+    assert_eq!(
+        staker.get_allocations_to(accounts(5), Some(1), Some(1))[0].distributor,
+        accounts(4)
+    );
+
+    // removed allocations are removed from the index
+    staker.deallocate_percentage(accounts(5), 5000);
+    let allocations = staker.get_allocations_to(accounts(5), None, None);
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].distributor, accounts(3));
+}
+
+#[test]
+fn test_migrate_allocations() {
+    let mut staker = setup_whitelisted_allocator();
+    let share_price = staker.current_share_price();
+    staker.legacy_allocations.insert(
+        accounts(3),
+        HashMap::from([
+            (
+                accounts(5),
+                Allocation {
+                    near_amount: 40 * ONE_NEAR,
+                    share_price,
+                },
+            ),
+            (
+                accounts(2),
+                Allocation {
+                    near_amount: 20 * ONE_NEAR,
+                    share_price,
+                },
+            ),
+        ]),
+    );
+    assert_eq!(staker.get_allocations(accounts(3), None, None).len(), 2);
+
+    staker.migrate_allocations(vec![accounts(3), accounts(4)]);
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocations_migrated_event");
+    assert_eq!(data[0]["allocations"], 2);
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations.len(), 2);
+    assert_eq!(allocations[0].recipient, accounts(2));
+    assert_eq!(allocations[1].recipient, accounts(5));
+    assert_eq!(
+        staker.get_total_allocated(accounts(3)).0,
+        U128(60 * ONE_NEAR)
+    );
+    assert_eq!(
+        staker.get_allocations_to(accounts(5), None, None)[0].distributor,
+        accounts(3)
+    );
+    assert!(!staker.legacy_allocations.contains_key(&accounts(3)));
+}
+
+#[test]
+fn test_views_read_allocations_before_migration() {
+    let mut staker = setup_whitelisted_allocator();
+    let share_price = staker.current_share_price();
+    staker.legacy_allocations.insert(
+        accounts(3),
+        HashMap::from([
+            (
+                accounts(5),
+                Allocation {
+                    near_amount: 40 * ONE_NEAR,
+                    share_price,
+                },
+            ),
+            (
+                accounts(2),
+                Allocation {
+                    near_amount: 20 * ONE_NEAR,
+                    share_price,
+                },
+            ),
+        ]),
+    );
+    staker.total_staked = 220 * ONE_NEAR;
+
+    // the allocations are listed in the order they will be migrated in
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations.len(), 2);
+    assert_eq!(allocations[0].recipient, accounts(2));
+    assert_eq!(allocations[1].recipient, accounts(5));
+    assert_eq!(
+        staker.get_allocations(accounts(3), Some(1), Some(1))[0].recipient,
+        accounts(5)
+    );
+    assert_eq!(
+        staker.get_total_allocated(accounts(3)).0,
+        U128(60 * ONE_NEAR)
+    );
+
+    let pending_rewards = staker.get_pending_rewards(accounts(3), None, None);
+    assert_eq!(pending_rewards.len(), 2);
+    let (required_shares, _) = staker.get_rewards_distribution_amounts(&accounts(3), None, false);
+    assert!(required_shares.0 > 0);
+    assert_eq!(
+        required_shares.0,
+        pending_rewards
+            .iter()
+            .map(|rewards| rewards.shares.0)
+            .sum::<u128>()
+    );
+    let (recipient_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert_eq!(recipient_shares, pending_rewards[1].shares);
+
+    // indexed distributors are read from the previous layout, and distributors without an allocation are skipped
+    staker.internal_index_allocation(&accounts(3), &accounts(5));
+    staker.internal_index_allocation(&accounts(4), &accounts(5));
+    let allocations = staker.get_allocations_to(accounts(5), None, None);
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].distributor, accounts(3));
+    assert_eq!(allocations[0].near_amount, U128(40 * ONE_NEAR));
+
+    // the views return the same amounts once the allocations are migrated
+    staker.migrate_allocations(vec![accounts(3)]);
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), None, false)
+            .0,
+        required_shares
+    );
+    assert_eq!(
+        staker.get_total_allocated(accounts(3)).0,
+        U128(60 * ONE_NEAR)
+    );
+}
+
+#[test]
+fn test_allocate_migrates_legacy_allocations() {
+    let mut staker = setup_whitelisted_allocator();
+    let share_price = staker.current_share_price();
+    staker.legacy_allocations.insert(
+        accounts(3),
+        HashMap::from([(
+            accounts(5),
+            Allocation {
+                near_amount: 40 * ONE_NEAR,
+                share_price,
+            },
+        )]),
+    );
+
+    // the existing allocation is updated without a new storage deposit
+    testing_env!(get_context(accounts(3)).build());
+    staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].near_amount, U128(50 * ONE_NEAR));
+    assert_eq!(
+        staker.get_total_allocated(accounts(3)).0,
+        U128(50 * ONE_NEAR)
+    );
+}
+
+#[test]
+fn test_get_pending_rewards() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.distribution_fee = 500;
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(20 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+
+    let pending_rewards = staker.get_pending_rewards(accounts(3), None, None);
+    assert_eq!(pending_rewards.len(), 2);
+    assert_eq!(pending_rewards[0].recipient, accounts(2));
+    assert_eq!(pending_rewards[1].recipient, accounts(5));
+    for rewards in &pending_rewards {
+        assert_eq!(rewards.fees.0, rewards.shares.0 * 500 / 10000);
+        assert_eq!(rewards.net_shares.0, rewards.shares.0 - rewards.fees.0);
+        assert_eq!(
+            rewards.near_amount.0,
+            staker
+                .current_share_price()
+                .convert_to_assets(rewards.net_shares.0, Rounding::Down)
+        );
+    }
+
+    // the breakdown adds up to the amount required to distribute to all recipients
+    let (required_shares, _) = staker.get_rewards_distribution_amounts(&accounts(3), None, false);
+    assert_eq!(
+        pending_rewards[0].shares.0 + pending_rewards[1].shares.0,
+        required_shares.0
+    );
+    assert_eq!(
+        staker.get_pending_rewards(accounts(3), Some(1), Some(1))[0].recipient,
+        accounts(5)
+    );
+    assert!(staker
+        .get_pending_rewards(accounts(4), None, None)
+        .is_empty());
+}
+
+#[test]
+fn test_no_rewards_when_share_price_drops() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate_percentage(accounts(2), 3000);
+
+    // the share price falls below the price the allocations were made at
+    staker.total_staked = 190 * ONE_NEAR;
+
+    for rewards in staker.get_pending_rewards(accounts(3), None, None) {
+        assert_eq!(rewards.shares, U128(0));
+        assert_eq!(rewards.near_amount, U128(0));
+    }
+    assert_eq!(
+        staker.get_rewards_distribution_amounts(&accounts(3), None, false),
+        (U128(0), U128(0))
+    );
+    staker.distribute_all(false);
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+    assert_eq!(staker.ft_balance_of(accounts(2)), U128(0));
+}
+
+#[test]
+fn test_distribution_ledger() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.distribution_fee = 500;
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    staker.distribute_rewards(accounts(5), false);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distributed_rewards_event");
+    let first_shares: u128 = data[0]["shares"].as_str().unwrap().parse().unwrap();
+    let first_near: u128 = data[0]["near_amount"].as_str().unwrap().parse().unwrap();
+    let first_fees: u128 = data[0]["fees"].as_str().unwrap().parse().unwrap();
+    assert_eq!(
+        data[0]["total_shares_distributed"],
+        first_shares.to_string()
+    );
+
+    testing_env!(get_context(accounts(3))
+        .epoch_height(1)
+        .attached_deposit(NearToken::from_near(1))
+        .build());
+    staker.total_staked = 240 * ONE_NEAR;
+    staker.total_staked_last_updated_at = 1;
+    staker.distribute_rewards(accounts(5), false);
+    let (data, _) = fetch_event(get_logs().last().unwrap());
+    let second_shares: u128 = data[0]["shares"].as_str().unwrap().parse().unwrap();
+    let second_near: u128 = data[0]["near_amount"].as_str().unwrap().parse().unwrap();
+    let second_fees: u128 = data[0]["fees"].as_str().unwrap().parse().unwrap();
+
+    // the ledger accumulates the distributions over the lifetime of the allocation
+    let allocation = &staker.get_allocations(accounts(3), None, None)[0];
+    assert_eq!(
+        allocation.shares_distributed,
+        U128(first_shares + second_shares)
+    );
+    assert_eq!(allocation.near_distributed, U128(first_near + second_near));
+    assert_eq!(allocation.fees_paid, U128(first_fees + second_fees));
+    assert_eq!(allocation.last_distribution_epoch, Some(U64(1)));
+    assert_eq!(
+        data[0]["total_shares_distributed"],
+        allocation.shares_distributed.0.to_string()
+    );
+    assert_eq!(
+        data[0]["total_near_distributed"],
+        allocation.near_distributed.0.to_string()
+    );
+    assert_eq!(
+        data[0]["total_fees_paid"],
+        allocation.fees_paid.0.to_string()
+    );
+
+    // the ledger is cleared when the allocation is removed
+    staker.deallocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    let allocation = &staker.get_allocations(accounts(3), None, None)[0];
+    assert_eq!(allocation.shares_distributed, U128(0));
+    assert!(allocation.last_distribution_epoch.is_none());
+}
+
+#[test]
+fn test_index_allocations() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(ONE_NEAR));
+    staker.allocate(accounts(2), U128(ONE_NEAR));
+    // allocations made before the index was introduced are not indexed
+    staker.allocations_to.get_mut(&accounts(5)).unwrap().clear();
+    staker.allocations_to.get_mut(&accounts(2)).unwrap().clear();
+    assert!(staker
+        .get_allocations_to(accounts(5), None, None)
+        .is_empty());
+
+    specify_signer(4);
+    staker.index_allocations(accounts(3));
+
+    assert_eq!(
+        staker.get_allocations_to(accounts(5), None, None)[0].distributor,
+        accounts(3)
+    );
+    assert_eq!(
+        staker.get_allocations_to(accounts(2), None, None)[0].distributor,
+        accounts(3)
+    );
+}
+
+#[test]
+fn test_reassign_allocation_carries_over_rewards() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.approve_distribution(accounts(5), U128(ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (pending_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    let share_price = staker.get_allocations(accounts(3), None, None)[0]
+        .share_price_num
+        .clone();
+
+    staker.reassign_allocation(accounts(5), accounts(2), false);
+
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].recipient, accounts(2));
+    assert_eq!(allocations[0].near_amount, U128(40 * ONE_NEAR));
+    assert_eq!(allocations[0].share_price_num, share_price);
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(2)), false)
+            .0,
+        pending_shares
+    );
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+    assert_eq!(
+        staker.get_distribution_allowance(accounts(3), accounts(5)),
+        U128(0)
+    );
+    assert!(staker
+        .get_allocations_to(accounts(5), None, None)
+        .is_empty());
+    assert_eq!(
+        staker.get_allocations_to(accounts(2), None, None)[0].distributor,
+        accounts(3)
+    );
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocation_reassigned_event");
+    assert_eq!(data[0]["old_recipient"], accounts(5).to_string());
+    assert_eq!(data[0]["new_recipient"], accounts(2).to_string());
+    assert!(!data[0]["settled"].as_bool().unwrap());
+}
+
+#[test]
+fn test_reassign_allocation_settles_rewards_first() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (pending_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    assert!(staker.reassign_allocation(accounts(5), accounts(2), true));
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), pending_shares);
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(2)), false)
+            .0,
+        U128(0)
+    );
+    assert_eq!(
+        staker.get_last_distribution_epoch(accounts(3), accounts(2)),
+        Some(U64(0))
+    );
+}
+
+#[test]
+fn test_reassign_allocation_while_locked_settles_rewards_first() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (pending_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    staker.is_locked = true;
+
+    staker.reassign_allocation(accounts(5), accounts(2), true);
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), pending_shares);
+    let logs = get_logs();
+    let (data, event) = fetch_event(&logs[logs.len() - 2]);
+    assert_eq!(event, "distributed_rewards_event");
+    assert_eq!(data[0]["shares"], pending_shares.0.to_string());
+}
+
+#[test]
+fn test_reassign_pending_allocation_carries_over_rewards_instead_of_settling() {
+    let mut staker = setup_recipient_consent();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let share_price = staker.get_allocations(accounts(3), None, None)[0]
+        .share_price_num
+        .clone();
+
+    assert!(!staker.reassign_allocation(accounts(5), accounts(2), true));
+
+    // the recipient hasn't accepted the allocation, so the rewards are not distributed to them
+    assert_eq!(staker.ft_balance_of(accounts(5)), U128(0));
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations[0].recipient, accounts(2));
+    assert_eq!(allocations[0].share_price_num, share_price);
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocation_reassigned_event");
+    assert!(!data[0]["settled"].as_bool().unwrap());
+}
+
+#[test]
+fn test_reassign_allocation_with_trunear_locked_by_escrow_carries_over_rewards() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(60 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (pending_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(2)), false);
+
+    // only the TruNEAR locked by the escrowed allocation is left
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+    staker.ft_transfer(accounts(4), U128(60 * ONE_NEAR), None);
+
+    assert!(!staker.reassign_allocation(accounts(2), accounts(0), true));
+
+    assert_eq!(staker.ft_balance_of(accounts(2)), U128(0));
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(0)), false)
+            .0,
+        pending_shares
+    );
+    let (data, _) = fetch_event(get_logs().last().unwrap());
+    assert!(!data[0]["settled"].as_bool().unwrap());
+}
+
+#[test]
+fn test_reassign_escrowed_allocation_keeps_trunear_locked() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate_escrowed(accounts(5), U128(40 * ONE_NEAR));
+
+    staker.reassign_allocation(accounts(5), accounts(2), false);
+
+    assert!(staker.get_allocations(accounts(3), None, None)[0].escrowed);
+    assert_eq!(staker.get_locked_balance(accounts(3)), U128(40 * ONE_NEAR));
+}
+
+#[test]
+fn test_reassign_allocation_to_existing_recipient_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(ONE_NEAR));
+    staker.allocate(accounts(2), U128(ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.reassign_allocation(accounts(5), accounts(2), false);
+        })),
+        ERR_ALLOCATION_ALREADY_EXISTS,
+    );
+}
+
+fn setup_expiring_allocation() -> NearStaker {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(40 * ONE_NEAR));
+    staker.allocate(accounts(2), U128(40 * ONE_NEAR));
+    staker.set_allocation_expiry(accounts(5), Some(U64(2)));
+    staker
+}
+
+fn sync_at_epoch(staker: &mut NearStaker, epoch: u64, total_staked: u128) {
+    testing_env!(get_context(accounts(3)).epoch_height(epoch).build());
+    staker.internal_record_expiry_share_prices();
+    staker.total_staked = total_staked;
+    staker.total_staked_last_updated_at = epoch;
+}
+
+#[test]
+fn test_set_allocation_expiry() {
+    let staker = setup_expiring_allocation();
+
+    assert_eq!(
+        staker.get_allocation_expiry(accounts(3), accounts(5)),
+        Some(U64(2))
+    );
+    assert_eq!(
+        staker.get_allocations(accounts(3), None, None)[1].expires_at_epoch,
+        Some(U64(2))
+    );
+    assert_eq!(staker.pending_expiry_epochs.len(), 1);
+    assert!(staker.pending_expiry_epochs.contains_key(&2));
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "allocation_expiry_set_event");
+    assert_eq!(data[0]["expires_at_epoch"], "2");
+}
+
+#[test]
+fn test_set_allocation_expiry_in_current_epoch_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(ONE_NEAR));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_allocation_expiry(accounts(5), Some(U64(0)));
+        })),
+        ERR_INVALID_EXPIRY_EPOCH,
+    );
+}
+
+#[test]
+fn test_set_allocation_expiry_beyond_horizon_fails() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(ONE_NEAR));
+    staker.set_allocation_expiry(accounts(5), Some(U64(MAX_EXPIRY_EPOCHS)));
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.set_allocation_expiry(accounts(5), Some(U64(MAX_EXPIRY_EPOCHS + 1)));
+        })),
+        ERR_EXPIRY_EPOCH_TOO_FAR,
+    );
+}
+
+#[test]
+fn test_removing_last_expiry_before_it_is_reached_removes_pending_epoch() {
+    let mut staker = setup_expiring_allocation();
+    staker.set_allocation_expiry(accounts(2), Some(U64(2)));
+
+    // the epoch stays pending while an allocation still expires at it
+    staker.set_allocation_expiry(accounts(5), None);
+    assert!(staker.pending_expiry_epochs.contains_key(&2));
+
+    staker.deallocate(accounts(2), U128(40 * ONE_NEAR));
+    assert!(staker.pending_expiry_epochs.is_empty());
+    assert!(staker.expiry_allocation_counts.get(&2).is_none());
+}
+
+#[test]
+fn test_expired_allocation_stops_accruing() {
+    let mut staker = setup_expiring_allocation();
+
+    sync_at_epoch(&mut staker, 1, 220 * ONE_NEAR);
+    let (rewards_at_expiry, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+    assert!(rewards_at_expiry.0 > 0);
+    let share_price_at_expiry = staker.current_share_price();
+
+    // the share price is recorded before the first update at or after the expiry epoch is applied
+    sync_at_epoch(&mut staker, 2, 240 * ONE_NEAR);
+    assert_eq!(
+        staker.get_expiry_share_price(U64(2)),
+        Some(share_price_at_expiry)
+    );
+    assert!(staker.pending_expiry_epochs.is_empty());
+
+    // the expired allocation accrues no rewards after expiry while the other allocation keeps accruing
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false)
+            .0,
+        rewards_at_expiry
+    );
+    assert!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(2)), false)
+            .0
+             .0
+            > rewards_at_expiry.0
+    );
+
+    staker.distribute_rewards(accounts(5), false);
+    assert_eq!(staker.ft_balance_of(accounts(5)), rewards_at_expiry);
+    assert_eq!(
+        staker
+            .get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false)
+            .0,
+        U128(0)
+    );
+}
+
+#[test]
+fn test_top_up_expired_allocation_fails() {
+    let mut staker = setup_expiring_allocation();
+    sync_at_epoch(&mut staker, 2, 220 * ONE_NEAR);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.allocate(accounts(5), U128(ONE_NEAR));
+        })),
+        ERR_ALLOCATION_EXPIRED,
+    );
+}
+
+#[test]
+fn test_remove_expired_allocation() {
+    let mut staker = setup_expiring_allocation();
+    sync_at_epoch(&mut staker, 3, 220 * ONE_NEAR);
+    staker.distribute_rewards(accounts(5), false);
+
+    // anyone can remove the expired allocation
+    specify_signer(4);
+    staker.remove_expired_allocation(accounts(3), accounts(5));
+
+    let allocations = staker.get_allocations(accounts(3), None, None);
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].recipient, accounts(2));
+    assert!(staker
+        .get_allocation_expiry(accounts(3), accounts(5))
+        .is_none());
+    assert!(staker
+        .get_allocations_to(accounts(5), None, None)
+        .is_empty());
+    // the share price recorded for the expiry epoch is removed with its last allocation
+    assert!(staker.get_expiry_share_price(U64(2)).is_none());
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "expired_allocation_removed_event");
+    assert_eq!(data[0]["user"], accounts(3).to_string());
+    assert_eq!(data[0]["caller"], accounts(4).to_string());
+}
+
+#[test]
+fn test_reassign_expired_allocation_keeps_expiry_share_price() {
+    let mut staker = setup_expiring_allocation();
+    sync_at_epoch(&mut staker, 2, 220 * ONE_NEAR);
+    let expiry_share_price = staker.get_expiry_share_price(U64(2));
+    assert!(expiry_share_price.is_some());
+
+    staker.reassign_allocation(accounts(5), accounts(4), false);
+
+    assert_eq!(
+        staker.get_allocation_expiry(accounts(3), accounts(4)),
+        Some(U64(2))
+    );
+    assert_eq!(staker.get_expiry_share_price(U64(2)), expiry_share_price);
+    assert!(staker.pending_expiry_epochs.is_empty());
+}
+
+#[test]
+fn test_remove_expired_allocation_with_rewards_fails() {
+    let mut staker = setup_expiring_allocation();
+    sync_at_epoch(&mut staker, 1, 220 * ONE_NEAR);
+    sync_at_epoch(&mut staker, 2, 220 * ONE_NEAR);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.remove_expired_allocation(accounts(3), accounts(5));
+        })),
+        ERR_EXPIRED_ALLOCATION_HAS_REWARDS,
+    );
+}
+
+#[test]
+fn test_remove_allocation_before_expiry_fails() {
+    let mut staker = setup_expiring_allocation();
+    sync_at_epoch(&mut staker, 1, 200 * ONE_NEAR);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.remove_expired_allocation(accounts(3), accounts(5));
+        })),
+        ERR_ALLOCATION_NOT_EXPIRED,
+    );
+}
+
+/// Sets up a 10 NEAR allocation from accounts(3) to the whitelisted accounts(4) that accrued rewards,
+/// with the stake held on the default pool.
+fn setup_unstake_distribution() -> NearStaker {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(4), U128(10 * ONE_NEAR));
+    specify_signer(0);
+    staker.add_user_to_whitelist(accounts(4));
+    staker.total_staked = 220 * ONE_NEAR;
+    staker
+        .delegation_pools
+        .get_mut(&accounts(2))
+        .unwrap()
+        .total_staked = U128(220 * ONE_NEAR);
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(NearStaker::get_storage_cost().0))
+        .build());
+    staker
+}
+
+#[test]
+fn test_distribute_rewards_via_unstake() {
+    let mut staker = setup_unstake_distribution();
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(4)), false);
+    let distributor_balance = staker.ft_balance_of(accounts(3)).0;
+    let recipient_balance = staker.ft_balance_of(accounts(4)).0;
+    let total_supply = staker.ft_total_supply().0;
+
+    assert!(staker.distribute_rewards_via_unstake(accounts(4)).is_some());
+
+    // the rewards are burned rather than credited to the recipient
+    assert_eq!(
+        staker.ft_balance_of(accounts(3)).0,
+        distributor_balance - required_shares.0
+    );
+    assert!(staker.ft_balance_of(accounts(4)).0 - recipient_balance <= 1);
+    assert!(total_supply - staker.ft_total_supply().0 + 1 >= required_shares.0);
+    assert!(staker.is_locked);
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distributed_rewards_event");
+    assert_eq!(data[0]["recipient"], accounts(4).to_string());
+    assert!(data[0]["in_near"].as_bool().unwrap());
+    assert!(data[0]["via_unstake"].as_bool().unwrap());
+    let near_amount: u128 = data[0]["near_amount"].as_str().unwrap().parse().unwrap();
+    assert_eq!(staker.total_staked, 220 * ONE_NEAR - near_amount);
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(4)), false);
+    assert_eq!(required_shares, U128(0));
+}
+
+#[test]
+fn test_failed_unstake_distribution_refunds_storage_to_distributor() {
+    let mut staker = setup_unstake_distribution();
+    staker.distribute_rewards_via_unstake(accounts(4));
+    let (data, _) = fetch_event(get_logs().last().unwrap());
+    let near_amount: u128 = data[0]["near_amount"].as_str().unwrap().parse().unwrap();
+    let shares: u128 = data[0]["shares"].as_str().unwrap().parse().unwrap();
+    let recipient_balance = staker.ft_balance_of(accounts(4)).0;
+    let storage_cost = NearToken::from_yoctonear(NearStaker::get_storage_cost().0);
+
+    testing_env!(get_context(accounts(0))
+        .predecessor_account_id(env::current_account_id())
+        .build());
+    staker.finalize_unstake(
+        UnstakeDetails {
+            pool_id: accounts(2),
+            amount: U128(near_amount),
+            caller: accounts(4),
+            pre_unstake_staker_balance: NearToken::from_yoctonear(0),
+            share_price_num: "1".to_string(),
+            share_price_denom: "1".to_string(),
+            shares_amount: U128(shares),
+            withdraw_occurred: false,
+            attached_near: storage_cost,
+            unstake_epoch: env::epoch_height(),
+            storage_payer: accounts(3),
+        },
+        Err(near_sdk::PromiseError::Failed),
+    );
+
+    // the rewards stay with the recipient while the storage deposit goes back to the distributor
+    assert!(staker.ft_balance_of(accounts(4)).0 >= recipient_balance + shares - 1);
+    assert_eq!(staker.total_staked, 220 * ONE_NEAR);
+    assert!(!staker.is_locked);
+    let receipts = near_sdk::test_utils::get_created_receipts();
+    assert_eq!(receipts.last().unwrap().receiver_id, accounts(3));
+}
+
+#[test]
+fn test_distribute_rewards_via_unstake_without_rewards() {
+    let mut staker = setup_unstake_distribution();
+    staker.total_staked = 200 * ONE_NEAR;
+
+    assert!(staker.distribute_rewards_via_unstake(accounts(4)).is_none());
+
+    assert_eq!(staker.total_staked, 200 * ONE_NEAR);
+    assert!(!staker.is_locked);
+}
+
+#[test]
+fn test_distribute_rewards_via_unstake_to_non_whitelisted_recipient_fails() {
+    let mut staker = setup_unstake_distribution();
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(
+            NearStaker::get_allocation_storage_cost().0
+        ))
+        .build());
+    staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+    testing_env!(get_context(accounts(3))
+        .attached_deposit(NearToken::from_yoctonear(NearStaker::get_storage_cost().0))
+        .build());
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.distribute_rewards_via_unstake(accounts(5));
+        })),
+        ERR_RECIPIENT_NOT_WHITELISTED,
+    );
+}
+
+#[test]
+fn test_distribute_rewards_via_unstake_without_storage_deposit_fails() {
+    let mut staker = setup_unstake_distribution();
+    testing_env!(get_context(accounts(3)).build());
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.distribute_rewards_via_unstake(accounts(4));
+        })),
+        ERR_STORAGE_DEPOSIT_TOO_SMALL,
+    );
+}
+
+#[test]
+fn test_distribute_rewards_via_unstake_when_unstake_locked_fails() {
+    let mut staker = setup_unstake_distribution();
+    staker
+        .delegation_pools
+        .get_mut(&accounts(2))
+        .unwrap()
+        .last_unstake = Some(env::epoch_height() + 1);
+
+    check_error_message(
+        std::panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            staker.distribute_rewards_via_unstake(accounts(4));
+        })),
+        ERR_UNSTAKE_LOCKED,
+    );
+}
+
+/// Distributes the rewards of a 10 NEAR allocation from accounts(3) to accounts(5) with `distribute_rewards_call`
+/// and returns the distributed TruNEAR and its NEAR value.
+fn setup_distribution_call() -> (NearStaker, u128, u128) {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+
+    staker.distribute_rewards_call(accounts(5), "deposit".to_string());
+
+    let allocation = &staker.get_allocations(accounts(3), None, None)[0];
+    let (shares, near_amount) = (
+        allocation.shares_distributed.0,
+        allocation.near_distributed.0,
+    );
+    (staker, shares, near_amount)
+}
+
+/// Mocks the result of the recipient's `ft_on_transfer` call in the resolver context.
+fn mock_ft_on_transfer_result(promise_result: PromiseResult) {
+    testing_env!(
+        get_context(accounts(0)).build(),
+        near_sdk::test_vm_config(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![promise_result],
+    );
+}
+
+#[test]
+fn test_distribute_rewards_call() {
+    let mut staker = setup_whitelisted_allocator();
+    staker.allocate(accounts(5), U128(10 * ONE_NEAR));
+    staker.total_staked = 220 * ONE_NEAR;
+    let (required_shares, _) =
+        staker.get_rewards_distribution_amounts(&accounts(3), Some(accounts(5)), false);
+
+    staker.distribute_rewards_call(accounts(5), "deposit".to_string());
+
+    assert_eq!(staker.ft_balance_of(accounts(5)), required_shares);
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distributed_rewards_event");
+    assert_eq!(data[0]["recipient"], accounts(5).to_string());
+    assert_eq!(data[0]["shares"], required_shares.0.to_string());
+}
+
+#[test]
+fn test_resolve_distribution_call_refunds_unused_rewards() {
+    let (mut staker, shares, near_amount) = setup_distribution_call();
+    let distributor_balance = staker.ft_balance_of(accounts(3)).0;
+    mock_ft_on_transfer_result(PromiseResult::Successful(
+        serde_json::to_vec(&U128(shares / 4)).unwrap(),
+    ));
+
+    let used_shares =
+        staker.resolve_distribution_call(accounts(3), accounts(5), U128(shares), U128(near_amount));
+
+    assert_eq!(used_shares, U128(shares - shares / 4));
+    assert_eq!(
+        staker.ft_balance_of(accounts(3)).0,
+        distributor_balance + shares / 4
+    );
+    assert_eq!(staker.ft_balance_of(accounts(5)).0, shares - shares / 4);
+    let allocation = &staker.get_allocations(accounts(3), None, None)[0];
+    assert_eq!(allocation.shares_distributed.0, shares - shares / 4);
+    assert!(allocation.near_distributed.0 < near_amount);
+
+    let (data, event) = fetch_event(get_logs().last().unwrap());
+    assert_eq!(event, "distribution_refunded_event");
+    assert_eq!(data[0]["shares"], (shares / 4).to_string());
+}
+
+#[test]
+fn test_resolve_distribution_call_when_recipient_fails() {
+    let (mut staker, shares, near_amount) = setup_distribution_call();
+    let distributor_balance = staker.ft_balance_of(accounts(3)).0;
+    mock_ft_on_transfer_result(PromiseResult::Failed);
+
+    let used_shares =
+        staker.resolve_distribution_call(accounts(3), accounts(5), U128(shares), U128(near_amount));
+
+    assert_eq!(used_shares, U128(0));
+    assert_eq!(
+        staker.ft_balance_of(accounts(3)).0,
+        distributor_balance + shares
+    );
+    assert_eq!(staker.ft_balance_of(accounts(5)).0, 0);
+    let allocation = &staker.get_allocations(accounts(3), None, None)[0];
+    assert_eq!(allocation.shares_distributed.0, 0);
+    assert_eq!(allocation.near_distributed.0, 0);
+}
+
+#[test]
+fn test_resolve_distribution_call_when_rewards_are_used() {
+    let (mut staker, shares, near_amount) = setup_distribution_call();
+    mock_ft_on_transfer_result(PromiseResult::Successful(
+        serde_json::to_vec(&U128(0)).unwrap(),
+    ));
+
+    let used_shares =
+        staker.resolve_distribution_call(accounts(3), accounts(5), U128(shares), U128(near_amount));
+
+    assert_eq!(used_shares, U128(shares));
+    assert_eq!(staker.ft_balance_of(accounts(5)).0, shares);
+    assert_eq!(
+        staker.get_allocations(accounts(3), None, None)[0]
+            .near_distributed
+            .0,
+        near_amount
+    );
+}
+
+fn share_price_strategy() -> impl proptest::strategy::Strategy<Value = SharePrice> {
+    use proptest::prelude::*;
+    (
+        ONE_NEAR..MAX_PROPTEST_NEAR,
+        1u128..=20_000,
+        0u128..=10_000,
+        0u16..FEE_PRECISION,
+    )
+        .prop_map(|(total_staked, supply_bps, tax_exempt_bps, fee)| {
+            let shares_supply = (total_staked * supply_bps / 10_000).max(1);
+            let tax_exempt_stake = total_staked * tax_exempt_bps / 10_000;
+            NearStaker::internal_share_price(total_staked, shares_supply, tax_exempt_stake, fee)
+        })
+}
+
+proptest::proptest! {
+    #[test]
+    fn prop_near_to_shares_to_near_never_favours_the_user(
+        share_price in share_price_strategy(),
+        assets in 0u128..MAX_PROPTEST_NEAR,
+    ) {
+        // shares are minted rounding down, so converting them back can never exceed the deposit
+        let shares = share_price.convert_to_shares(assets, Rounding::Down);
+        let assets_back = share_price.convert_to_assets(shares, Rounding::Down);
+        proptest::prop_assert!(assets_back <= assets);
+    }
+
+    #[test]
+    fn prop_shares_to_near_to_shares_never_favours_the_user(
+        share_price in share_price_strategy(),
+        shares in 0u128..MAX_PROPTEST_NEAR,
+    ) {
+        let assets = share_price.convert_to_assets(shares, Rounding::Down);
+        let shares_back = share_price.convert_to_shares(assets, Rounding::Down);
+        proptest::prop_assert!(shares_back <= shares);
+    }
+
+    #[test]
+    fn prop_rounding_up_differs_from_rounding_down_by_at_most_one(
+        share_price in share_price_strategy(),
+        amount in 0u128..MAX_PROPTEST_NEAR,
+    ) {
+        let shares_down = share_price.convert_to_shares(amount, Rounding::Down);
+        let shares_up = share_price.convert_to_shares(amount, Rounding::Up);
+        proptest::prop_assert!(shares_up >= shares_down && shares_up - shares_down <= 1);
+
+        let assets_down = share_price.convert_to_assets(amount, Rounding::Down);
+        let assets_up = share_price.convert_to_assets(amount, Rounding::Up);
+        proptest::prop_assert!(assets_up >= assets_down && assets_up - assets_down <= 1);
+    }
+
+    #[test]
+    fn prop_distribution_never_exceeds_the_allocation_rewards(
+        allocation_price in share_price_strategy(),
+        reward_bps in 0u128..=10_000,
+        near_amount in ONE_NEAR..MAX_PROPTEST_NEAR,
+    ) {
+        // the share price can only grow between distributions
+        let global_price = SharePrice::new(
+            allocation_price.num * U256::from(10_000 + reward_bps),
+            allocation_price.denom * U256::from(10_000),
+        );
+        let allocation = Allocation { near_amount, share_price: allocation_price };
+
+        // the distributed shares are worth no more than the NEAR gained by the allocated amount
+        let shares = NearStaker::internal_calculate_distribution_amount(&allocation, global_price);
+        let distributed_near = global_price.convert_to_assets(shares, Rounding::Down);
+        let allocated_shares = allocation_price.convert_to_shares(near_amount, Rounding::Up);
+        let allocation_value = global_price.convert_to_assets(allocated_shares, Rounding::Up);
+        proptest::prop_assert!(distributed_near <= allocation_value - near_amount + 1);
+    }
+}
@@ -1,6 +1,6 @@
 use near_contract_standards::fungible_token::{FungibleTokenCore, FungibleTokenResolver};
 use near_sdk::json_types::U128;
-use near_sdk::{near, AccountId, PromiseOrValue};
+use near_sdk::{env, near, AccountId, PromiseOrValue};
 
 use crate::*;
 
@@ -9,7 +9,11 @@ impl FungibleTokenCore for NearStaker {
     /// Sends TruNEAR to another registered account.
     #[payable]
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
-        self.token.ft_transfer(receiver_id, amount, memo)
+        self.check_unlocked_balance(&env::predecessor_account_id(), amount.0);
+        self.check_allocation_coverage_after_transfer(&env::predecessor_account_id(), amount.0);
+        self.token.ft_transfer(receiver_id.clone(), amount, memo);
+        self.internal_checkpoint_percentage_allocations(&env::predecessor_account_id());
+        self.internal_checkpoint_percentage_allocations(&receiver_id);
     }
 
     /// Transfers with a callback to the receiver contract.
@@ -21,7 +25,14 @@ impl FungibleTokenCore for NearStaker {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        self.token.ft_transfer_call(receiver_id, amount, memo, msg)
+        self.check_unlocked_balance(&env::predecessor_account_id(), amount.0);
+        self.check_allocation_coverage_after_transfer(&env::predecessor_account_id(), amount.0);
+        let promise = self
+            .token
+            .ft_transfer_call(receiver_id.clone(), amount, memo, msg);
+        self.internal_checkpoint_percentage_allocations(&env::predecessor_account_id());
+        self.internal_checkpoint_percentage_allocations(&receiver_id);
+        promise
     }
 
     /// Returns the total supply of the token.
@@ -45,9 +56,11 @@ impl FungibleTokenResolver for NearStaker {
         receiver_id: AccountId,
         amount: U128,
     ) -> U128 {
-        self.token
-            .ft_resolve_transfer(sender_id, receiver_id, amount)
-            .0
-            .into()
+        let used_amount =
+            self.token
+                .ft_resolve_transfer(sender_id.clone(), receiver_id.clone(), amount);
+        self.internal_checkpoint_percentage_allocations(&sender_id);
+        self.internal_checkpoint_percentage_allocations(&receiver_id);
+        used_amount
     }
 }
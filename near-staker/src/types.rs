@@ -1,9 +1,11 @@
 use near_sdk::{
     json_types::{U128, U64},
-    near, AccountId,
+    near, AccountId, NearToken,
 };
 use uint::construct_uint;
 
+use crate::math::SharePrice;
+
 construct_uint! {
     #[near(serializers = [json, borsh])]
     pub struct U256(4);
@@ -19,6 +21,22 @@ pub enum ValidatorState {
     DISABLED,
 }
 
+/// The staker settings whose loosening is timelocked.
+#[near(serializers = [json, borsh])]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConfigKey {
+    #[allow(clippy::upper_case_acronyms)]
+    FEE,
+    #[allow(non_camel_case_types)]
+    DISTRIBUTION_FEE,
+    #[allow(non_camel_case_types)]
+    MANAGEMENT_FEE,
+    #[allow(non_camel_case_types)]
+    CONFIG_CHANGE_DELAY,
+    #[allow(non_camel_case_types)]
+    MAX_FEE_INCREASE,
+}
+
 #[near(serializers = [json, borsh])]
 #[derive(Debug, PartialEq, Clone)]
 pub enum UserStatus {
@@ -38,6 +56,7 @@ pub struct StakerInfo {
     pub default_delegation_pool: AccountId,
     pub fee: u16,
     pub dist_fee: u16,
+    pub management_fee: u16,
     pub min_deposit: U128,
     pub is_paused: bool,
     pub current_epoch: U64,
@@ -53,6 +72,91 @@ pub struct Pool {
     pub last_unstake: Option<u64>,
 }
 
+/// A proposed config change that can be executed from the given epoch.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct PendingConfigChange {
+    pub key: ConfigKey,
+    pub old_value: U64,
+    pub new_value: U64,
+    pub proposed_at: U64,
+    pub executable_at: U64,
+}
+
+#[near(serializers = [json, borsh])]
+pub struct PendingFeesInfo {
+    pub near_amount: U128,
+    pub shares_amount: U128,
+    pub rebated_shares: U128,
+}
+
+/// A beneficiary of collected fees and its share of them with FEE_PRECISION digits of precision.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeBeneficiary {
+    pub account_id: AccountId,
+    pub basis_points: u16,
+}
+
+/// Fees charged to a specific account instead of the global fees.
+#[near(serializers = [borsh])]
+#[derive(Clone)]
+pub struct FeeOverride {
+    pub fee: u16,
+    pub distribution_fee: u16,
+    /// The shares the account held throughout the current fee period, on which its fee rebate is calculated.
+    pub rebate_eligible_shares: u128,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct FeeOverrideInfo {
+    pub account_id: AccountId,
+    pub fee: u16,
+    pub distribution_fee: u16,
+}
+
+/// The total staked on a pool before and after a total staked update.
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct PoolTotalStakedUpdate {
+    pub pool_id: AccountId,
+    pub old_total_staked: U128,
+    pub new_total_staked: U128,
+}
+
+/// The cumulative NEAR the staker has covered for rounding on a pool.
+#[near(serializers = [borsh])]
+#[derive(Clone, Default)]
+pub struct RoundingSubsidy {
+    /// The intended stake amounts that the pool did not stake.
+    pub stake_subsidy: u128,
+    /// The requested unstake amounts that the pool did not unstake.
+    pub unstake_subsidy: u128,
+    /// The requested withdrawal amounts that the pool did not transfer back.
+    pub withdraw_subsidy: u128,
+    /// The current shortfall between the unstaked amount tracked on the pool and the one it reports.
+    pub unstake_shortfall: u128,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct PoolRoundingSubsidy {
+    pub pool_id: AccountId,
+    pub stake_subsidy: U128,
+    pub unstake_subsidy: U128,
+    pub withdraw_subsidy: U128,
+}
+
+#[near(serializers = [json, borsh])]
+pub struct RoundingReserveInfo {
+    pub reserve_balance: U128,
+    pub total_subsidy: U128,
+    pub remaining_reserve: U128,
+    pub threshold: U128,
+    pub pools: Vec<PoolRoundingSubsidy>,
+}
+
 #[near(serializers = [json, borsh])]
 #[derive(Clone)]
 pub struct PoolInfo {
@@ -67,8 +171,23 @@ pub struct PoolInfo {
 #[derive(Clone, Copy, Default)]
 pub struct Allocation {
     pub near_amount: u128,
-    pub share_price_num: U256,
-    pub share_price_denom: U256,
+    pub share_price: SharePrice,
+}
+
+/// The NEAR allocated by an allocator and the TruNEAR it was worth at the allocation share prices.
+#[near(serializers = [borsh])]
+#[derive(Clone, Copy, Default)]
+pub struct AllocationTotals {
+    pub near_amount: u128,
+    pub shares: U256,
+}
+
+#[near(serializers = [borsh])]
+#[derive(Clone, Copy, Default)]
+pub struct DistributionLedger {
+    pub shares_distributed: u128,
+    pub near_distributed: u128,
+    pub fees_paid: u128,
 }
 
 #[near(serializers = [json, borsh])]
@@ -77,6 +196,34 @@ pub struct AllocationInfo {
     pub near_amount: U128,
     pub share_price_num: String,
     pub share_price_denom: String,
+    pub basis_points: Option<u16>,
+    pub escrowed: bool,
+    pub pending: bool,
+    pub expires_at_epoch: Option<U64>,
+    pub shares_distributed: U128,
+    pub near_distributed: U128,
+    pub fees_paid: U128,
+    pub last_distribution_epoch: Option<U64>,
+}
+
+#[near(serializers = [json])]
+pub struct RecipientAllocationInfo {
+    pub distributor: AccountId,
+    pub near_amount: U128,
+    pub pending_rewards: U128,
+    pub basis_points: Option<u16>,
+    pub escrowed: bool,
+    pub pending: bool,
+    pub expires_at_epoch: Option<U64>,
+}
+
+#[near(serializers = [json])]
+pub struct PendingRewardsInfo {
+    pub recipient: AccountId,
+    pub shares: U128,
+    pub fees: U128,
+    pub net_shares: U128,
+    pub near_amount: U128,
 }
 
 pub(crate) struct DistributionInfo {
@@ -84,8 +231,39 @@ pub(crate) struct DistributionInfo {
     pub shares_amount: u128,
     pub refund_amount: u128,
     pub fees: u128,
-    pub share_price_num: U256,
-    pub share_price_denom: U256,
+    pub share_price: SharePrice,
+    pub ledger: DistributionLedger,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct RewardCampaign {
+    pub funder: AccountId,
+    pub total_amount: u128,
+    // the amount already staked and added to the total staked
+    pub released_amount: u128,
+    pub start_epoch: u64,
+    pub num_epochs: u64,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct DistributionSchedule {
+    pub keeper: AccountId,
+    // the minimum number of epochs between distributions to the same recipient
+    pub interval: U64,
+}
+
+#[near(serializers = [json, borsh])]
+pub struct RewardCampaignInfo {
+    pub campaign_id: U64,
+    pub funder: AccountId,
+    pub total_amount: U128,
+    pub released_amount: U128,
+    pub releasable_amount: U128,
+    pub remaining_amount: U128,
+    pub start_epoch: U64,
+    pub end_epoch: U64,
 }
 
 #[near(serializers = [json, borsh])]
@@ -95,3 +273,19 @@ pub struct UnstakeRequest {
     pub pool_id: AccountId,
     pub epoch: u64,
 }
+
+/// The details of an unstake passed to the unstake callback.
+#[near(serializers = [json])]
+pub struct UnstakeDetails {
+    pub pool_id: AccountId,
+    pub amount: U128,
+    pub caller: AccountId,
+    pub pre_unstake_staker_balance: NearToken,
+    pub share_price_num: String,
+    pub share_price_denom: String,
+    pub shares_amount: U128,
+    pub withdraw_occurred: bool,
+    pub attached_near: NearToken,
+    pub unstake_epoch: u64,
+    pub storage_payer: AccountId,
+}
@@ -0,0 +1,92 @@
+use near_sdk::{env, json_types::U128, log, near, require, AccountId, NearToken, Promise};
+
+use crate::errors::*;
+use crate::whitelist::WhitelistTrait;
+use crate::*;
+
+#[near]
+impl NearStaker {
+    #[payable]
+    /// Distributes NEAR staking rewards to a recipient by unstaking them in the recipient's name.
+    /// The reward shares are moved from the distributor and burned in an unstake request for the recipient,
+    /// who can withdraw the NEAR after the unlock period. The distributor only attaches the storage cost
+    /// of the unstake request, which is paid out to the recipient with the withdrawal or refunded to the
+    /// distributor if the unstake fails. The event is emitted with `via_unstake` set.
+    pub fn distribute_rewards_via_unstake(&mut self, recipient: AccountId) -> Option<Promise> {
+        self.check_not_paused();
+        self.check_whitelisted();
+        self.check_contract_in_sync();
+        self.check_not_locked();
+
+        // the recipient must be able to withdraw the unstaked rewards
+        require!(
+            self.is_whitelisted(recipient.clone()),
+            ERR_RECIPIENT_NOT_WHITELISTED
+        );
+
+        let attached_near = env::attached_deposit();
+        let storage_cost = NearToken::from_yoctonear(Self::get_storage_cost().0);
+        require!(attached_near >= storage_cost, ERR_STORAGE_DEPOSIT_TOO_SMALL);
+
+        let pool_id = self.default_delegation_pool.clone();
+        self.check_unstake_not_locked(&pool_id);
+
+        let distributor = env::predecessor_account_id();
+        self.internal_migrate_allocations(&distributor);
+        require!(
+            self.internal_allocation(&distributor, &recipient).is_some(),
+            ERR_NO_ALLOCATIONS_TO_RECIPIENT
+        );
+
+        // the rewards are moved to the recipient as TruNEAR and unstaked from their account
+        let global_price = self.current_share_price();
+        let distribution_info = match self.internal_distribute(
+            distributor.clone(),
+            recipient.clone(),
+            global_price,
+            false,
+            NearToken::from_yoctonear(0),
+            None,
+        ) {
+            Err(error) => env::panic_str(error.to_string().as_str()),
+            Ok(None) => {
+                log!("No rewards to distribute");
+                Promise::new(distributor).transfer(attached_near);
+                return None;
+            }
+            Ok(Some(distribution_info)) => distribution_info,
+        };
+        require!(
+            self.delegation_pools.get(&pool_id).unwrap().total_staked
+                >= U128(distribution_info.near_amount),
+            ERR_INSUFFICIENT_FUNDS_ON_POOL
+        );
+
+        // refund any excess NEAR to distributor
+        if attached_near > storage_cost {
+            Promise::new(distributor.clone())
+                .transfer(attached_near.checked_sub(storage_cost).unwrap());
+        }
+
+        self.is_locked = true;
+        let promise = self.send_unstake_promises(
+            pool_id,
+            distribution_info.near_amount,
+            recipient.clone(),
+            storage_cost,
+            distributor.clone(),
+        );
+
+        // emit Distribute Rewards event
+        self.internal_emit_distributed_rewards(
+            &distributor,
+            &recipient,
+            &distribution_info,
+            true,
+            true,
+            self.get_total_allocated(distributor.clone()),
+        );
+
+        Some(promise)
+    }
+}
@@ -1,16 +1,102 @@
-use crate::NearStaker;
-use near_sdk::near;
+use crate::constants::*;
+use crate::types::*;
+use crate::{NearStaker, Whitelist};
+use near_contract_standards::fungible_token::FungibleToken;
+use near_sdk::store::{LookupMap, LookupSet, TreeMap};
+use near_sdk::{env, near, AccountId};
+use std::collections::HashMap;
+
+/// The contract state as deployed before this upgrade, with the original storage layout. Every field added
+/// since is initialised in the conversion below.
+#[near(serializers=[borsh])]
+pub struct NearStakerV1 {
+    pub whitelist: Whitelist,
+    pub owner_id: AccountId,
+    pub pending_owner: Option<AccountId>,
+    pub treasury: AccountId,
+    pub default_delegation_pool: AccountId,
+    pub is_paused: bool,
+    pub fee: u16,
+    pub distribution_fee: u16,
+    pub min_deposit: u128,
+    pub delegation_pools: HashMap<AccountId, Pool>,
+    pub delegation_pools_list: Vec<AccountId>,
+    pub total_staked: u128,
+    pub total_staked_last_updated_at: u64,
+    pub allocations: LookupMap<AccountId, HashMap<AccountId, Allocation>>,
+    pub unstake_requests: LookupMap<u128, UnstakeRequest>,
+    pub unstake_nonce: u128,
+    pub tax_exempt_stake: u128,
+    pub withdrawn_amount: u128,
+    pub token: FungibleToken,
+    pub is_locked: bool,
+}
 
 #[near(serializers=[borsh])]
 pub enum VersionedNearStaker {
-    V1(NearStaker),
+    V1(NearStakerV1),
 }
 
 /// Converts from an old version of the contract to the new one.
 impl From<VersionedNearStaker> for NearStaker {
     fn from(contract: VersionedNearStaker) -> Self {
         match contract {
-            VersionedNearStaker::V1(state) => state,
+            VersionedNearStaker::V1(state) => NearStaker {
+                whitelist: state.whitelist,
+                owner_id: state.owner_id,
+                pending_owner: state.pending_owner,
+                treasury: state.treasury,
+                default_delegation_pool: state.default_delegation_pool,
+                is_paused: state.is_paused,
+                fee: state.fee,
+                distribution_fee: state.distribution_fee,
+                management_fee: 0,
+                management_fee_accrued_at: env::block_timestamp(),
+                accrued_management_fees: 0,
+                min_deposit: state.min_deposit,
+                delegation_pools: state.delegation_pools,
+                delegation_pools_list: state.delegation_pools_list,
+                total_staked: state.total_staked,
+                total_staked_last_updated_at: state.total_staked_last_updated_at,
+                allocations: LookupMap::new(b"n".to_vec()),
+                legacy_allocations: state.allocations,
+                allocation_totals: LookupMap::new(b"z".to_vec()),
+                allocation_percentages: LookupMap::new(b"p".to_vec()),
+                percentage_allocation_shares: LookupMap::new(b"h".to_vec()),
+                escrowed_allocations: LookupMap::new(b"e".to_vec()),
+                distribution_allowances: LookupMap::new(b"d".to_vec()),
+                distribution_schedules: LookupMap::new(b"k".to_vec()),
+                last_distribution_epochs: LookupMap::new(b"l".to_vec()),
+                distribution_ledgers: LookupMap::new(b"g".to_vec()),
+                distribution_cursors: LookupMap::new(b"c".to_vec()),
+                allocations_to: LookupMap::new(b"r".to_vec()),
+                allocation_storage_deposits: LookupMap::new(b"b".to_vec()),
+                recipient_consent_required: true,
+                pending_allocations: LookupSet::new(b"q".to_vec()),
+                strict_allocators: LookupSet::new(b"m".to_vec()),
+                allocation_expiries: LookupMap::new(b"x".to_vec()),
+                expiry_share_prices: LookupMap::new(b"s".to_vec()),
+                expiry_allocation_counts: LookupMap::new(b"E".to_vec()),
+                pending_expiry_epochs: TreeMap::new(b"P".to_vec()),
+                unstake_requests: state.unstake_requests,
+                unstake_storage_deposits: LookupMap::new(b"v".to_vec()),
+                unstake_nonce: state.unstake_nonce,
+                tax_exempt_stake: state.tax_exempt_stake,
+                withdrawn_amount: state.withdrawn_amount,
+                token: state.token,
+                is_locked: state.is_locked,
+                reward_campaigns: HashMap::new(),
+                reward_campaign_nonce: 0,
+                pending_config_changes: vec![],
+                config_change_delay: DEFAULT_CONFIG_CHANGE_DELAY,
+                max_fee_increase: DEFAULT_MAX_FEE_INCREASE,
+                auto_collect_fees: false,
+                fee_split: vec![],
+                fee_overrides: HashMap::new(),
+                rounding_subsidies: HashMap::new(),
+                rounding_reserve: 0,
+                rounding_reserve_threshold: 0,
+            },
         }
     }
 }
@@ -74,6 +74,14 @@ pub async fn setup_contract(
         .await?;
     assert!(init.is_success());
 
+    // recipients are paid without accepting their allocations, consent is covered by test_recipient_consent
+    let consent = owner
+        .call(contract.id(), "set_recipient_consent_required")
+        .args_json(json!({ "required": false }))
+        .transact()
+        .await?;
+    assert!(consent.is_success());
+
     Ok((owner, sandbox, contract))
 }
 
@@ -202,6 +210,14 @@ pub async fn setup_contract_with_pool(
         .await?;
     assert!(init.is_success());
 
+    // recipients are paid without accepting their allocations, consent is covered by test_recipient_consent
+    let consent = owner
+        .call(contract.id(), "set_recipient_consent_required")
+        .args_json(json!({ "required": false }))
+        .transact()
+        .await?;
+    assert!(consent.is_success());
+
     Ok((owner, sandbox, contract, default_pool))
 }
 
@@ -603,6 +619,57 @@ pub async fn stake_to_specific_pool(
     Ok(stake)
 }
 
+pub async fn donate(
+    contract: &Contract,
+    donor: &Account,
+    pool_id: &AccountId,
+    amount: u128,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let donation = donor
+        .call(contract.id(), "donate")
+        .args_json(json!({
+            "pool_id": pool_id,
+        }))
+        .deposit(NearToken::from_near(amount))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(donation.is_success());
+
+    Ok(donation)
+}
+
+pub async fn create_reward_campaign(
+    contract: &Contract,
+    owner: &Account,
+    amount: u128,
+    num_epochs: u64,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let create_campaign = owner
+        .call(contract.id(), "create_reward_campaign")
+        .args_json(json!({
+            "num_epochs": num_epochs,
+        }))
+        .deposit(NearToken::from_near(amount))
+        .transact()
+        .await?;
+    assert!(create_campaign.is_success());
+
+    Ok(create_campaign)
+}
+
+pub async fn get_reward_campaigns(
+    contract: &Contract,
+) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let response = contract
+        .view("get_reward_campaigns")
+        .args_json(json!({}))
+        .await?
+        .json::<Vec<serde_json::Value>>()
+        .unwrap();
+    Ok(response)
+}
+
 pub async fn unstake(
     contract: &Contract,
     user: Account,
@@ -1,4 +1,9 @@
-use near_sdk::{json_types::U128, serde_json::json, test_utils::accounts, Gas, NearToken};
+use near_sdk::{
+    json_types::{U128, U64},
+    serde_json::{self, json},
+    test_utils::accounts,
+    Gas, NearToken,
+};
 pub mod constants;
 pub mod helpers;
 mod types;
@@ -411,7 +416,11 @@ async fn test_allocate_refunds_excess_deposit() -> Result<(), Box<dyn std::error
     assert!(result.is_success());
 
     let fees = NearToken::from_millinear(2);
-    let storage_cost: U128 = contract.view("get_storage_cost").await?.json().unwrap();
+    let storage_cost: U128 = contract
+        .view("get_allocation_storage_cost")
+        .await?
+        .json()
+        .unwrap();
     assert!(
         alice.view_account().await?.balance.as_yoctonear()
             > pre_balance.as_yoctonear() - fees.as_yoctonear() - storage_cost.0
@@ -691,3 +700,327 @@ async fn test_total_allocated_for_many_users_with_many_allocations_at_different_
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_escrowed_allocation() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = accounts(4);
+    let _ = stake(&contract, alice.clone(), 10).await?;
+
+    let result = alice
+        .call(contract.id(), "allocate_escrowed")
+        .args_json(json!({
+            "recipient": bob,
+            "amount": U128::from(4 * ONE_NEAR),
+        }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let locked_balance: U128 = contract
+        .view("get_locked_balance")
+        .args_json(json!({ "account_id": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(locked_balance, U128(4 * ONE_NEAR));
+
+    // the locked TruNEAR cannot be transferred
+    let alice_balance = get_trunear_balance(&contract, alice.id()).await?;
+    let transfer = alice
+        .call(contract.id(), "ft_transfer")
+        .args_json(json!({
+            "receiver_id": owner.id(),
+            "amount": U128(alice_balance),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?;
+    check_error_msg(transfer, "Insufficient unlocked TruNEAR balance");
+
+    // deallocating distributes the rewards on the locked TruNEAR and releases the principal
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+    let deallocation = alice
+        .call(contract.id(), "deallocate")
+        .args_json(json!({
+            "recipient": bob,
+            "amount": U128::from(4 * ONE_NEAR),
+        }))
+        .transact()
+        .await?;
+    assert!(deallocation.is_success());
+    assert!(get_trunear_balance(&contract, &bob).await? > 0);
+
+    let locked_balance: U128 = contract
+        .view("get_locked_balance")
+        .args_json(json!({ "account_id": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(locked_balance, U128(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reassign_allocation() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    setup_allocation(&alice, &accounts(4), 4 * ONE_NEAR, contract.id()).await?;
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    let result = alice
+        .call(contract.id(), "reassign_allocation")
+        .args_json(json!({
+            "old_recipient": accounts(4),
+            "new_recipient": accounts(5),
+            "settle_first": true,
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    assert!(get_trunear_balance(&contract, &accounts(4)).await? > 0);
+
+    let allocations: Vec<AllocationInfo> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0].recipient, accounts(5));
+    assert_eq!(allocations[0].near_amount, U128(4 * ONE_NEAR));
+
+    let event_json = get_event(result.logs());
+    assert_eq!(event_json["event"], "allocation_reassigned_event");
+    assert_eq!(event_json["data"][0]["settled"], true);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expired_allocation_can_be_removed_by_anyone() -> Result<(), Box<dyn std::error::Error>>
+{
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    setup_allocation(&alice, &accounts(4), 4 * ONE_NEAR, contract.id()).await?;
+
+    let expires_at_epoch = get_current_epoch(&contract).await? + 2;
+    let result = alice
+        .call(contract.id(), "set_allocation_expiry")
+        .args_json(json!({
+            "recipient": accounts(4),
+            "expires_at_epoch": U64(expires_at_epoch),
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    // the allocation accrues rewards until the epoch before expiry
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    // the share price is recorded before the first total staked update after expiry is applied
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+    let expiry_share_price: Option<serde_json::Value> = contract
+        .view("get_expiry_share_price")
+        .args_json(json!({ "epoch": U64(expires_at_epoch) }))
+        .await?
+        .json()?;
+    assert!(expiry_share_price.is_some());
+
+    // the allocation cannot be removed before its final distribution
+    let removal = owner
+        .call(contract.id(), "remove_expired_allocation")
+        .args_json(json!({
+            "distributor": alice.id(),
+            "recipient": accounts(4),
+        }))
+        .transact()
+        .await?;
+    check_error_msg(removal, "The expired allocation has undistributed rewards");
+
+    let result = alice
+        .call(contract.id(), "distribute_rewards")
+        .args_json(json!({
+            "recipient": accounts(4),
+            "in_near": false,
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let alice_near_balance = alice.view_account().await?.balance;
+    let removal = owner
+        .call(contract.id(), "remove_expired_allocation")
+        .args_json(json!({
+            "distributor": alice.id(),
+            "recipient": accounts(4),
+        }))
+        .transact()
+        .await?;
+    assert!(removal.is_success());
+    let event = get_event(removal.logs());
+    assert_eq!(event["event"], "expired_allocation_removed_event");
+
+    // the storage deposit is refunded to the distributor
+    assert!(alice.view_account().await?.balance > alice_near_balance);
+    let allocations: Vec<AllocationInfo> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert!(allocations.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_allocation_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    setup_allocation(&alice, &accounts(4), 3 * ONE_NEAR, contract.id()).await?;
+
+    let result = alice
+        .call(contract.id(), "set_strict_allocation_mode")
+        .args_json(json!({ "enabled": true }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    let event = get_event(result.logs());
+    assert_eq!(event["event"], "strict_allocation_mode_set_event");
+
+    // alice staked 5 NEAR, so she cannot allocate more than 2 NEAR on top of her allocation
+    let result = alice
+        .call(contract.id(), "allocate")
+        .args_json(json!({
+            "recipient": accounts(5),
+            "amount": U128::from(3 * ONE_NEAR),
+        }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?;
+    check_error_msg(
+        result,
+        "The allocated amount is not covered by the withdrawable balance",
+    );
+
+    // nor unstake or transfer the TruNEAR covering her allocation
+    let result = alice
+        .call(contract.id(), "unstake")
+        .args_json(json!({ "amount": U128::from(3 * ONE_NEAR) }))
+        .deposit(NearToken::from_near(1))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    check_error_msg(
+        result,
+        "The allocated amount is not covered by the withdrawable balance",
+    );
+
+    let result = alice
+        .call(contract.id(), "ft_transfer")
+        .args_json(json!({
+            "receiver_id": owner.id(),
+            "amount": U128::from(3 * ONE_NEAR),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?;
+    check_error_msg(
+        result,
+        "The allocated amount is not covered by the withdrawable balance",
+    );
+
+    // once opted out, the allocation is no longer enforced
+    let result = alice
+        .call(contract.id(), "set_strict_allocation_mode")
+        .args_json(json!({ "enabled": false }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    let is_strict: bool = contract
+        .view("is_strict_allocation_mode")
+        .args_json(json!({ "account_id": alice.id() }))
+        .await?
+        .json()?;
+    assert!(!is_strict);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recipient_consent() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = setup_user(&sandbox, "bob").await?;
+
+    let result = owner
+        .call(contract.id(), "set_recipient_consent_required")
+        .args_json(json!({ "required": true }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    // bob isn't whitelisted, so the allocation stays pending until he accepts it
+    setup_allocation(&alice, bob.id(), 2 * ONE_NEAR, contract.id()).await?;
+    let allocations: Vec<AllocationInfo> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert!(allocations[0].pending);
+
+    move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await?;
+    let result = alice
+        .call(contract.id(), "distribute_rewards")
+        .args_json(json!({
+            "recipient": bob.id(),
+            "in_near": false,
+        }))
+        .transact()
+        .await?;
+    check_error_msg(
+        result,
+        "The allocation has not been accepted by the recipient",
+    );
+
+    let result = bob
+        .call(contract.id(), "accept_allocation")
+        .args_json(json!({ "distributor": alice.id() }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    let event = get_event(result.logs());
+    assert_eq!(event["event"], "allocation_accepted_event");
+
+    let result = alice
+        .call(contract.id(), "distribute_rewards")
+        .args_json(json!({
+            "recipient": bob.id(),
+            "in_near": false,
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    assert!(get_trunear_balance(&contract, bob.id()).await? > 0);
+
+    // bob leaves the allocation and alice's storage deposit is refunded
+    let alice_near_balance = alice.view_account().await?.balance;
+    let result = bob
+        .call(contract.id(), "reject_allocation")
+        .args_json(json!({ "distributor": alice.id() }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    let event = get_event(result.logs());
+    assert_eq!(event["event"], "allocation_rejected_event");
+    assert!(alice.view_account().await?.balance > alice_near_balance);
+
+    let allocations: Vec<AllocationInfo> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert!(allocations.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use near_sdk::{json_types::U128, serde_json::json, test_utils::accounts, AccountId, NearToken};
+use near_workspaces::operations::Function;
+pub mod constants;
+pub mod helpers;
+
+use helpers::*;
+use serde_json::Value;
+
+/// The number of allocations created in a single batch transaction.
+const ALLOCATIONS_PER_TRANSACTION: usize = 20;
+
+/// The gas burnt by allocating to a new recipient, distributing a batch starting at the
+/// last recipient, distributing the rewards of an allocation and fully deallocating it.
+struct AllocationGas {
+    allocate: u64,
+    distribute_batch: u64,
+    distribute: u64,
+    deallocate: u64,
+}
+
+/// Creates the given number of allocations for a distributor and measures the gas of the allocation operations.
+async fn measure_allocation_gas(
+    recipients: usize,
+) -> Result<AllocationGas, Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user_with_custom_balance(
+        &owner,
+        &contract,
+        "alice",
+        NearToken::from_near(50),
+    )
+    .await?;
+    let bob = accounts(4);
+    setup_allocation(&alice, &bob, ONE_NEAR, contract.id()).await?;
+
+    let storage_cost: U128 = contract.view("get_allocation_storage_cost").await?.json()?;
+    let other_recipients: Vec<AccountId> = (1..recipients)
+        .map(|i| format!("recipient-{i}.near").parse().unwrap())
+        .collect();
+    for chunk in other_recipients.chunks(ALLOCATIONS_PER_TRANSACTION) {
+        let mut transaction = alice.batch(contract.id());
+        for recipient in chunk {
+            transaction = transaction.call(
+                Function::new("allocate")
+                    .args_json(json!({
+                        "recipient": recipient,
+                        "amount": U128(ONE_NEAR),
+                    }))
+                    .deposit(NearToken::from_yoctonear(storage_cost.0))
+                    .gas(near_workspaces::types::Gas::from_tgas(14)),
+            );
+        }
+        assert!(transaction.transact().await?.is_success());
+    }
+
+    let allocations: Vec<Value> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(allocations.len(), recipients);
+
+    move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await?;
+
+    // the new recipient is ordered after all other recipients
+    let last_recipient: AccountId = "zulu.near".parse().unwrap();
+    let allocate = alice
+        .call(contract.id(), "allocate")
+        .args_json(json!({
+            "recipient": last_recipient,
+            "amount": U128(ONE_NEAR),
+        }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?;
+    assert!(allocate.is_success());
+
+    let distribute_batch = alice
+        .call(contract.id(), "distribute_batch")
+        .args_json(json!({
+            "in_near": false,
+            "from_recipient": last_recipient,
+            "limit": 1,
+        }))
+        .transact()
+        .await?;
+    assert!(distribute_batch.is_success());
+
+    let distribute = alice
+        .call(contract.id(), "distribute_rewards")
+        .args_json(json!({
+            "recipient": bob,
+            "in_near": false,
+        }))
+        .transact()
+        .await?;
+    assert!(distribute.is_success());
+
+    let deallocate = alice
+        .call(contract.id(), "deallocate")
+        .args_json(json!({
+            "recipient": bob,
+            "amount": U128(ONE_NEAR),
+        }))
+        .transact()
+        .await?;
+    assert!(deallocate.is_success());
+
+    Ok(AllocationGas {
+        allocate: allocate.total_gas_burnt.as_gas(),
+        distribute_batch: distribute_batch.total_gas_burnt.as_gas(),
+        distribute: distribute.total_gas_burnt.as_gas(),
+        deallocate: deallocate.total_gas_burnt.as_gas(),
+    })
+}
+
+/// Checks that the gas of an operation is within 25% of its gas with a single allocation.
+fn check_gas_is_constant(operation: &str, recipients: usize, single_allocation_gas: u64, gas: u64) {
+    assert!(
+        gas * 4 <= single_allocation_gas * 5,
+        "{} burnt {} gas with {} allocations, {} gas with a single allocation",
+        operation,
+        gas,
+        recipients,
+        single_allocation_gas
+    );
+}
+
+#[tokio::test]
+async fn test_allocation_gas_does_not_grow_with_allocations(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let single_allocation_gas = measure_allocation_gas(1).await?;
+
+    for recipients in [50, 500] {
+        let gas = measure_allocation_gas(recipients).await?;
+        check_gas_is_constant(
+            "allocate",
+            recipients,
+            single_allocation_gas.allocate,
+            gas.allocate,
+        );
+        check_gas_is_constant(
+            "distribute_batch",
+            recipients,
+            single_allocation_gas.distribute_batch,
+            gas.distribute_batch,
+        );
+        check_gas_is_constant(
+            "distribute_rewards",
+            recipients,
+            single_allocation_gas.distribute,
+            gas.distribute,
+        );
+        check_gas_is_constant(
+            "deallocate",
+            recipients,
+            single_allocation_gas.deallocate,
+            gas.deallocate,
+        );
+    }
+
+    Ok(())
+}
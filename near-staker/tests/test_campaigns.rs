@@ -0,0 +1,114 @@
+use near_sdk::{serde_json::json, NearToken};
+pub mod helpers;
+
+use helpers::*;
+
+#[tokio::test]
+async fn test_create_reward_campaign() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+
+    let create_campaign = create_reward_campaign(&contract, &owner, 10, 5).await?;
+    let start_epoch = get_current_epoch(&contract).await?;
+
+    // verify the campaign
+    let campaigns = get_reward_campaigns(&contract).await?;
+    assert_eq!(campaigns.len(), 1);
+    assert_eq!(campaigns[0]["campaign_id"], "1");
+    assert_eq!(campaigns[0]["funder"], owner.id().to_string());
+    assert_eq!(campaigns[0]["total_amount"], (10 * ONE_NEAR).to_string());
+    assert_eq!(campaigns[0]["released_amount"], "0");
+    assert_eq!(campaigns[0]["end_epoch"], (start_epoch + 5).to_string());
+
+    // verify the event
+    let event_json = get_event(create_campaign.logs());
+    assert_eq!(event_json["event"], "reward_campaign_created_event");
+    assert_eq!(event_json["data"][0]["campaign_id"], "1");
+    assert_eq!(event_json["data"][0]["amount"], (10 * ONE_NEAR).to_string());
+    assert_eq!(event_json["data"][0]["num_epochs"], "5");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_reward_campaign_by_non_owner_fails() -> Result<(), Box<dyn std::error::Error>>
+{
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    let create_campaign = alice
+        .call(contract.id(), "create_reward_campaign")
+        .args_json(json!({ "num_epochs": 5 }))
+        .deposit(NearToken::from_near(10))
+        .transact()
+        .await?;
+    check_error_msg(create_campaign, "Only the owner can call this method");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reward_campaign_is_released_on_total_staked_update(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    let _ = create_reward_campaign(&contract, &owner, 10, 1_000_000).await?;
+    let (total_staked_before, _) = get_total_staked(contract.clone()).await?;
+
+    move_epoch_forward(&sandbox, &contract).await?;
+    let update = update_total_staked(contract.clone(), owner.clone()).await?;
+
+    // the vested rewards are released as tax exempt stake
+    let campaigns = get_reward_campaigns(&contract).await?;
+    let released_amount: u128 = campaigns[0]["released_amount"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(released_amount > 0);
+    assert_eq!(
+        get_tax_exempt_stake(contract.clone()).await?,
+        released_amount
+    );
+
+    let (total_staked_after, _) = get_total_staked(contract.clone()).await?;
+    assert!(total_staked_after >= total_staked_before + released_amount);
+
+    // verify the release event
+    let event = get_events(update.logs())
+        .into_iter()
+        .find(|event| event["event"] == "reward_campaigns_released_event")
+        .unwrap();
+    assert_eq!(event["data"][0]["amount"], released_amount.to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_reward_campaign() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+
+    let _ = create_reward_campaign(&contract, &owner, 10, 1_000_000).await?;
+    let owner_balance = owner.view_account().await?.balance;
+
+    let cancel_campaign = owner
+        .call(contract.id(), "cancel_reward_campaign")
+        .args_json(json!({ "campaign_id": "1" }))
+        .transact()
+        .await?;
+    assert!(cancel_campaign.is_success());
+
+    // the unvested amount is refunded to the owner
+    let event_json = get_event(cancel_campaign.logs());
+    assert_eq!(event_json["event"], "reward_campaign_cancelled_event");
+    let refunded_amount: u128 = event_json["data"][0]["refunded_amount"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(refunded_amount > 0);
+    assert!(owner.view_account().await?.balance > owner_balance);
+
+    Ok(())
+}
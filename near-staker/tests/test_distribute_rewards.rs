@@ -132,6 +132,27 @@ async fn test_distribute_rewards_in_trunear() -> Result<(), Box<dyn std::error::
         share_price_denom.to_string()
     );
     assert_eq!(event_json["data"][0]["in_near"], false);
+    assert_eq!(
+        event_json["data"][0]["total_shares_distributed"],
+        trunear_amount.to_string()
+    );
+    assert_eq!(
+        event_json["data"][0]["total_near_distributed"],
+        near_amount.to_string()
+    );
+    assert_eq!(event_json["data"][0]["total_fees_paid"], 0.to_string());
+
+    // the distribution is recorded in the allocation's ledger
+    let allocations: serde_json::Value = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(
+        allocations[0]["shares_distributed"],
+        trunear_amount.to_string()
+    );
+    assert_eq!(allocations[0]["near_distributed"], near_amount.to_string());
 
     Ok(())
 }
@@ -282,8 +303,7 @@ async fn test_distribute_rewards_in_near_does_not_alter_tax_exempt_stake(
     let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
     let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
     let bob = setup_whitelisted_user(&owner, &contract, "bob").await?;
-    setup_allocation(&alice, bob.id(), 4 * ONE_NEAR, contract.id()).await?;
-
+    // TruNEAR is not in circulation yet, so the distribution fee applies immediately
     let set_dist_fee = owner
         .call(contract.id(), "set_distribution_fee")
         .args_json(json!({
@@ -292,6 +312,7 @@ async fn test_distribute_rewards_in_near_does_not_alter_tax_exempt_stake(
         .transact()
         .await?;
     assert!(set_dist_fee.is_success());
+    setup_allocation(&alice, bob.id(), 4 * ONE_NEAR, contract.id()).await?;
 
     let pre_balance_alice = alice.view_account().await?.balance;
     let pre_balance_bob = bob.view_account().await?.balance;
@@ -564,8 +585,7 @@ async fn test_distribute_rewards_gives_fees_to_treasury() -> Result<(), Box<dyn
 {
     let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
     let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
-    setup_allocation(&alice, &accounts(4), 4 * ONE_NEAR, contract.id()).await?;
-
+    // TruNEAR is not in circulation yet, so the distribution fee applies immediately
     let set_dist_fee = owner
         .call(contract.id(), "set_distribution_fee")
         .args_json(json!({
@@ -574,6 +594,7 @@ async fn test_distribute_rewards_gives_fees_to_treasury() -> Result<(), Box<dyn
         .transact()
         .await?;
     assert!(set_dist_fee.is_success());
+    setup_allocation(&alice, &accounts(4), 4 * ONE_NEAR, contract.id()).await?;
 
     let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
 
@@ -594,12 +615,60 @@ async fn test_distribute_rewards_gives_fees_to_treasury() -> Result<(), Box<dyn
 }
 
 #[tokio::test]
-async fn test_distribute_rewards_in_near_with_no_trunear_if_dist_fee_is_set_fails(
+async fn test_distribute_rewards_splits_fees_across_beneficiaries(
 ) -> Result<(), Box<dyn std::error::Error>> {
     let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
     let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    // TruNEAR is not in circulation yet, so the distribution fee applies immediately
+    let set_dist_fee = owner
+        .call(contract.id(), "set_distribution_fee")
+        .args_json(json!({
+            "new_distribution_fee": 1000 //10%
+        }))
+        .transact()
+        .await?;
+    assert!(set_dist_fee.is_success());
     setup_allocation(&alice, &accounts(4), 4 * ONE_NEAR, contract.id()).await?;
 
+    // the treasury and a partner share the fees equally
+    let set_fee_split = owner
+        .call(contract.id(), "set_fee_split")
+        .args_json(json!({
+            "fee_split": [
+                { "account_id": accounts(1), "basis_points": 5000 },
+                { "account_id": accounts(5), "basis_points": 5000 },
+            ]
+        }))
+        .transact()
+        .await?;
+    assert!(set_fee_split.is_success());
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    let distribution = alice
+        .call(contract.id(), "distribute_rewards")
+        .args_json(json!({
+            "recipient": accounts(4),
+            "in_near": false,
+        }))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+
+    let treasury_balance = get_trunear_balance(&contract, &accounts(1)).await?;
+    let partner_balance = get_trunear_balance(&contract, &accounts(5)).await?;
+    assert!(partner_balance > 0);
+    assert!(treasury_balance.abs_diff(partner_balance) <= 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_distribute_rewards_in_near_with_no_trunear_if_dist_fee_is_set_fails(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    // TruNEAR is not in circulation yet, so the distribution fee applies immediately
     let set_dist_fee = owner
         .call(contract.id(), "set_distribution_fee")
         .args_json(json!({
@@ -608,6 +677,7 @@ async fn test_distribute_rewards_in_near_with_no_trunear_if_dist_fee_is_set_fail
         .transact()
         .await?;
     assert!(set_dist_fee.is_success());
+    setup_allocation(&alice, &accounts(4), 4 * ONE_NEAR, contract.id()).await?;
 
     let alice_balance = get_trunear_balance(&contract, alice.id()).await?;
 
@@ -814,7 +884,7 @@ async fn test_distribute_all_in_trunear() -> Result<(), Box<dyn std::error::Erro
     let events_json = get_events(distribution.logs());
     assert!(events_json.len() == 5);
 
-    // verify bob distributed_rewards_event
+    // verify bob distributed_rewards_event, recipients are distributed to in account id order so bob is first
     let bob_distribution_event: Event<DistributedRewardsEvent> =
         find_event(&events_json, |event: &Value| {
             event["event"] == "distributed_rewards_event"
@@ -830,7 +900,7 @@ async fn test_distribute_all_in_trunear() -> Result<(), Box<dyn std::error::Erro
             recipient: bob.to_string(),
             shares: bob_trunear_amount.to_string(),
             near_amount: bob_near_amount.to_string(),
-            user_balance: alice_balance.to_string(),
+            user_balance: (alice_balance + charlie_trunear_amount).to_string(),
             recipient_balance: bob_post_balance.to_string(),
             fees: 0.to_string(),
             treasury_balance: 0.to_string(),
@@ -859,7 +929,7 @@ async fn test_distribute_all_in_trunear() -> Result<(), Box<dyn std::error::Erro
             recipient: charlie.to_string(),
             shares: charlie_trunear_amount.to_string(),
             near_amount: charlie_near_amount.to_string(),
-            user_balance: (alice_balance + bob_trunear_amount).to_string(),
+            user_balance: alice_balance.to_string(),
             recipient_balance: charlie_post_balance.to_string(),
             fees: 0.to_string(),
             treasury_balance: 0.to_string(),
@@ -1704,3 +1774,340 @@ async fn test_distribute_all_with_locked_contract_should_fail(
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_distribute_rewards_to_percentage_allocation() -> Result<(), Box<dyn std::error::Error>>
+{
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = accounts(4);
+    let _ = stake(&contract, alice.clone(), 10).await?;
+
+    let allocation = alice
+        .call(contract.id(), "allocate_percentage")
+        .args_json(json!({
+            "recipient": bob,
+            "basis_points": 5000,
+        }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?;
+    assert!(allocation.is_success());
+    assert_eq!(
+        get_event(allocation.logs())["event"],
+        "allocated_percentage_event"
+    );
+
+    // half of alice's position is allocated to bob
+    let alice_balance = get_trunear_balance(&contract, alice.id()).await?;
+    let allocations = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json::<near_sdk::serde_json::Value>()?;
+    assert_eq!(allocations[0]["basis_points"], 5000);
+    assert_eq!(
+        allocations[0]["near_amount"],
+        (alice_balance / 2).to_string()
+    );
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+    let (required_shares, _) = calculate_distribute_amounts(&contract, alice.id(), false).await?;
+    assert!(required_shares > 0);
+
+    let distribution = alice
+        .call(contract.id(), "distribute_rewards")
+        .args_json(json!({
+            "recipient": bob,
+            "in_near": false,
+        }))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+    assert_eq!(get_trunear_balance(&contract, &bob).await?, required_shares);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_claim_allocation_rewards() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = setup_whitelisted_user(&owner, &contract, "bob").await?;
+    setup_allocation(&alice, bob.id(), 4 * ONE_NEAR, contract.id()).await?;
+
+    let approval = alice
+        .call(contract.id(), "approve_distribution")
+        .args_json(json!({
+            "recipient": bob.id(),
+            "amount": U128(ONE_NEAR),
+        }))
+        .transact()
+        .await?;
+    assert!(approval.is_success());
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+    let (required_shares, _) = calculate_distribute_amounts(&contract, alice.id(), false).await?;
+
+    // bob claims the rewards without alice distributing them
+    let claim = bob
+        .call(contract.id(), "claim_allocation_rewards")
+        .args_json(json!({ "distributor": alice.id() }))
+        .transact()
+        .await?;
+    assert!(claim.is_success());
+    assert_eq!(
+        get_trunear_balance(&contract, bob.id()).await?,
+        required_shares
+    );
+
+    let allowance: U128 = contract
+        .view("get_distribution_allowance")
+        .args_json(json!({
+            "distributor": alice.id(),
+            "recipient": bob.id(),
+        }))
+        .await?
+        .json()?;
+    assert_eq!(allowance, U128(ONE_NEAR - required_shares));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_keeper_distributes_on_schedule() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let keeper = setup_whitelisted_user(&owner, &contract, "keeper").await?;
+    let bob = accounts(4);
+    setup_allocation(&alice, &bob, 4 * ONE_NEAR, contract.id()).await?;
+
+    let set_keeper = alice
+        .call(contract.id(), "set_distribution_keeper")
+        .args_json(json!({
+            "keeper": keeper.id(),
+            "interval": "100",
+        }))
+        .transact()
+        .await?;
+    assert!(set_keeper.is_success());
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+    let distribution = keeper
+        .call(contract.id(), "distribute_all_for")
+        .args_json(json!({ "distributor": alice.id() }))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+    let bob_balance = get_trunear_balance(&contract, &bob).await?;
+    assert!(bob_balance > 0);
+
+    // bob is skipped until the interval has elapsed
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+    let distribution = keeper
+        .call(contract.id(), "distribute_all_for")
+        .args_json(json!({ "distributor": alice.id() }))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+    assert_eq!(get_trunear_balance(&contract, &bob).await?, bob_balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_distribute_batch() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = accounts(4);
+    let charlie = accounts(5);
+    setup_allocation(&alice, &bob, 4 * ONE_NEAR, contract.id()).await?;
+    setup_allocation(&alice, &charlie, 2 * ONE_NEAR, contract.id()).await?;
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    let distribution = alice
+        .call(contract.id(), "distribute_batch")
+        .args_json(json!({
+            "in_near": false,
+            "limit": 1,
+        }))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+    assert!(get_trunear_balance(&contract, &bob).await? > 0);
+    assert_eq!(get_trunear_balance(&contract, &charlie).await?, 0);
+
+    let cursor: Option<AccountId> = contract
+        .view("get_distribution_cursor")
+        .args_json(json!({ "distributor": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(cursor, Some(charlie.clone()));
+
+    // the next batch continues from the cursor
+    let distribution = alice
+        .call(contract.id(), "distribute_batch")
+        .args_json(json!({
+            "in_near": false,
+            "limit": 1,
+        }))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+    assert!(get_trunear_balance(&contract, &charlie).await? > 0);
+
+    let event_json = get_event(distribution.logs());
+    assert_eq!(event_json["event"], "distributed_batch_event");
+    assert_eq!(event_json["data"][0]["from_recipient"], charlie.to_string());
+    assert!(event_json["data"][0]["next_recipient"].is_null());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_distribute_rewards_via_unstake() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = setup_whitelisted_user(&owner, &contract, "bob").await?;
+    setup_allocation(&alice, bob.id(), 4 * ONE_NEAR, contract.id()).await?;
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    let storage_cost: U128 = contract.view("get_storage_cost").await?.json()?;
+    let distribution = alice
+        .call(contract.id(), "distribute_rewards_via_unstake")
+        .args_json(json!({ "recipient": bob.id() }))
+        .deposit(NearToken::from_yoctonear(storage_cost.0))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+
+    // the rewards are unstaked in bob's name rather than credited as TruNEAR
+    assert!(get_trunear_balance(&contract, bob.id()).await? <= 1);
+    let unstake_nonce: U128 = contract.view("get_latest_unstake_nonce").await?.json()?;
+    assert_eq!(unstake_nonce, U128(1));
+    let events = get_events(distribution.logs());
+    let event_json = events
+        .iter()
+        .find(|event| event["event"] == "distributed_rewards_event")
+        .unwrap();
+    assert_eq!(event_json["data"][0]["in_near"], true);
+    assert_eq!(events.last().unwrap()["event"], "unstaked_event");
+    let near_amount: u128 = event_json["data"][0]["near_amount"]
+        .as_str()
+        .unwrap()
+        .parse()?;
+    assert!(near_amount > 0);
+
+    for _ in 0..4 {
+        move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await?;
+    }
+
+    let pre_balance = bob.view_account().await?.balance;
+    let withdraw = bob
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "unstake_nonce": unstake_nonce }))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(withdraw.is_success());
+
+    // the rewards and the storage deposit are paid out to bob, less the gas fees
+    let fees = NearToken::from_millinear(5);
+    assert!(
+        bob.view_account().await?.balance.as_yoctonear() - pre_balance.as_yoctonear()
+            >= near_amount + storage_cost.0 - fees.as_yoctonear()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_distribute_rewards_via_unstake_to_non_whitelisted_recipient_fails(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = accounts(4);
+    setup_allocation(&alice, &bob, 4 * ONE_NEAR, contract.id()).await?;
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    let distribution = alice
+        .call(contract.id(), "distribute_rewards_via_unstake")
+        .args_json(json!({ "recipient": bob }))
+        .deposit(NearToken::from_near(1))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    check_error_msg(
+        distribution,
+        "The recipient must be whitelisted to withdraw unstaked rewards",
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_distribute_rewards_call_to_account_without_contract(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = accounts(4);
+    setup_allocation(&alice, &bob, 4 * ONE_NEAR, contract.id()).await?;
+
+    let _ = move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await;
+
+    let alice_balance = get_trunear_balance(&contract, alice.id()).await?;
+    let distribution = alice
+        .call(contract.id(), "distribute_rewards_call")
+        .args_json(json!({
+            "recipient": bob,
+            "msg": "deposit",
+        }))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(distribution.is_success());
+
+    let events = get_events(distribution.logs());
+
+    // bob has no contract to accept the rewards, so they are refunded to alice
+    let used_shares: U128 = distribution.json()?;
+    assert_eq!(used_shares, U128(0));
+    assert_eq!(get_trunear_balance(&contract, &bob).await?, 0);
+
+    let distributed_event = events
+        .iter()
+        .find(|event| event["event"] == "distributed_rewards_event")
+        .unwrap();
+    let shares: u128 = distributed_event["data"][0]["shares"]
+        .as_str()
+        .unwrap()
+        .parse()?;
+    assert!(shares > 0);
+    let fees: u128 = distributed_event["data"][0]["fees"]
+        .as_str()
+        .unwrap()
+        .parse()?;
+    assert_eq!(
+        get_trunear_balance(&contract, alice.id()).await?,
+        alice_balance - fees
+    );
+
+    let refunded_event = events.last().unwrap();
+    assert_eq!(refunded_event["event"], "distribution_refunded_event");
+    assert_eq!(refunded_event["data"][0]["shares"], shares.to_string());
+
+    let allocations: Vec<Value> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(allocations[0]["shares_distributed"], "0");
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use near_sdk::{serde_json::json, test_utils::accounts, Gas, NearToken};
+pub mod constants;
+pub mod helpers;
+
+use constants::*;
+use helpers::*;
+
+#[tokio::test]
+async fn test_donate() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, pool) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    let total_supply = get_total_supply(&contract).await?;
+
+    // the owner donates 5 NEAR to the default pool
+    let donation = donate(&contract, &owner, pool.id(), 5).await?;
+
+    // no TruNEAR is minted and the donation is accounted as staked
+    assert_eq!(get_total_supply(&contract).await?, total_supply);
+    let (total_staked, _) = get_total_staked(contract.clone()).await?;
+    assert_eq!(total_staked, 15 * ONE_NEAR);
+
+    // the donation is shared by all TruNEAR holders
+    let share_price = get_share_price(contract.clone()).await?;
+    assert_eq!(share_price, 3 * SHARE_PRICE_SCALING_FACTOR / 2);
+    let max_withdraw = get_max_withdraw(contract.clone(), alice.clone()).await?;
+    assert_eq!(max_withdraw, 15 * ONE_NEAR);
+
+    // verify the donation event
+    let event_json = get_event(donation.logs());
+    assert_eq!(event_json["event"], "donation_event");
+    assert_eq!(event_json["data"][0]["donor"], owner.id().to_string());
+    assert_eq!(event_json["data"][0]["amount"], (5 * ONE_NEAR).to_string());
+    assert_eq!(
+        event_json["data"][0]["total_staked"],
+        (15 * ONE_NEAR).to_string()
+    );
+    assert_eq!(
+        event_json["data"][0]["total_supply"],
+        total_supply.to_string()
+    );
+    assert_eq!(event_json["data"][0]["pool_id"], pool.id().to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_donate_by_whitelisted_user() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, pool) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = setup_whitelisted_user(&owner, &contract, "bob").await?;
+
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    let _ = donate(&contract, &bob, pool.id(), 2).await?;
+
+    let max_withdraw = get_max_withdraw(contract.clone(), alice.clone()).await?;
+    assert_eq!(max_withdraw, 12 * ONE_NEAR);
+    assert_eq!(get_trunear_balance(&contract, bob.id()).await?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_donation_is_not_charged_fees() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, pool) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    set_fee(&contract, &owner, 1000).await?;
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    let _ = donate(&contract, &owner, pool.id(), 5).await?;
+
+    // the donation is tax exempt
+    let (total_staked, _) = get_total_staked(contract.clone()).await?;
+    let tax_exempt_stake = get_tax_exempt_stake(contract.clone()).await?;
+    assert_eq!(tax_exempt_stake, total_staked);
+
+    // collecting fees does not mint any TruNEAR to the treasury
+    let collect_fees = alice
+        .call(contract.id(), "collect_fees")
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(collect_fees.is_success());
+    assert_eq!(get_trunear_balance(&contract, &accounts(1)).await?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_donate_by_non_whitelisted_user_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let (_, sandbox, contract, pool) = setup_contract_with_pool().await?;
+    let alice = setup_user(&sandbox, "alice").await?;
+
+    let donation = alice
+        .call(contract.id(), "donate")
+        .args_json(json!({
+            "pool_id": pool.id(),
+        }))
+        .deposit(NearToken::from_near(5))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(donation.is_failure());
+    check_error_msg(donation, "User not whitelisted");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_donate_to_disabled_pool_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let second_pool = setup_pool(&sandbox, &owner, "second_pool").await?;
+
+    let result = owner
+        .call(contract.id(), "add_pool")
+        .args_json(json!({
+            "pool_id": second_pool.id(),
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let result = owner
+        .call(contract.id(), "disable_pool")
+        .args_json(json!({
+            "pool_id": second_pool.id(),
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let donation = owner
+        .call(contract.id(), "donate")
+        .args_json(json!({
+            "pool_id": second_pool.id(),
+        }))
+        .deposit(NearToken::from_near(5))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(donation.is_failure());
+    check_error_msg(donation, "Delegation pool not enabled");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_donate_below_min_deposit_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, pool) = setup_contract_with_pool().await?;
+
+    let donation = owner
+        .call(contract.id(), "donate")
+        .args_json(json!({
+            "pool_id": pool.id(),
+        }))
+        .deposit(NearToken::from_millinear(500))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(donation.is_failure());
+    check_error_msg(donation, "Deposit amount is below minimum deposit");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_donate_when_paused_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, pool) = setup_contract_with_pool().await?;
+
+    let pause = owner.call(contract.id(), "pause").transact().await?;
+    assert!(pause.is_success());
+
+    let donation = owner
+        .call(contract.id(), "donate")
+        .args_json(json!({
+            "pool_id": pool.id(),
+        }))
+        .deposit(NearToken::from_near(5))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(donation.is_failure());
+    check_error_msg(donation, "Contract is paused");
+
+    Ok(())
+}
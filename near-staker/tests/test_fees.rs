@@ -239,3 +239,252 @@ async fn test_collect_fees_when_contract_not_in_sync_fails(
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_collect_fees_rebates_fee_override() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = setup_whitelisted_user(&owner, &contract, "bob").await?;
+
+    set_fee(&contract, &owner, 1000).await?;
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    let _ = stake(&contract, bob.clone(), 10).await?;
+
+    // alice is exempt from fees
+    let set_fee_override = owner
+        .call(contract.id(), "set_fee_override")
+        .args_json(serde_json::json!({
+            "account_id": alice.id(),
+            "fee": 0,
+            "distribution_fee": 0,
+        }))
+        .transact()
+        .await?;
+    assert!(set_fee_override.is_success());
+    let fee_overrides = contract
+        .view("get_fee_overrides")
+        .await?
+        .json::<Vec<serde_json::Value>>()?;
+    assert_eq!(fee_overrides.len(), 1);
+    assert_eq!(fee_overrides[0]["account_id"], alice.id().to_string());
+
+    // the first collection starts the fee period of the override
+    move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await?;
+    let fees_collected = alice
+        .call(contract.id(), "collect_fees")
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(fees_collected.is_success());
+    let alice_balance = get_trunear_balance(&contract, alice.id()).await?;
+    let bob_balance = get_trunear_balance(&contract, bob.id()).await?;
+
+    // accrue rewards and collect fees again
+    move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await?;
+    let share_price = get_share_price(contract.clone()).await?;
+    let fees_collected = alice
+        .call(contract.id(), "collect_fees")
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(fees_collected.is_success());
+
+    // alice receives a rebate while the share price and bob's balance are unchanged
+    assert_eq!(get_share_price(contract.clone()).await?, share_price);
+    assert!(get_trunear_balance(&contract, alice.id()).await? > alice_balance);
+    assert_eq!(get_trunear_balance(&contract, bob.id()).await?, bob_balance);
+    let rebate_event = get_events(fees_collected.logs())
+        .into_iter()
+        .find(|event| event["event"] == "fee_rebated_event")
+        .unwrap();
+    assert_eq!(
+        rebate_event["data"][0]["account_id"],
+        alice.id().to_string()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fee_increase_is_timelocked() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let _ = stake(&contract, alice.clone(), 10).await?;
+
+    // once TruNEAR is in circulation, fee increases are proposed
+    let propose_fee = owner
+        .call(contract.id(), "set_fee")
+        .args_json(serde_json::json!({ "new_fee": 100 }))
+        .transact()
+        .await?;
+    assert!(propose_fee.is_success());
+    let event_json = get_event(propose_fee.logs());
+    assert_eq!(event_json["event"], "config_change_proposed_event");
+
+    let staker_info = contract
+        .view("get_staker_info")
+        .await?
+        .json::<StakerInfo>()
+        .unwrap();
+    assert_eq!(staker_info.fee, 0);
+    let pending_changes = contract
+        .view("get_pending_config_changes")
+        .await?
+        .json::<Vec<serde_json::Value>>()?;
+    assert_eq!(pending_changes.len(), 1);
+    assert_eq!(pending_changes[0]["key"], "FEE");
+    assert_eq!(pending_changes[0]["new_value"], "100");
+
+    // the change cannot be executed before the delay
+    let execute = owner
+        .call(contract.id(), "execute_config_change")
+        .args_json(serde_json::json!({ "key": "FEE" }))
+        .transact()
+        .await?;
+    check_error_msg(execute, "Config change cannot be executed yet");
+
+    // the owner can cancel the change
+    let cancel = owner
+        .call(contract.id(), "cancel_config_change")
+        .args_json(serde_json::json!({ "key": "FEE" }))
+        .transact()
+        .await?;
+    assert!(cancel.is_success());
+    let event_json = get_event(cancel.logs());
+    assert_eq!(event_json["event"], "config_change_cancelled_event");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fee_increase_above_max_fee_increase_fails() -> Result<(), Box<dyn std::error::Error>>
+{
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let _ = stake(&contract, alice.clone(), 10).await?;
+
+    // fee increases are limited to 5% in a single change by default
+    let set_distribution_fee = owner
+        .call(contract.id(), "set_distribution_fee")
+        .args_json(serde_json::json!({ "new_distribution_fee": 1000 }))
+        .transact()
+        .await?;
+    check_error_msg(
+        set_distribution_fee,
+        "Fee increase exceeds the maximum fee increase",
+    );
+
+    // increases up to the maximum are proposed
+    let set_distribution_fee = owner
+        .call(contract.id(), "set_distribution_fee")
+        .args_json(serde_json::json!({ "new_distribution_fee": 500 }))
+        .transact()
+        .await?;
+    assert!(set_distribution_fee.is_success());
+    let pending_changes = contract
+        .view("get_pending_config_changes")
+        .await?
+        .json::<Vec<serde_json::Value>>()?;
+    assert_eq!(pending_changes[0]["key"], "DISTRIBUTION_FEE");
+    assert_eq!(pending_changes[0]["new_value"], "500");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_auto_collect_fees_on_total_staked_update() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    set_fee(&contract, &owner, 1000).await?;
+    let set_auto_collect_fees = owner
+        .call(contract.id(), "set_auto_collect_fees")
+        .args_json(serde_json::json!({ "auto_collect_fees": true }))
+        .transact()
+        .await?;
+    assert!(set_auto_collect_fees.is_success());
+    let _ = stake(&contract, alice.clone(), 10).await?;
+
+    // accrue rewards without syncing
+    move_epoch_forward(&sandbox, &contract).await?;
+    let pending_fees = contract
+        .view("get_pending_fees")
+        .await?
+        .json::<serde_json::Value>()?;
+    assert_eq!(pending_fees["near_amount"], "0");
+
+    // the fees are collected as part of the sync
+    let update = update_total_staked(contract.clone(), owner.clone()).await?;
+    assert!(get_events(update.logs())
+        .iter()
+        .any(|event| event["event"] == "fees_collected_event"));
+    assert!(get_trunear_balance(&contract, &accounts(1)).await? > 0);
+
+    let pending_fees = contract
+        .view("get_pending_fees")
+        .await?
+        .json::<serde_json::Value>()?;
+    assert_eq!(pending_fees["near_amount"], "0");
+    assert_eq!(pending_fees["shares_amount"], "0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_management_fee_accrues_on_sync_and_is_collected(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    // no TruNEAR is in circulation, so the management fee applies immediately
+    let set_management_fee = owner
+        .call(contract.id(), "set_management_fee")
+        .args_json(serde_json::json!({ "new_management_fee": 200 }))
+        .transact()
+        .await?;
+    assert!(set_management_fee.is_success());
+    let _ = stake(&contract, alice.clone(), 10).await?;
+
+    // the management fee accrues for the time elapsed since it was set
+    move_epoch_forward(&sandbox, &contract).await?;
+    let update = update_total_staked(contract.clone(), owner.clone()).await?;
+    let accrued_events: Vec<_> = get_events(update.logs())
+        .into_iter()
+        .filter(|event| event["event"] == "management_fee_accrued_event")
+        .collect();
+    assert_eq!(accrued_events.len(), 1);
+    let accrued = accrued_events[0]["data"][0]["accrued_management_fees"]
+        .as_str()
+        .unwrap()
+        .parse::<u128>()?;
+    assert!(accrued > 0);
+
+    let pending_fees = contract
+        .view("get_pending_fees")
+        .await?
+        .json::<serde_json::Value>()?;
+    assert!(
+        pending_fees["near_amount"]
+            .as_str()
+            .unwrap()
+            .parse::<u128>()?
+            >= accrued
+    );
+
+    // the accrued management fees are minted to the treasury on collection
+    let collect_fees = owner
+        .call(contract.id(), "collect_fees")
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(collect_fees.is_success());
+    assert_eq!(
+        get_trunear_balance(&contract, &accounts(1)).await?,
+        pending_fees["shares_amount"]
+            .as_str()
+            .unwrap()
+            .parse::<u128>()?
+    );
+
+    Ok(())
+}
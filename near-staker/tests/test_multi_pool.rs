@@ -453,6 +453,29 @@ async fn test_update_total_staked_multi_pool() -> Result<(), Box<dyn std::error:
     assert_eq!(total_staked.0, U128(ONE_NEAR * 8));
     assert!(total_staked.1 .0 != 0);
 
+    // verify the total staked updated event
+    let event_json = get_events(update_total_staked.logs())
+        .into_iter()
+        .find(|event| event["event"] == "total_staked_updated_event")
+        .unwrap();
+    let pools = event_json["data"][0]["pools"].as_array().unwrap();
+    assert_eq!(pools.len(), 2);
+    assert_eq!(pools[0]["pool_id"], pool.id().to_string());
+    assert_eq!(pools[0]["old_total_staked"], "0");
+    assert_eq!(pools[0]["new_total_staked"], (5 * ONE_NEAR).to_string());
+    assert_eq!(pools[1]["pool_id"], pool_2.id().to_string());
+    assert_eq!(pools[1]["new_total_staked"], (3 * ONE_NEAR).to_string());
+    assert_eq!(event_json["data"][0]["old_total_staked"], "0");
+    assert_eq!(
+        event_json["data"][0]["total_staked"],
+        (8 * ONE_NEAR).to_string()
+    );
+    assert_eq!(event_json["data"][0]["total_supply"], "0");
+    assert_eq!(
+        event_json["data"][0]["epoch"],
+        total_staked.1 .0.to_string()
+    );
+
     Ok(())
 }
 
@@ -517,6 +540,16 @@ async fn test_update_total_staked_with_failure() -> Result<(), Box<dyn std::erro
     assert!(update_total_staked.is_success());
     assert!(total_staked == pre_total_staked);
 
+    // verify the failure event lists the broken pool
+    let event_json = get_events(update_total_staked.logs())
+        .into_iter()
+        .find(|event| event["event"] == "total_staked_update_failed_event")
+        .unwrap();
+    assert_eq!(
+        event_json["data"][0]["failed_pools"],
+        json!([accounts(5).to_string()])
+    );
+
     // verify that the first pool was not updated
     let result = owner.view(contract.id(), "get_pools").await?;
     let pools: Vec<PoolInfo> = result.json()?;
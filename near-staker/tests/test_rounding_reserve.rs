@@ -0,0 +1,88 @@
+use near_sdk::{json_types::U128, serde_json::json, NearToken};
+pub mod helpers;
+
+use helpers::*;
+
+async fn get_rounding_reserve(
+    contract: &near_workspaces::Contract,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let response = contract
+        .view("get_rounding_reserve")
+        .args_json(json!({}))
+        .await?
+        .json::<serde_json::Value>()
+        .unwrap();
+    Ok(response)
+}
+
+#[tokio::test]
+async fn test_fund_rounding_reserve() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, pool) = setup_contract_with_pool().await?;
+
+    let fund_reserve = owner
+        .call(contract.id(), "fund_rounding_reserve")
+        .deposit(NearToken::from_near(5))
+        .transact()
+        .await?;
+    assert!(fund_reserve.is_success());
+
+    let reserve = get_rounding_reserve(&contract).await?;
+    assert_eq!(reserve["reserve_balance"], (5 * ONE_NEAR).to_string());
+    assert_eq!(reserve["remaining_reserve"], (5 * ONE_NEAR).to_string());
+    assert_eq!(reserve["pools"][0]["pool_id"], pool.id().to_string());
+
+    let event_json = get_event(fund_reserve.logs());
+    assert_eq!(event_json["event"], "rounding_reserve_funded_event");
+    assert_eq!(event_json["data"][0]["amount"], (5 * ONE_NEAR).to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rounding_subsidy_is_tracked_on_stake() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    let _ = stake(&contract, alice.clone(), 7).await?;
+
+    // the subsidy only covers rounding on the pool, which is a few yoctoNEAR at most
+    let reserve = get_rounding_reserve(&contract).await?;
+    let stake_subsidy: u128 = reserve["pools"][0]["stake_subsidy"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(stake_subsidy < 10);
+    assert_eq!(reserve["total_subsidy"], stake_subsidy.to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_set_rounding_reserve_threshold() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+
+    let set_threshold = owner
+        .call(contract.id(), "set_rounding_reserve_threshold")
+        .args_json(json!({ "threshold": U128(ONE_NEAR) }))
+        .transact()
+        .await?;
+    assert!(set_threshold.is_success());
+
+    let event_json = get_event(set_threshold.logs());
+    assert_eq!(event_json["event"], "set_rounding_reserve_threshold_event");
+    assert_eq!(event_json["data"][0]["old_threshold"], "0");
+    assert_eq!(event_json["data"][0]["new_threshold"], ONE_NEAR.to_string());
+
+    // only the owner can set the threshold
+    let set_threshold = alice
+        .call(contract.id(), "set_rounding_reserve_threshold")
+        .args_json(json!({ "threshold": U128(0) }))
+        .transact()
+        .await?;
+    check_error_msg(set_threshold, "Only the owner can call this method");
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use helpers::*;
 use near_sdk::{
     base64::{engine::general_purpose, Engine},
-    serde_json::json,
+    serde_json::{json, Value},
+    test_utils::accounts,
     Gas,
 };
 
@@ -44,6 +45,69 @@ async fn test_upgrade_and_migrate() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_upgrade_and_migrate_allocations() -> Result<(), Box<dyn std::error::Error>> {
+    // deploy an older version of the contract and allocate
+    let (owner, _, contract) =
+        setup_contract_with_code("./tests/upgrades/near_staker-upgrade.wasm".to_string()).await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    setup_allocation(&alice, &accounts(4), 2 * ONE_NEAR, contract.id()).await?;
+
+    let upgrade_contract_wasm = near_workspaces::compile_project("./").await?;
+    let upgrade = owner
+        .call(contract.id(), "upgrade")
+        .args_json(json!({
+           "code": general_purpose::STANDARD.encode(&upgrade_contract_wasm),
+           "migrate": true
+        }))
+        .gas(Gas::from_tgas(300))
+        .transact()
+        .await?;
+    assert!(upgrade.is_success());
+
+    // the allocations are returned before they are moved to the new storage layout
+    let allocations: Vec<Value> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0]["near_amount"], (2 * ONE_NEAR).to_string());
+    let (total_allocated, _, _, _) = get_total_allocated(&contract, alice.id()).await?;
+    assert_eq!(total_allocated, 2 * ONE_NEAR);
+
+    // the allocations are moved to the new storage layout by anyone
+
+    let migration = owner
+        .call(contract.id(), "migrate_allocations")
+        .args_json(json!({ "allocators": [alice.id()] }))
+        .transact()
+        .await?;
+    assert!(migration.is_success());
+
+    let allocations: Vec<Value> = contract
+        .view("get_allocations")
+        .args_json(json!({ "allocator": alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(allocations.len(), 1);
+    assert_eq!(allocations[0]["recipient"], accounts(4).to_string());
+    assert_eq!(allocations[0]["near_amount"], (2 * ONE_NEAR).to_string());
+
+    // consent is required for new allocations, allocations made before the upgrade stay active
+    assert_eq!(allocations[0]["pending"], false);
+    let consent_required: bool = contract
+        .view("is_recipient_consent_required")
+        .await?
+        .json()?;
+    assert!(consent_required);
+
+    let (total_allocated, _, _, _) = get_total_allocated(&contract, alice.id()).await?;
+    assert_eq!(total_allocated, 2 * ONE_NEAR);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_upgrade_by_non_owner_fails() -> Result<(), Box<dyn std::error::Error>> {
     // deploy an older version of the contract
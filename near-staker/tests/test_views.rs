@@ -302,6 +302,7 @@ async fn test_get_staker_info() -> Result<(), Box<dyn std::error::Error>> {
             default_delegation_pool: default_pool.id().clone(),
             fee: 100,
             dist_fee: 500,
+            management_fee: 0,
             min_deposit: U128(10 * ONE_NEAR),
             is_paused: false,
             current_epoch: U64(1),
@@ -569,18 +570,74 @@ async fn test_is_claimable_from_disabled_validator() -> Result<(), Box<dyn std::
 }
 
 #[tokio::test]
-async fn test_get_allocations_fails_with_user_has_no_allocations(
+async fn test_get_allocations_for_user_with_no_allocations(
 ) -> Result<(), Box<dyn std::error::Error>> {
     let (owner, _, contract) = setup_contract().await?;
     let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
 
-    contract
+    let allocations: Vec<AllocationInfo> = contract
         .view("get_allocations")
         .args_json(json!({
            "allocator": alice.id(),
         }))
-        .await
-        .expect_err("User has no allocations");
+        .await?
+        .json()?;
+    assert!(allocations.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_allocations_to() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, _, contract) = setup_contract().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let bob = setup_whitelisted_user(&owner, &contract, "bob").await?;
+    setup_allocation(&alice, &accounts(5), 2 * ONE_NEAR, contract.id()).await?;
+    setup_allocation(&bob, &accounts(5), ONE_NEAR, contract.id()).await?;
+
+    let allocations = contract
+        .view("get_allocations_to")
+        .args_json(json!({
+           "recipient": accounts(5),
+           "from_index": 1,
+           "limit": 1,
+        }))
+        .await?
+        .json::<serde_json::Value>()?;
+    assert_eq!(allocations.as_array().unwrap().len(), 1);
+    assert_eq!(allocations[0]["distributor"], bob.id().to_string());
+    assert_eq!(allocations[0]["near_amount"], ONE_NEAR.to_string());
+    assert_eq!(allocations[0]["pending_rewards"], "0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_pending_rewards() -> Result<(), Box<dyn std::error::Error>> {
+    let (owner, sandbox, contract, _) = setup_contract_with_pool().await?;
+    let alice = setup_whitelisted_user(&owner, &contract, "alice").await?;
+    let _ = stake(&contract, alice.clone(), 10).await?;
+    setup_allocation(&alice, &accounts(5), 2 * ONE_NEAR, contract.id()).await?;
+    setup_allocation(&alice, &accounts(4), ONE_NEAR, contract.id()).await?;
+    move_epoch_forward_and_update_total_staked(&sandbox, &contract, owner.clone()).await?;
+
+    let pending_rewards = contract
+        .view("get_pending_rewards")
+        .args_json(json!({ "distributor": alice.id() }))
+        .await?
+        .json::<serde_json::Value>()?;
+    let pending_rewards = pending_rewards.as_array().unwrap();
+    assert_eq!(pending_rewards.len(), 2);
+    assert_eq!(pending_rewards[0]["recipient"], accounts(4).to_string());
+    assert_eq!(pending_rewards[1]["recipient"], accounts(5).to_string());
+
+    // the gross shares of all recipients add up to the TruNEAR required by distribute_all
+    let (required_shares, _) = calculate_distribute_amounts(&contract, alice.id(), false).await?;
+    let total_shares: u128 = pending_rewards
+        .iter()
+        .map(|rewards| rewards["shares"].as_str().unwrap().parse::<u128>().unwrap())
+        .sum();
+    assert_eq!(total_shares, required_shares);
 
     Ok(())
 }
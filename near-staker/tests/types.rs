@@ -50,6 +50,14 @@ pub struct AllocationInfo {
     pub near_amount: U128,
     pub share_price_num: String,
     pub share_price_denom: String,
+    pub basis_points: Option<u16>,
+    pub escrowed: bool,
+    pub pending: bool,
+    pub expires_at_epoch: Option<U64>,
+    pub shares_distributed: U128,
+    pub near_distributed: U128,
+    pub fees_paid: U128,
+    pub last_distribution_epoch: Option<U64>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -59,6 +67,7 @@ pub struct StakerInfo {
     pub default_delegation_pool: AccountId,
     pub fee: u16,
     pub dist_fee: u16,
+    pub management_fee: u16,
     pub min_deposit: U128,
     pub is_paused: bool,
     pub current_epoch: U64,